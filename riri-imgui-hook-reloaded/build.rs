@@ -1,3 +1,4 @@
+use cbindgen;
 use csbindgen;
 use riri_mod_tools::{ config_codegen, mod_package, reloaded_codegen };
 use walkdir::{ DirEntry, WalkDir };
@@ -69,6 +70,15 @@ fn main() {
     // (metaphor.multiplayer.ReloadedFFI.Interfaces.Ryo)
     // ...
 
+    // Generate the C header for the plugin ABI (plugin.rs) and exports (backend.rs)
+    cbindgen::Builder::new()
+        .with_src(base.join("src/plugin.rs"))
+        .with_src(base.join("src/backend.rs"))
+        .with_language(cbindgen::Language::C)
+        .with_include_guard("RIRI_IMGUI_HOOK_H")
+        .with_documentation(true)
+        .generate().unwrap()
+        .write_to_file(base.join("include/riri_imgui_hook.h"));
     // Generate Config.cs
     config_codegen::generate(&base).unwrap(); 
     // Copy middata to C# project 
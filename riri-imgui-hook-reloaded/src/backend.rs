@@ -7,58 +7,109 @@ use riri_imgui_hook::{
         init::D3D12Init,
         state::D3D12Hook
     },
+    fonts::{ self, FontEntry },
+    headless_impl::{
+        platform::HeadlessPlatform,
+        renderer::{ DrawStats, HeadlessRenderer }
+    },
+    image,
+    overlay::{ self, OverlayConfig, OverlayMode, OverlayState },
     registry::{ RendererType, RegistryFlags },
-    win32_impl::state::Win32Impl
+    renderer::RendererBackend,
+    win32_impl::{
+        keys::{ self, SystemKeyState },
+        state::Win32Impl
+    }
+};
+use crate::{
+    diagnostics::{ self, CallbackDiagnostics, CallbackHealth },
+    pending::PendingRegistrations,
+    plugin::{ self, CallbackTypeSignature, Plugin, PluginHandle, PluginHook, PluginList, RiriImguiPluginDesc },
+    start::BackendStatus
 };
 use imgui::{
     internal::RawWrapper,
     Context as ImContext,
-    DrawData,
+    Style,
+    FontGlyphRanges,
+    TextureId,
     Ui as ImUI
 };
 use std::{
-    collections::HashSet,
     error::Error,
+    ffi::c_void,
     ptr::NonNull,
-    sync::Mutex,
-};
-use riri_mod_tools_proc::{ create_hook, riri_hook_fn };
-use riri_mod_tools_rt::logln;
-use windows::Win32::{
-    Foundation::{ LPARAM, WPARAM },
-    Graphics::{
-        Direct3D12::ID3D12CommandQueue,
-        Dxgi::{ IDXGISwapChain, IDXGISwapChain1 }
+    sync::{
+        atomic::{ AtomicBool, Ordering },
+        Mutex,
+        MutexGuard
     },
 };
+use riri_mod_tools_proc::{ create_hook, disable_hook, riri_hook_fn };
+use riri_mod_tools_rt::{ logln, mod_loader_data };
+use windows::{
+    core::Interface,
+    Win32::{
+        Foundation::{ HWND, LPARAM, LRESULT, WPARAM },
+        UI::WindowsAndMessaging::{ DefWindowProcW, WM_INPUT, WM_KEYDOWN, WM_SYSKEYDOWN },
+        Graphics::{
+            Direct3D11::ID3D11ShaderResourceView,
+            Direct3D12::ID3D12CommandQueue,
+            Dxgi::{ IDXGISwapChain, IDXGISwapChain1 }
+        },
+    }
+};
 
 #[derive(Debug)]
-pub enum Renderer {
-    Direct3D11(D3D11Hook),
-    Direct3D12(D3D12Hook),
+pub enum Platform {
+    Win32(Win32Impl),
+    Headless(HeadlessPlatform)
 }
-impl Renderer {
-    pub fn render(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
+impl Platform {
+    pub fn new_frame(&mut self, ctx: &mut ImContext) {
         match self {
-            Self::Direct3D11(r) => r.render(draw_data),
-            Self::Direct3D12(r) => r.render(draw_data)
+            Self::Win32(p) => p.new_frame(ctx),
+            Self::Headless(p) => p.new_frame(ctx)
         }
     }
-    pub fn invalidate_render_target_view(&mut self, ctx: &mut ImContext) -> windows::core::Result<()> {
+    pub fn shutdown(&mut self, ctx: &mut ImContext) {
         match self {
-            Self::Direct3D11(r) => r.invalidate_render_target_view(ctx),
-            Self::Direct3D12(r) => r.invalidate_device_objects(ctx),
+            Self::Win32(p) => p.shutdown(ctx),
+            Self::Headless(p) => p.shutdown(ctx)
         }
     }
-    pub fn create_render_target_view(&mut self, ctx: &mut ImContext) -> windows::core::Result<()> {
+    // Scripted input is always sent to the headless platform
+    pub fn set_input_enabled(&mut self, ctx: &mut ImContext, enabled: bool) {
         match self {
-            Self::Direct3D11(r) => unsafe { r.create_render_target_view(ctx) },
-            Self::Direct3D12(r) => unsafe { r.create_device_objects(ctx) }
+            Self::Win32(p) => p.set_input_enabled(ctx, enabled),
+            Self::Headless(_) => ()
+        }
+    }
+    pub fn set_dpi_scale_override(&mut self, scale: Option<f32>) {
+        match self {
+            Self::Win32(p) => p.set_dpi_scale_override(scale),
+            Self::Headless(p) => p.set_dpi_scale(scale.unwrap_or(1.))
+        }
+    }
+    pub fn take_dpi_scale_change(&mut self) -> Option<f32> {
+        match self {
+            Self::Win32(p) => p.take_dpi_scale_change(),
+            Self::Headless(p) => p.take_dpi_scale_change()
+        }
+    }
+    pub fn handle_window_message(&mut self, umsg: u32, wparam: WPARAM) {
+        if let Self::Win32(p) = self {
+            p.handle_window_message(umsg, wparam);
+        }
+    }
+    pub unsafe fn wnd_proc(&mut self, ctx: &mut ImContext, umsg: u32, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
+        match self {
+            Self::Win32(p) => p.wnd_proc(ctx, umsg, wparam, lparam),
+            Self::Headless(_) => None
         }
     }
 }
 
-type CallbackTypeSignature = unsafe extern "C" fn(*mut ImUI, *mut <ImContext as RawWrapper>::Raw);
 type CallbackInitAllocator = unsafe extern "C" fn(
     imgui::sys::ImGuiMemAllocFunc,
     imgui::sys::ImGuiMemFreeFunc,
@@ -69,10 +120,14 @@ type CallbackInitAllocator = unsafe extern "C" fn(
 #[derive(Debug)]
 pub struct Backend {
     imgui: ImContext,
-    platform: Win32Impl,
-    renderer: Renderer,
-    callbacks: HashSet<CallbackTypeSignature>,
-    allocator_callbacks: Vec<CallbackInitAllocator>
+    platform: Platform,
+    renderer: Box<dyn RendererBackend>,
+    plugins: PluginList,
+    allocator_callbacks: Vec<CallbackInitAllocator>,
+    overlay: OverlayState,
+    callback_health: CallbackDiagnostics<PluginHandle>,
+    // Style before DPI scaling, so scaling can be redone without rounding errors building up
+    base_style: Style
 }
 
 struct CommandQueueStore(Mutex<Option<NonNull<u8>>>);
@@ -94,12 +149,39 @@ unsafe impl Sync for CommandQueueStore {}
 
 static COMMAND_QUEUE: CommandQueueStore = CommandQueueStore::new();
 
+// Only hooks that have been created can be disabled. Present and ResizeBuffers are always
+// created together.
+static PRESENT_HOOKED: AtomicBool = AtomicBool::new(false);
+static EXECUTE_COMMAND_LISTS_HOOKED: AtomicBool = AtomicBool::new(false);
+static WND_PROC_HOOKED: AtomicBool = AtomicBool::new(false);
+
+// Stop the hook library from calling into us. The WindowProc hook goes first so no more messages
+// reach the backend while it's being released. A call that was already inside a hook keeps going
+// to the original function since hooks_enabled returns false by now.
+pub(crate) unsafe fn disable_hooks() {
+    if WND_PROC_HOOKED.swap(false, Ordering::AcqRel) {
+        disable_hook!(hook_window_proc);
+    }
+    if PRESENT_HOOKED.swap(false, Ordering::AcqRel) {
+        disable_hook!(hook_present);
+        disable_hook!(hook_resize_buffers);
+    }
+    if EXECUTE_COMMAND_LISTS_HOOKED.swap(false, Ordering::AcqRel) {
+        disable_hook!(hook_execute_command_lists);
+    }
+}
+
+// Plugins registered while there's no backend. Always lock BACKEND before this.
+static PENDING: Mutex<PendingRegistrations<Plugin, CallbackInitAllocator>> =
+    Mutex::new(PendingRegistrations::new());
+
 impl Backend {
     pub unsafe fn make_hooks_d3d11() {
         let dummy = match D3D11Init::new() {
             Ok(v) => v,
             Err(e) => {
                 logln!(Error, "Error initializing D3D11: {}. Closing Imgui Hook.", e);
+                crate::start::set_status(BackendStatus::Failed);
                 return;
             }
         };
@@ -114,6 +196,7 @@ impl Backend {
         let resize_buffers_ptr_thunk = resize_buffers_ptr_thunk.as_ptr() as usize;
         logln!(Verbose, "IDXGISwapChain::ResizeBuffers: 0x{:x} -> 0x{:x}", resize_buffers_ptr, resize_buffers_ptr_thunk);
         create_hook!(resize_buffers_ptr_thunk, hook_resize_buffers);
+        PRESENT_HOOKED.store(true, Ordering::Release);
     }
 
     pub unsafe fn make_hooks_d3d12() {
@@ -121,6 +204,7 @@ impl Backend {
             Ok(v) => v,
             Err(e) => {
                 logln!(Error, "Error initializing D3D12: {}. Closing Imgui Hook.", e);
+                crate::start::set_status(BackendStatus::Failed);
                 return;
             }
         };
@@ -130,8 +214,126 @@ impl Backend {
         logln!(Verbose, "IDXGISwapChain::Present: 0x{:x}", present_ptr);
         create_hook!(present_ptr, hook_present);
         logln!(Verbose, "IDXGISwapChain::ResizeBuffers: 0x{:x}", resize_buffers_ptr);
+        create_hook!(resize_buffers_ptr, hook_resize_buffers);
+        PRESENT_HOOKED.store(true, Ordering::Release);
         logln!(Verbose, "ID3D12CommandQueue::ExecuteCommandLists: 0x{:x}", exec_cmd_list_ptr);
         create_hook!(exec_cmd_list_ptr, hook_execute_command_lists);
+        EXECUTE_COMMAND_LISTS_HOOKED.store(true, Ordering::Release);
+    }
+
+    fn new(imgui: ImContext, platform: Platform, renderer: Box<dyn RendererBackend>) -> Self {
+        let mod_dir: String = mod_loader_data::get_directory_for_mod().into();
+        let overlay = OverlayState::new(overlay::load_overlay_config(
+            std::path::Path::new(&mod_dir).join(overlay::OVERLAY_FILE_NAME)));
+        let mut platform = platform;
+        platform.set_dpi_scale_override(crate::start::TARGET.get().unwrap().get_dpi_scale());
+        Self::from_parts(imgui, platform, renderer, overlay)
+    }
+
+    fn from_parts(imgui: ImContext, platform: Platform, renderer: Box<dyn RendererBackend>, overlay: OverlayState) -> Self {
+        let base_style = *imgui.style();
+        let mut backend = Self { imgui, platform, renderer, plugins: PluginList::new(), allocator_callbacks: vec![], overlay,
+            callback_health: CallbackDiagnostics::new(diagnostics::MAX_CONSECUTIVE_FAILURES), base_style };
+        backend.apply_overlay_mode();
+        backend
+    }
+
+    // Hand over plugins that were registered before the backend started. Returns their on_init
+    // hooks, which have to be run once BACKEND is unlocked.
+    fn apply_pending(&mut self) -> Vec<PluginHook> {
+        let (plugins, allocators) = PENDING.lock().unwrap().take();
+        if plugins.is_empty() && allocators.is_empty() {
+            return vec![];
+        }
+        logln!(Verbose, "Adding {} plugins and {} allocators registered before startup", plugins.len(), allocators.len());
+        self.allocator_callbacks.extend(allocators);
+        plugins.into_iter().filter_map(|p| self.add_plugin(p)).collect()
+    }
+
+    // Returns the plugin's on_init hook if it has one
+    fn add_plugin(&mut self, plugin: Plugin) -> Option<PluginHook> {
+        let init = plugin.get_init_hook();
+        self.plugins.insert(plugin);
+        init
+    }
+
+    fn remove_plugin<F: Fn(&Plugin) -> bool>(&mut self, f: F) -> Option<Plugin> {
+        let plugin = self.plugins.remove(f)?;
+        self.callback_health.remove(&plugin.get_handle());
+        Some(plugin)
+    }
+
+    fn get_device_lost_hooks(&self) -> Vec<PluginHook> {
+        self.plugins.iter().filter_map(|p| p.get_device_lost_hook()).collect()
+    }
+
+    // Release everything in the reverse order it was created in: plugins first so nothing
+    // external runs against a half torn down context, then the renderer and platform, then
+    // the ImGui context itself. BACKEND is already unlocked, so on_shutdown can still use the
+    // font exports.
+    pub fn shutdown(mut self) {
+        for hook in self.plugins.iter().filter_map(|p| p.get_shutdown_hook()) {
+            unsafe { hook.call() }
+        }
+        self.plugins.clear();
+        self.allocator_callbacks.clear();
+        self.save_ini();
+        if let Err(e) = self.renderer.shutdown(&mut self.imgui) {
+            logln!(Error, "Error while shutting down renderer: {}", e);
+        }
+        self.platform.shutdown(&mut self.imgui);
+        drop(self.renderer);
+        drop(self.platform);
+        drop(self.imgui);
+        logln!(Information, "Backend shut down");
+    }
+
+    // ImGui only writes imgui.ini every few seconds, so save it now to keep the latest layout
+    fn save_ini(&mut self) {
+        let Some(path) = self.imgui.ini_filename() else { return };
+        let mut settings = String::new();
+        self.imgui.save_ini_settings(&mut settings);
+        if let Err(e) = std::fs::write(&path, settings) {
+            logln!(Error, "Could not save {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn get_overlay_mode(&self) -> OverlayMode { self.overlay.get_mode() }
+
+    pub fn set_overlay_mode(&mut self, mode: OverlayMode) {
+        if self.overlay.set_mode(mode) {
+            self.apply_overlay_mode();
+        }
+    }
+
+    fn apply_overlay_mode(&mut self) {
+        let mode = self.overlay.get_mode();
+        self.platform.set_input_enabled(&mut self.imgui, mode.is_interactive());
+        logln!(Verbose, "Overlay mode: {:?}", mode);
+    }
+
+    // Check if a window message is one of the overlay hotkeys, returns true if it was consumed
+    fn handle_overlay_hotkey(&mut self, umsg: u32, wparam: WPARAM, lparam: LPARAM) -> bool {
+        if umsg != WM_KEYDOWN && umsg != WM_SYSKEYDOWN {
+            return false;
+        }
+        let Some(key) = keys::key_event_to_imgui_key(wparam.0, lparam.0) else { return false };
+        let prev_mode = self.overlay.get_mode();
+        if !self.overlay.on_key_down(key, keys::get_key_modifiers(&SystemKeyState), keys::is_key_repeat(lparam.0)) {
+            return false;
+        }
+        if self.overlay.get_mode() != prev_mode {
+            self.apply_overlay_mode();
+        }
+        true
+    }
+
+    // Finish setting up a renderer once it's been constructed for the game's graphics API
+    fn init_renderer<R: RendererBackend + 'static>(imgui: &mut ImContext, renderer: R, flags: RegistryFlags)
+        -> windows::core::Result<Box<dyn RendererBackend>> {
+        let mut renderer = Box::new(renderer);
+        renderer.init(imgui, flags)?;
+        Ok(renderer)
     }
 
     pub fn init_d3d11(swapchain: IDXGISwapChain, flags: RegistryFlags) -> Result<Self, Box<dyn Error>> {
@@ -146,13 +348,14 @@ impl Backend {
         let wnd_proc_ptr = platform.get_wnd_proc();
         logln!(Verbose, "Hook WindowProc: 0x{:x}", wnd_proc_ptr);
         create_hook!(wnd_proc_ptr, hook_window_proc);
+        WND_PROC_HOOKED.store(true, Ordering::Release);
         // ImGui_ImplDX11_Init
-        let renderer = Renderer::Direct3D11(D3D11Hook::new(&mut imgui, swapchain, flags)?);
+        let renderer = Self::init_renderer(&mut imgui, unsafe { D3D11Hook::new_blank(&mut imgui, swapchain)? }, flags)?;
         logln!(Verbose, "Platform: {}, Renderer: {}", imgui.platform_name().unwrap(), imgui.renderer_name().unwrap());
-        Ok(Self { imgui, platform, renderer, callbacks: HashSet::new(), allocator_callbacks: vec![] })
+        Ok(Self::new(imgui, Platform::Win32(platform), renderer))
     }
 
-    pub fn init_d3d12(swapchain: IDXGISwapChain1, command_queue: ID3D12CommandQueue) -> Result<Self, Box<dyn Error>> {
+    pub fn init_d3d12(swapchain: IDXGISwapChain1, command_queue: ID3D12CommandQueue, flags: RegistryFlags) -> Result<Self, Box<dyn Error>> {
         let desc = unsafe { (&swapchain).GetDesc()? };
         let swapchain_ptr = unsafe { *std::mem::transmute::<_, *const usize>(&swapchain) };
         logln!(Verbose, "Got HWND: {}, swapchain: 0x{:x}", desc.OutputWindow.0 as usize, swapchain_ptr);
@@ -164,16 +367,101 @@ impl Backend {
         let wnd_proc_ptr = platform.get_wnd_proc();
         logln!(Verbose, "Hook WindowProc: 0x{:x}", wnd_proc_ptr);
         create_hook!(wnd_proc_ptr, hook_window_proc);
+        WND_PROC_HOOKED.store(true, Ordering::Release);
         // ImGui_ImplDX12_Init
-        let renderer = Renderer::Direct3D12(unsafe { D3D12Hook::new(&mut imgui, swapchain, command_queue)? });
+        let renderer = Self::init_renderer(&mut imgui, unsafe { D3D12Hook::new(&mut imgui, swapchain, command_queue)? }, flags)?;
         logln!(Verbose, "Platform: {}, Renderer: {}", imgui.platform_name().unwrap(), imgui.renderer_name().unwrap());
-        Ok(Self { imgui, platform, renderer, callbacks: HashSet::new(), allocator_callbacks: vec![] })
+        Ok(Self::new(imgui, Platform::Win32(platform), renderer))
+    }
+
+    /// Create a backend that doesn't need a window or GPU, for running plugins in tests. It
+    /// isn't stored in BACKEND, so plugins are added with add_plugin_now and frames are run
+    /// by calling tick. Input is scripted through get_headless_platform.
+    pub fn init_headless(display_size: [f32; 2]) -> Self {
+        let mut imgui = ImContext::create();
+        imgui.set_ini_filename(None);
+        let platform = HeadlessPlatform::new(&mut imgui, display_size);
+        let renderer = HeadlessRenderer::new(&mut imgui);
+        Self::from_parts(imgui, Platform::Headless(platform), Box::new(renderer),
+            OverlayState::new(OverlayConfig::default()))
+    }
+
+    /// Add a plugin to a backend that isn't in BACKEND (e.g a headless one), calling on_init
+    /// straight away
+    pub unsafe fn add_plugin_now(&mut self, plugin: Plugin) -> PluginHandle {
+        let handle = plugin.get_handle();
+        if let Some(hook) = self.add_plugin(plugin) {
+            hook.call();
+        }
+        if let Some(p) = self.plugins.get_mut(handle) {
+            p.set_initialized();
+        }
+        handle
+    }
+
+    pub fn get_headless_platform(&mut self) -> Option<&mut HeadlessPlatform> {
+        match &mut self.platform {
+            Platform::Headless(p) => Some(p),
+            _ => None
+        }
+    }
+
+    /// What the headless renderer was given on the last frame
+    pub fn get_last_draw_stats(&self) -> Option<&DrawStats> {
+        self.renderer.as_any().downcast_ref::<HeadlessRenderer>()?.get_last_stats()
+    }
+
+    // Rescale the style and fonts when the window moves to a monitor with a different DPI.
+    // Style changes made by GUI callbacks after startup are lost when this happens.
+    fn update_dpi_scale(&mut self) {
+        let Some(scale) = self.platform.take_dpi_scale_change() else { return };
+        let style = self.imgui.style_mut();
+        *style = self.base_style;
+        style.scale_all_sizes(scale);
+        // The atlas is rebuilt in update_fonts if the scale is different
+        fonts::get_font_stack().set_scale(scale);
+        logln!(Verbose, "UI scale: {}", scale);
+    }
+
+    // Rebuild the font atlas if it was changed since the last frame. This has to happen before
+    // NewFrame since the atlas is locked until the frame is rendered.
+    fn update_fonts(&mut self) {
+        let mut font_stack = fonts::get_font_stack();
+        if !font_stack.is_dirty() {
+            return;
+        }
+        font_stack.build(self.imgui.fonts());
+        drop(font_stack);
+        match self.renderer.rebuild_font_texture(&mut self.imgui) {
+            Ok(_) => logln!(Verbose, "Rebuilt font atlas"),
+            Err(e) => logln!(Error, "Error while rebuilding font atlas: {}", e)
+        }
+    }
+
+    // Run a GUI callback, then clean up anything it left open on ImGui's window and ID stacks
+    // so the next callback starts from the same state. Callbacks live in other DLLs and can't
+    // unwind into us, a plugin that panics part way through a frame catches it itself (see
+    // riri_imgui_hook::callback) and usually leaves something open that's reported here.
+    unsafe fn run_callback(plugin: &Plugin, ui: *mut ImUI, ctx: *mut <ImContext as RawWrapper>::Raw) -> Result<(), String> {
+        plugin.draw(ui as *mut c_void, ctx as *mut c_void);
+        let recovered = diagnostics::recover_imgui_stacks();
+        match recovered.is_empty() {
+            true => Ok(()),
+            false => Err(format!("Unbalanced ImGui calls: {}", recovered.join(", ")))
+        }
     }
 
     pub fn tick(&mut self) {
+        self.update_dpi_scale();
+        self.update_fonts();
+        if !self.overlay.get_mode().is_visible() {
+            return;
+        }
         self.platform.new_frame(&mut self.imgui);
-        // self.renderer.new_frame (just calls CreateDeviceObjects if font sampler isn't initialized)
-        // let _ui = self.imgui.new_frame();
+        if let Err(e) = self.renderer.new_frame(&mut self.imgui) {
+            logln!(Error, "Error while starting frame: {}", e);
+            return;
+        }
         let ui = self.imgui.new_frame();
         let ui_ptr = &raw mut *ui;
         let ctx_ptr = unsafe { &raw mut *self.imgui.raw_mut() };
@@ -187,8 +475,27 @@ impl Backend {
                 unsafe { cb(alloc, free, user) }
             }
         }
-        for cb in self.callbacks.iter() {
-            unsafe { cb(ui_ptr, ctx_ptr) }
+        for plugin in self.plugins.iter().filter(|p| p.is_initialized() && p.has_draw()) {
+            let handle = plugin.get_handle();
+            let health = self.callback_health.get_or_insert_with(handle,
+                || CallbackHealth::new(plugin.get_name().to_owned(), plugin.get_address()));
+            if health.is_disabled() {
+                continue;
+            }
+            match unsafe { Self::run_callback(plugin, ui_ptr, ctx_ptr) } {
+                Ok(_) => self.callback_health.record_success(&handle),
+                Err(e) => {
+                    logln!(Error, "GUI callback for {} failed: {}", plugin.get_name(), e);
+                    if self.callback_health.record_failure(&handle, e) {
+                        logln!(Warning, "Disabled GUI callback for {} after {} failures in a row",
+                            plugin.get_name(), diagnostics::MAX_CONSECUTIVE_FAILURES);
+                        diagnostics::set_visible(true);
+                    }
+                }
+            }
+        }
+        if diagnostics::is_visible() {
+            diagnostics::draw_diagnostics(unsafe { &*ui_ptr }, &mut self.callback_health);
         }
         let draw_data = self.imgui.render();
         if let Err(e) = self.renderer.render(draw_data) {
@@ -202,25 +509,44 @@ unsafe impl Sync for Backend {}
 #[riri_hook_fn(user_defined())]
 pub unsafe extern "C" fn hook_present(p_swapchain: *const u8, sync_interval: u32, flags: u32) {
     let mut backend_lock = crate::start::BACKEND.lock().unwrap();
+    let mut init = vec![];
     match (*backend_lock).as_mut() {
         Some(v) => { v.tick(); },
+        // Don't start the backend up again after it's been shut down
+        None if !crate::start::hooks_enabled() => (),
         None => { 
             let target = *crate::start::TARGET.get().unwrap();
-            *backend_lock = match target.get_renderer() {
+            let backend = match target.get_renderer() {
                 RendererType::Direct3D11 => {
                     let swapchain = std::mem::transmute::<_, IDXGISwapChain>(p_swapchain).clone();
-                    Some(Backend::init_d3d11(swapchain, target.get_flags()).unwrap())
+                    Some(Backend::init_d3d11(swapchain, target.get_flags()))
                 },
                 RendererType::Direct3D12 => {
                     if let Some(cmd) = COMMAND_QUEUE.try_get() {
                         let swapchain = std::mem::transmute::<_, IDXGISwapChain1>(p_swapchain).clone();
                         let cmd_queue = std::mem::transmute::<_, ID3D12CommandQueue>(cmd).clone();
-                        Some(Backend::init_d3d12(swapchain, cmd_queue).unwrap())
+                        Some(Backend::init_d3d12(swapchain, cmd_queue, target.get_flags()))
                     } else { None }
                 },
-            } 
+            };
+            match backend {
+                Some(Ok(mut v)) => {
+                    init = v.apply_pending();
+                    *backend_lock = Some(v);
+                    crate::start::set_status(BackendStatus::Running);
+                },
+                Some(Err(e)) => {
+                    logln!(Error, "Error initializing backend: {}. Closing Imgui Hook.", e);
+                    crate::start::set_status(BackendStatus::Failed);
+                    disable_hooks();
+                },
+                // D3D12 has to wait for ExecuteCommandLists to give us the command queue
+                None => ()
+            }
         }
     }
+    drop(backend_lock);
+    init_plugins(init);
     original_function!(p_swapchain, sync_interval, flags)
 }
 
@@ -229,9 +555,32 @@ pub unsafe extern "C" fn hook_window_proc(hook_hwnd: usize, umsg: u32, hook_wpar
     let wparam = WPARAM(hook_wparam);
     let lparam = LPARAM(hook_lparam);
     let mut backend_lock = crate::start::BACKEND.lock().unwrap();
-    let backend = (*backend_lock).as_mut().unwrap();
-    match backend.platform.wnd_proc(&mut backend.imgui, umsg, wparam, lparam) {
+    let backend = match (*backend_lock).as_mut() {
+        Some(v) if crate::start::hooks_enabled() => v,
+        _ => {
+            drop(backend_lock);
+            return original_function!(hook_hwnd, umsg, hook_wparam, hook_lparam);
+        }
+    };
+    if backend.handle_overlay_hotkey(umsg, wparam, lparam) {
+        return 0;
+    }
+    let mode = backend.overlay.get_mode();
+    let result = match mode.is_interactive() {
+        true => backend.platform.wnd_proc(&mut backend.imgui, umsg, wparam, lparam),
+        false => {
+            backend.platform.handle_window_message(umsg, wparam);
+            None
+        }
+    };
+    match result {
         Some(r) => r.0,
+        // Interactive mode keeps all mouse and keyboard input from the game, even when ImGui
+        // doesn't want it. Raw input still has to go through DefWindowProc to be cleaned up.
+        None if mode.captures_message(umsg) => match umsg {
+            WM_INPUT => DefWindowProcW(HWND(hook_hwnd as _), umsg, wparam, lparam).0,
+            _ => 0
+        },
         None => {
             drop(backend_lock);
             original_function!(hook_hwnd, umsg, hook_wparam, hook_lparam)
@@ -255,46 +604,353 @@ pub unsafe extern "C" fn hook_execute_command_lists(
 #[riri_hook_fn(user_defined())]
 pub unsafe extern "C" fn hook_resize_buffers(p_swapchain: *const u8, buffer_count: u32, 
     width: u32, height: u32, new_format: u32, swapchain_flags: u32) -> i32 {
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    let device_lost = (*backend_lock).as_ref().map_or(vec![], |b| b.get_device_lost_hooks());
+    drop(backend_lock);
+    for hook in &device_lost {
+        hook.call();
+    }
     let mut backend_lock = crate::start::BACKEND.lock().unwrap();
     if let Some(b) = (*backend_lock).as_mut() {
-        let _ = b.renderer.invalidate_render_target_view(&mut b.imgui);
+        let _ = b.renderer.before_resize(&mut b.imgui);
     }
     drop(backend_lock);
     let hresult = original_function!(p_swapchain, buffer_count, width, height, new_format, swapchain_flags);
     let mut backend_lock = crate::start::BACKEND.lock().unwrap();
     if let Some(b) = (*backend_lock).as_mut() {
-        let _ = b.renderer.create_render_target_view(&mut b.imgui);
+        let _ = b.renderer.after_resize(&mut b.imgui);
     }
     drop(backend_lock);
     hresult
 }
 
+/// Shut the backend down so the mod can be disabled without restarting the game. The Present,
+/// ResizeBuffers, ExecuteCommandLists and WindowProc hooks are disabled, then plugins get
+/// on_shutdown and are removed, imgui.ini is saved and renderer objects are released. This must
+/// not be called from a GUI callback.
+#[no_mangle]
+pub unsafe extern "C" fn shutdown() {
+    crate::start::set_status(BackendStatus::ShutDown);
+    disable_hooks();
+    let backend = crate::start::BACKEND.lock().unwrap().take();
+    if let Some(backend) = backend {
+        backend.shutdown();
+    }
+}
+
+/// Register a plugin. Returns a handle for unregister_plugin, or 0 if desc is invalid. If the
+/// backend is already running, on_init is called before this returns, otherwise it's called
+/// once the game presents its first frame. This must not be called from on_draw.
+#[no_mangle]
+pub unsafe extern "C" fn register_plugin(desc: *const RiriImguiPluginDesc) -> u64 {
+    let Some(desc) = desc.as_ref() else { return 0 };
+    let plugin = match Plugin::from_desc(desc) {
+        Ok(v) => v,
+        Err(e) => {
+            logln!(Error, "Could not register plugin: {}", e);
+            return 0;
+        }
+    };
+    let handle = plugin.get_handle();
+    logln!(Verbose, "Registered plugin {} as {}", plugin.get_name(), handle.get_raw());
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    let init = add_or_queue(backend_lock, plugin, |b, v| b.add_plugin(v), |p, v| { p.add_callback(v); None });
+    init_plugins(init.into_iter().collect());
+    handle.get_raw()
+}
+
+/// Remove a plugin added with register_plugin. on_shutdown is called if on_init was. Returns
+/// false if the handle isn't registered. This must not be called from on_draw.
+#[no_mangle]
+pub unsafe extern "C" fn unregister_plugin(handle: u64) -> bool {
+    let handle = PluginHandle::from_raw(handle);
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    let plugin = add_or_queue(backend_lock, handle,
+        |b, h| b.remove_plugin(|p| p.get_handle() == h),
+        |p, h| p.remove_callback(|p| p.get_handle() == h));
+    match plugin {
+        Some(p) => {
+            if let Some(hook) = p.get_shutdown_hook() {
+                hook.call();
+            }
+            true
+        },
+        None => false
+    }
+}
+
+/// Change the order a plugin is drawn in. Lower priorities are drawn first. Returns false if
+/// the handle isn't registered. This must not be called from on_draw.
+#[no_mangle]
+pub unsafe extern "C" fn set_plugin_priority(handle: u64, priority: i32) -> bool {
+    let handle = PluginHandle::from_raw(handle);
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    add_or_queue(backend_lock, priority,
+        |b, v| b.plugins.set_priority(|p| p.get_handle() == handle, v),
+        |p, v| p.get_callback_mut(|p| p.get_handle() == handle).map(|p| p.set_priority(v)).is_some())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn get_abi_version() -> u32 {
+    plugin::RIRI_IMGUI_ABI_VERSION
+}
+
+/// Get the RIRI_IMGUI_CAP_* flags for the game's renderer. This is 0 until the renderer is
+/// known, which is always the case by the time on_init is called.
+#[no_mangle]
+pub unsafe extern "C" fn get_capabilities() -> u32 {
+    match crate::start::TARGET.get() {
+        Some(t) => plugin::get_capabilities(t.get_renderer()),
+        None => 0
+    }
+}
+
+// Run on_init for plugins that were just added, then let them start drawing. BACKEND has to be
+// unlocked so on_init can use the texture and font exports.
+unsafe fn init_plugins(hooks: Vec<PluginHook>) {
+    if hooks.is_empty() {
+        return;
+    }
+    for hook in &hooks {
+        hook.call();
+    }
+    if let Some(b) = crate::start::BACKEND.lock().unwrap().as_mut() {
+        for hook in &hooks {
+            if let Some(p) = b.plugins.get_mut(hook.get_handle()) {
+                p.set_initialized();
+            }
+        }
+    }
+}
+
+/// Add a draw callback without a RiriImguiPluginDesc. It's registered as a plugin named after
+/// the DLL it's in. The callback must not unwind, a panic escaping it aborts the game (Rust
+/// plugins can define it with riri_imgui_hook::gui_callback!).
 #[no_mangle]
 pub unsafe extern "C" fn add_gui_callback(cb: unsafe extern "C" fn(*mut u8, *mut u8), version: *const i8) {
-    let external_ver = std::ffi::CStr::from_ptr(version).to_str().unwrap();
-    let local_ver = imgui::dear_imgui_version();
-    if external_ver != local_ver {
-        logln!(Error, "Imgui version is {}, but external crate uses version {}", local_ver, external_ver);
+    if let Err(e) = plugin::check_imgui_version(version) {
+        logln!(Error, "{}", e);
         return;
     }
-    let mut backend_lock = crate::start::BACKEND.lock().unwrap();
-    let backend = (*backend_lock).as_mut().unwrap();
-    let cb = std::mem::transmute::<_, CallbackTypeSignature>(cb);
-    backend.callbacks.insert(cb);
+    let plugin = Plugin::from_callback(cb, diagnostics::get_module_name(cb as usize));
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    // Adding the same callback twice only registers it once
+    add_or_queue(backend_lock, plugin,
+        |b, v| if !b.plugins.iter().any(|p| p.is_callback(cb)) { b.add_plugin(v); },
+        |p, v| if !p.has_callback(|p| p.is_callback(cb)) { p.add_callback(v); });
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn add_allocator(cb: unsafe extern "C" fn (*mut u8, *mut u8, *mut u8)) {
-    let mut backend_lock = crate::start::BACKEND.lock().unwrap();
-    let backend = (*backend_lock).as_mut().unwrap();
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
     let cb = std::mem::transmute::<_, CallbackInitAllocator>(cb);
-    backend.allocator_callbacks.push(cb);
+    add_or_queue(backend_lock, cb, |b, v| b.allocator_callbacks.push(v), |p, v| p.add_allocator(v));
+}
+
+/// Change the order a callback from add_gui_callback is drawn in. These start at priority 0.
+#[no_mangle]
+pub unsafe extern "C" fn set_gui_callback_priority(cb: unsafe extern "C" fn(*mut u8, *mut u8), priority: i32) -> bool {
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    add_or_queue(backend_lock, priority,
+        |b, v| b.plugins.set_priority(|p| p.is_callback(cb), v),
+        |p, v| p.get_callback_mut(|p| p.is_callback(cb)).map(|p| p.set_priority(v)).is_some())
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn remove_gui_callback(cb: unsafe extern "C" fn(*mut u8, *mut u8)) {
+    let backend_lock = crate::start::BACKEND.lock().unwrap();
+    add_or_queue(backend_lock, cb,
+        |b, v| { b.remove_plugin(|p| p.is_callback(v)); },
+        |p, v| { p.remove_callback(|p| p.is_callback(v)); });
+}
+
+/// Show or hide the window listing GUI callbacks that have failed. It's also shown when a
+/// callback is disabled. This can be called from a GUI callback.
+#[no_mangle]
+pub unsafe extern "C" fn set_diagnostics_visible(visible: bool) {
+    diagnostics::set_visible(visible);
+}
+
+// Apply a registration change to the backend if it's running, otherwise to the pending queue
+fn add_or_queue<T, R, B, P>(mut backend_lock: MutexGuard<Option<Backend>>, value: T, backend: B, pending: P) -> R
+where B: FnOnce(&mut Backend, T) -> R,
+      P: FnOnce(&mut PendingRegistrations<Plugin, CallbackInitAllocator>, T) -> R
+{
+    match (*backend_lock).as_mut() {
+        Some(b) => backend(b, value),
+        None => pending(&mut PENDING.lock().unwrap(), value)
+    }
+}
+
+/// Get the backend's state as a BackendStatus: 0 = waiting for the first frame, 1 = running,
+/// 2 = failed to start, 3 = shut down
+#[no_mangle]
+pub unsafe extern "C" fn get_backend_status() -> u32 {
+    crate::start::get_status() as u32
+}
+
+/// Register an existing ID3D11ShaderResourceView for use with imgui::Image. The renderer
+/// takes its own reference to the view. On success, the texture ID is written to out_id.
+#[no_mangle]
+pub unsafe extern "C" fn register_texture_d3d11(srv: *mut u8, out_id: *mut usize) -> bool {
+    if out_id.is_null() {
+        return false;
+    }
+    let raw = srv as *mut std::ffi::c_void;
+    let srv = match ID3D11ShaderResourceView::from_raw_borrowed(&raw) {
+        Some(v) => v.clone(),
+        None => return false
+    };
+    let mut backend_lock = crate::start::BACKEND.lock().unwrap();
+    let Some(backend) = (*backend_lock).as_mut() else { return false };
+    match backend.renderer.as_any_mut().downcast_mut::<D3D11Hook>() {
+        Some(r) => { *out_id = r.register_texture(srv).id(); true },
+        None => {
+            logln!(Error, "Could not register texture: Direct3D11 textures can only be used with the Direct3D11 renderer");
+            false
+        }
+    }
+}
+
+/// Create a texture from tightly packed RGBA8 pixels (width * height * 4 bytes). On success,
+/// the texture ID is written to out_id.
+#[no_mangle]
+pub unsafe extern "C" fn create_texture_rgba(width: u32, height: u32, data: *const u8, out_id: *mut usize) -> bool {
+    if data.is_null() || out_id.is_null() {
+        return false;
+    }
+    let Some(size) = (width as usize).checked_mul(height as usize).and_then(|v| v.checked_mul(4)) else {
+        return false;
+    };
+    let data = std::slice::from_raw_parts(data, size);
     let mut backend_lock = crate::start::BACKEND.lock().unwrap();
-    let backend = (*backend_lock).as_mut().unwrap();
-    let cb = std::mem::transmute::<_, CallbackTypeSignature>(cb);
-    backend.callbacks.remove(&cb);
+    let Some(backend) = (*backend_lock).as_mut() else { return false };
+    match backend.renderer.create_texture_rgba(width, height, data) {
+        Ok(id) => { *out_id = id.id(); true },
+        Err(e) => {
+            logln!(Error, "Could not create texture: {}", e);
+            false
+        }
+    }
+}
+
+/// Load a PNG, TGA or DDS file and upload it to the active renderer. On success, the texture
+/// ID is written to out_id.
+#[no_mangle]
+pub unsafe extern "C" fn load_texture_from_file(path: *const i8, out_id: *mut usize) -> bool {
+    if path.is_null() || out_id.is_null() {
+        return false;
+    }
+    let path = std::ffi::CStr::from_ptr(path).to_string_lossy();
+    let image = match image::load_image(path.as_ref()) {
+        Ok(v) => v,
+        Err(e) => {
+            logln!(Error, "Could not load texture {}: {}", path, e);
+            return false;
+        }
+    };
+    let mut backend_lock = crate::start::BACKEND.lock().unwrap();
+    let Some(backend) = (*backend_lock).as_mut() else { return false };
+    match backend.renderer.create_texture(&image) {
+        Ok(id) => { *out_id = id.id(); true },
+        Err(e) => {
+            logln!(Error, "Could not create texture for {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Decode a PNG, TGA or DDS file that's already in memory and upload it to the active
+/// renderer. On success, the texture ID is written to out_id.
+#[no_mangle]
+pub unsafe extern "C" fn load_texture_from_memory(data: *const u8, len: usize, out_id: *mut usize) -> bool {
+    if data.is_null() || out_id.is_null() {
+        return false;
+    }
+    let image = match image::decode_image(std::slice::from_raw_parts(data, len)) {
+        Ok(v) => v,
+        Err(e) => {
+            logln!(Error, "Could not decode texture: {}", e);
+            return false;
+        }
+    };
+    let mut backend_lock = crate::start::BACKEND.lock().unwrap();
+    let Some(backend) = (*backend_lock).as_mut() else { return false };
+    match backend.renderer.create_texture(&image) {
+        Ok(id) => { *out_id = id.id(); true },
+        Err(e) => {
+            logln!(Error, "Could not create texture: {}", e);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn unregister_texture(id: usize) -> bool {
+    let mut backend_lock = crate::start::BACKEND.lock().unwrap();
+    let Some(backend) = (*backend_lock).as_mut() else { return false };
+    backend.renderer.destroy_texture(TextureId::from(id))
+}
+
+// Font exports only touch the font stack, so they're safe to call from inside a GUI callback.
+// Changes are applied at the start of the next frame.
+
+/// Add a TTF/OTF font from a file. If merge is set, the font's glyphs are merged into the
+/// previously added font.
+#[no_mangle]
+pub unsafe extern "C" fn add_font_from_file(path: *const i8, size_pixels: f32, merge: bool) -> bool {
+    if path.is_null() {
+        return false;
+    }
+    let path = std::ffi::CStr::from_ptr(path).to_string_lossy();
+    match std::fs::read(path.as_ref()) {
+        Ok(f) => {
+            fonts::get_font_stack().add_font(FontEntry::new(f.into(), size_pixels, FontGlyphRanges::default(), merge));
+            true
+        },
+        Err(e) => {
+            logln!(Error, "Could not read font {}: {}", path, e);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn add_font_from_memory(data: *const u8, len: usize, size_pixels: f32, merge: bool) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let data = std::slice::from_raw_parts(data, len);
+    fonts::get_font_stack().add_font(FontEntry::new(data.into(), size_pixels, FontGlyphRanges::default(), merge));
+    true
+}
+
+/// Add glyph ranges to the nth font. Ranges are pairs of inclusive codepoints ending in a
+/// zero, and must stay valid for the rest of the program (ImGui keeps a pointer to them).
+/// Returns false if ranges is null.
+#[no_mangle]
+pub unsafe extern "C" fn add_font_glyph_ranges(font: usize, ranges: *const u32) -> bool {
+    if ranges.is_null() {
+        return false;
+    }
+    let mut len = 0;
+    while *ranges.add(len) != 0 {
+        len += 1;
+    }
+    let ranges = std::slice::from_raw_parts(ranges, len + 1);
+    if let Err(e) = fonts::validate_glyph_ranges(ranges) {
+        logln!(Error, "Could not add glyph ranges: {}", e);
+        return false;
+    }
+    fonts::get_font_stack().add_glyph_ranges(font, FontGlyphRanges::from_slice(ranges))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn set_font_size(size_pixels: f32) {
+    fonts::get_font_stack().set_size_pixels(size_pixels);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rebuild_fonts() {
+    fonts::get_font_stack().mark_dirty();
 }
\ No newline at end of file
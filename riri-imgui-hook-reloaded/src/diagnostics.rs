@@ -0,0 +1,290 @@
+// Failure tracking for GUI callbacks. A callback that leaves ImGui's stacks unbalanced is
+// logged against the module it came from, and turned off if it keeps failing.
+use imgui::{ Condition, Ui as ImUI };
+use std::{
+    ffi::{ c_char, c_void, CStr },
+    sync::atomic::{ AtomicBool, Ordering }
+};
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::HMODULE,
+        System::LibraryLoader::{
+            GetModuleFileNameA,
+            GetModuleHandleExA,
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT
+        }
+    }
+};
+
+/// Number of failures in a row before a callback is disabled
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct CallbackHealth {
+    // File name of the DLL the callback is in
+    owner: String,
+    address: usize,
+    calls: u64,
+    failures: u32,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    disabled: bool
+}
+impl CallbackHealth {
+    pub fn new(owner: String, address: usize) -> Self {
+        Self { owner, address, calls: 0, failures: 0, consecutive_failures: 0, last_error: None, disabled: false }
+    }
+
+    pub fn get_owner(&self) -> &str { &self.owner }
+    pub fn get_address(&self) -> usize { self.address }
+    pub fn get_calls(&self) -> u64 { self.calls }
+    pub fn get_failures(&self) -> u32 { self.failures }
+    pub fn get_last_error(&self) -> Option<&str> { self.last_error.as_deref() }
+    pub fn is_disabled(&self) -> bool { self.disabled }
+}
+
+/// Health of every callback that has been run, in the order they were first seen
+#[derive(Debug)]
+pub struct CallbackDiagnostics<K> {
+    entries: Vec<(K, CallbackHealth)>,
+    max_failures: u32
+}
+impl<K: Copy + PartialEq> CallbackDiagnostics<K> {
+    pub fn new(max_failures: u32) -> Self {
+        Self { entries: vec![], max_failures }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&CallbackHealth> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, h)| h)
+    }
+
+    /// Get the entry for a callback, creating it with make_health if it hasn't been run yet
+    pub fn get_or_insert_with<F>(&mut self, key: K, make_health: F) -> &mut CallbackHealth
+    where F: FnOnce() -> CallbackHealth
+    {
+        let index = match self.entries.iter().position(|(k, _)| *k == key) {
+            Some(i) => i,
+            None => {
+                self.entries.push((key, make_health()));
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[index].1
+    }
+
+    /// Forget a callback, so it starts with a clean record if it's added again
+    pub fn remove(&mut self, key: &K) {
+        self.entries.retain(|(k, _)| k != key);
+    }
+
+    pub fn is_disabled(&self, key: &K) -> bool {
+        self.get(key).is_some_and(|h| h.disabled)
+    }
+
+    pub fn record_success(&mut self, key: &K) {
+        if let Some((_, h)) = self.entries.iter_mut().find(|(k, _)| k == key) {
+            h.calls += 1;
+            h.consecutive_failures = 0;
+        }
+    }
+
+    /// Returns true if this failure caused the callback to be disabled
+    pub fn record_failure(&mut self, key: &K, error: String) -> bool {
+        let Some((_, h)) = self.entries.iter_mut().find(|(k, _)| k == key) else { return false };
+        h.calls += 1;
+        h.failures += 1;
+        h.consecutive_failures += 1;
+        h.last_error = Some(error);
+        if !h.disabled && h.consecutive_failures >= self.max_failures {
+            h.disabled = true;
+            return true;
+        }
+        false
+    }
+
+    /// Run a disabled callback again. Its failure count is kept.
+    pub fn enable(&mut self, key: &K) {
+        if let Some((_, h)) = self.entries.iter_mut().find(|(k, _)| k == key) {
+            h.disabled = false;
+            h.consecutive_failures = 0;
+        }
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.entries.iter().any(|(_, h)| h.failures > 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(K, CallbackHealth)> {
+        self.entries.iter()
+    }
+}
+
+/// Get the file name of the module containing an address, used to tell which plugin a
+/// callback belongs to. Falls back to the address itself.
+pub unsafe fn get_module_name(address: usize) -> String {
+    let mut module = HMODULE::default();
+    if GetModuleHandleExA(
+        GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+        PCSTR(address as *const u8),
+        &raw mut module
+    ).is_err() {
+        return format!("0x{:x}", address);
+    }
+    let mut path = [0u8; 260];
+    let len = GetModuleFileNameA(Some(module), &mut path) as usize;
+    let path = String::from_utf8_lossy(&path[..len]);
+    match path.rsplit('\\').next() {
+        Some(name) if !name.is_empty() => name.to_owned(),
+        _ => format!("0x{:x}", address)
+    }
+}
+
+// ImGuiErrorLogCallback is variadic, which can't be defined in stable Rust. The extra
+// arguments are passed after the format string and are never read, so a function taking just
+// the first two is safe to call through it on x64. We keep the unformatted message.
+unsafe extern "C" fn log_recovered_error(user_data: *mut c_void, fmt: *const c_char) {
+    let messages = &mut *(user_data as *mut Vec<String>);
+    messages.push(CStr::from_ptr(fmt).to_string_lossy().into_owned());
+}
+
+/// End any windows, child windows, tables, ID scopes, style pushes etc. that a callback left
+/// open (ImGui::ErrorCheckEndFrameRecover). This must be called between NewFrame and Render,
+/// when only the fallback window should be on the stack. Returns what had to be cleaned up.
+pub unsafe fn recover_imgui_stacks() -> Vec<String> {
+    let mut messages: Vec<String> = vec![];
+    let log: unsafe extern "C" fn(*mut c_void, *const c_char) = log_recovered_error;
+    imgui::sys::igErrorCheckEndFrameRecover(
+        Some(std::mem::transmute(log)),
+        &raw mut messages as *mut c_void
+    );
+    messages
+}
+
+// Set by set_diagnostics_visible, or when a callback is disabled. This is outside of BACKEND
+// so it can be toggled from inside a GUI callback.
+static DIAGNOSTICS_VISIBLE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_visible() -> bool {
+    DIAGNOSTICS_VISIBLE.load(Ordering::Relaxed)
+}
+
+pub fn set_visible(visible: bool) {
+    DIAGNOSTICS_VISIBLE.store(visible, Ordering::Relaxed);
+}
+
+/// Draw a window listing callbacks that have failed, with a button to re-enable disabled ones
+pub fn draw_diagnostics<K: Copy + PartialEq>(ui: &ImUI, diagnostics: &mut CallbackDiagnostics<K>) {
+    let mut opened = true;
+    let mut enable = None;
+    ui.window("Imgui Hook Diagnostics")
+        .size([480., 240.], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .build(|| {
+            if !diagnostics.has_failures() {
+                ui.text("No GUI callbacks have failed");
+                return;
+            }
+            for (key, health) in diagnostics.iter().filter(|(_, h)| h.failures > 0) {
+                let status = match health.disabled {
+                    true => "Disabled",
+                    false => "Enabled"
+                };
+                ui.text(format!("{} (0x{:x}): {}, {} of {} calls failed",
+                    health.owner, health.address, status, health.failures, health.calls));
+                if health.disabled {
+                    ui.same_line();
+                    if ui.small_button(format!("Re-enable##{:x}", health.address)) {
+                        enable = Some(*key);
+                    }
+                }
+                if let Some(e) = &health.last_error {
+                    ui.text_wrapped(format!("    {}", e));
+                }
+            }
+        });
+    if let Some(key) = enable {
+        diagnostics.enable(&key);
+    }
+    if !opened {
+        set_visible(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_diagnostics(keys: &[u32]) -> CallbackDiagnostics<u32> {
+        let mut diagnostics = CallbackDiagnostics::new(MAX_CONSECUTIVE_FAILURES);
+        for key in keys {
+            diagnostics.get_or_insert_with(*key, || CallbackHealth::new(format!("plugin{}.dll", key), *key as usize));
+        }
+        diagnostics
+    }
+
+    #[test]
+    fn disable_after_max_failures() {
+        let mut diagnostics = make_diagnostics(&[1]);
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            assert!(!diagnostics.record_failure(&1, "Unbalanced ImGui calls".to_owned()));
+            assert!(!diagnostics.is_disabled(&1));
+        }
+        assert!(diagnostics.record_failure(&1, "Unbalanced ImGui calls".to_owned()));
+        assert!(diagnostics.is_disabled(&1));
+        // Only the failure that disabled it reports true
+        assert!(!diagnostics.record_failure(&1, "Unbalanced ImGui calls".to_owned()));
+        let health = diagnostics.get(&1).unwrap();
+        assert_eq!(health.get_failures(), MAX_CONSECUTIVE_FAILURES + 1);
+        assert_eq!(health.get_last_error(), Some("Unbalanced ImGui calls"));
+    }
+
+    #[test]
+    fn success_resets_consecutive_failures() {
+        let mut diagnostics = make_diagnostics(&[1]);
+        for _ in 0..4 {
+            for _ in 1..MAX_CONSECUTIVE_FAILURES {
+                diagnostics.record_failure(&1, "error".to_owned());
+            }
+            diagnostics.record_success(&1);
+        }
+        assert!(!diagnostics.is_disabled(&1));
+        assert!(diagnostics.has_failures());
+        assert_eq!(diagnostics.get(&1).unwrap().get_calls(), 4 * MAX_CONSECUTIVE_FAILURES as u64);
+    }
+
+    #[test]
+    fn enable_keeps_failure_count() {
+        let mut diagnostics = make_diagnostics(&[1, 2]);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            diagnostics.record_failure(&1, "error".to_owned());
+        }
+        diagnostics.enable(&1);
+        assert!(!diagnostics.is_disabled(&1));
+        assert_eq!(diagnostics.get(&1).unwrap().get_failures(), MAX_CONSECUTIVE_FAILURES);
+        // It gets the full number of failures again before being disabled
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            assert!(!diagnostics.record_failure(&1, "error".to_owned()));
+        }
+        assert!(diagnostics.record_failure(&1, "error".to_owned()));
+        assert!(!diagnostics.is_disabled(&2));
+    }
+
+    #[test]
+    fn remove_forgets_callback() {
+        let mut diagnostics = make_diagnostics(&[1, 2]);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            diagnostics.record_failure(&1, "error".to_owned());
+        }
+        diagnostics.remove(&1);
+        assert!(diagnostics.get(&1).is_none());
+        assert!(!diagnostics.is_disabled(&1));
+        assert!(!diagnostics.has_failures());
+        // Nothing is recorded for a callback that isn't tracked
+        assert!(!diagnostics.record_failure(&1, "error".to_owned()));
+        let health = diagnostics.get_or_insert_with(1, || CallbackHealth::new("plugin1.dll".to_owned(), 1));
+        assert_eq!(health.get_failures(), 0);
+        assert_eq!(diagnostics.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![2, 1]);
+    }
+}
@@ -1,3 +1,6 @@
 pub mod backend;
+pub mod diagnostics;
 pub mod globals;
+pub mod pending;
+pub mod plugin;
 pub mod start;
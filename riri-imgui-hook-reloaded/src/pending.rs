@@ -0,0 +1,45 @@
+// Registrations made before the backend exists, e.g by mods during their own load, which
+// happens before the game's first Present. They're handed to the backend once it starts.
+
+#[derive(Debug)]
+pub struct PendingRegistrations<C, A> {
+    callbacks: Vec<C>,
+    allocators: Vec<A>
+}
+
+impl<C, A> PendingRegistrations<C, A> {
+    pub const fn new() -> Self {
+        Self { callbacks: vec![], allocators: vec![] }
+    }
+
+    pub fn add_callback(&mut self, cb: C) {
+        self.callbacks.push(cb);
+    }
+
+    pub fn has_callback<F: Fn(&C) -> bool>(&self, f: F) -> bool {
+        self.callbacks.iter().any(f)
+    }
+
+    pub fn get_callback_mut<F: Fn(&C) -> bool>(&mut self, f: F) -> Option<&mut C> {
+        self.callbacks.iter_mut().find(|c| f(c))
+    }
+
+    /// Remove the first queued GUI callback that matches. Returns None if nothing matched.
+    pub fn remove_callback<F: Fn(&C) -> bool>(&mut self, f: F) -> Option<C> {
+        let index = self.callbacks.iter().position(f)?;
+        Some(self.callbacks.remove(index))
+    }
+
+    pub fn add_allocator(&mut self, cb: A) {
+        self.allocators.push(cb);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty() && self.allocators.is_empty()
+    }
+
+    /// Take everything that's queued, in the order it was added
+    pub fn take(&mut self) -> (Vec<C>, Vec<A>) {
+        (std::mem::take(&mut self.callbacks), std::mem::take(&mut self.allocators))
+    }
+}
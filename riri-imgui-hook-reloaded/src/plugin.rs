@@ -0,0 +1,372 @@
+// The C plugin ABI. Plugins fill in a RiriImguiPluginDesc and pass it to register_plugin.
+// include/riri_imgui_hook.h is generated from this file and backend.rs by build.rs, so
+// anything in here that's visible to C should stay #[repr(C)].
+pub(crate) use riri_imgui_hook::callback::CallbackTypeSignature;
+use riri_imgui_hook::registry::RendererType;
+use std::{
+    error::Error,
+    ffi::{ c_char, c_void, CStr },
+    fmt::Display,
+    sync::atomic::{ AtomicU64, Ordering }
+};
+
+/// Version of RiriImguiPluginDesc. This is increased whenever the struct's layout changes.
+pub const RIRI_IMGUI_ABI_VERSION: u32 = 1;
+
+/// register_texture_d3d11 is available (Direct3D 11 only)
+pub const RIRI_IMGUI_CAP_TEXTURES_D3D11: u32 = 1 << 0;
+/// create_texture_rgba, load_texture_from_file and load_texture_from_memory are available
+pub const RIRI_IMGUI_CAP_TEXTURES: u32 = 1 << 1;
+/// Fonts can be added with add_font_from_file and add_font_from_memory
+pub const RIRI_IMGUI_CAP_FONTS: u32 = 1 << 2;
+/// Draw callbacks that leave ImGui's stacks unbalanced are cleaned up after and shown in the
+/// diagnostics window
+pub const RIRI_IMGUI_CAP_DIAGNOSTICS: u32 = 1 << 3;
+
+/// Draw the plugin's GUI. ui is the Rust imgui::Ui for the frame, ctx is the ImGuiContext.
+/// Plugin functions must not unwind, a panic escaping one aborts the game. Rust plugins can
+/// catch their own with riri_imgui_hook::callback::guard_callback.
+pub type PluginDrawFn = unsafe extern "C" fn(ui: *mut c_void, ctx: *mut c_void, user_data: *mut c_void);
+pub type PluginHookFn = unsafe extern "C" fn(user_data: *mut c_void);
+
+/// Describes a plugin to register_plugin. Every function is optional.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RiriImguiPluginDesc {
+    /// Must be RIRI_IMGUI_ABI_VERSION
+    pub abi_version: u32,
+    /// Draw order, lower priorities are drawn first (and end up underneath)
+    pub priority: i32,
+    /// Null terminated name shown in logs and the diagnostics window
+    pub name: *const c_char,
+    /// Null terminated Dear ImGui version the plugin was built with (IMGUI_VERSION)
+    pub imgui_version: *const c_char,
+    /// Passed to every function below
+    pub user_data: *mut c_void,
+    /// Called every frame while the overlay is visible
+    pub on_draw: Option<PluginDrawFn>,
+    /// Called once the backend is running, before the first on_draw. Textures can be created
+    /// from here.
+    pub on_init: Option<PluginHookFn>,
+    /// Called before the swapchain's buffers are resized. Anything referencing them should be
+    /// released.
+    pub on_device_lost: Option<PluginHookFn>,
+    /// Called when the plugin is unregistered or the backend shuts down. Textures are
+    /// released by the backend afterwards.
+    pub on_shutdown: Option<PluginHookFn>
+}
+
+/// Get which optional exports can be used with a renderer as RIRI_IMGUI_CAP_* flags
+pub fn get_capabilities(renderer: RendererType) -> u32 {
+    let common = RIRI_IMGUI_CAP_TEXTURES | RIRI_IMGUI_CAP_FONTS | RIRI_IMGUI_CAP_DIAGNOSTICS;
+    match renderer {
+        RendererType::Direct3D11 => common | RIRI_IMGUI_CAP_TEXTURES_D3D11,
+        RendererType::Direct3D12 => common
+    }
+}
+
+#[derive(Debug)]
+pub enum PluginDescError {
+    AbiVersion(u32),
+    ImguiVersion(String),
+    NoImguiVersion,
+    NoName
+}
+impl Display for PluginDescError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AbiVersion(v) => write!(f, "Plugin ABI version is {}, but expected {}", v, RIRI_IMGUI_ABI_VERSION),
+            Self::ImguiVersion(v) => write!(f, "Imgui version is {}, but plugin uses version {}", imgui::dear_imgui_version(), v),
+            Self::NoImguiVersion => write!(f, "Plugin didn't give the Imgui version it uses"),
+            Self::NoName => write!(f, "Plugin has no name")
+        }
+    }
+}
+impl Error for PluginDescError {}
+
+/// Check a Dear ImGui version string from a plugin against ours. ImGui's structs change
+/// between versions, so a mismatch can't be used safely.
+pub unsafe fn check_imgui_version(version: *const c_char) -> Result<(), PluginDescError> {
+    if version.is_null() {
+        return Err(PluginDescError::NoImguiVersion);
+    }
+    let version = CStr::from_ptr(version).to_string_lossy();
+    match version == imgui::dear_imgui_version() {
+        true => Ok(()),
+        false => Err(PluginDescError::ImguiVersion(version.into_owned()))
+    }
+}
+
+/// Identifies a registered plugin. Handles are never reused, 0 is never a valid handle.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PluginHandle(u64);
+impl PluginHandle {
+    fn next() -> Self {
+        static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed))
+    }
+    pub fn from_raw(value: u64) -> Self { Self(value) }
+    pub fn get_raw(&self) -> u64 { self.0 }
+}
+
+/// A lifecycle function to call once BACKEND is unlocked, so it can use the other exports
+#[derive(Debug)]
+pub struct PluginHook {
+    handle: PluginHandle,
+    hook: PluginHookFn,
+    user_data: *mut c_void
+}
+impl PluginHook {
+    pub fn get_handle(&self) -> PluginHandle { self.handle }
+
+    /// Call the hook. It's in the plugin's DLL, so it has to catch its own panics.
+    pub unsafe fn call(&self) {
+        (self.hook)(self.user_data)
+    }
+}
+
+#[derive(Debug)]
+pub struct Plugin {
+    handle: PluginHandle,
+    name: String,
+    priority: i32,
+    user_data: *mut c_void,
+    on_draw: Option<PluginDrawFn>,
+    on_init: Option<PluginHookFn>,
+    on_device_lost: Option<PluginHookFn>,
+    on_shutdown: Option<PluginHookFn>,
+    // Set for plugins added through add_gui_callback, which only have a draw function
+    callback: Option<CallbackTypeSignature>,
+    // Set once on_init has returned. Nothing else is called before then.
+    initialized: bool
+}
+// user_data belongs to the plugin, which is responsible for making it safe to use from the
+// render thread
+unsafe impl Send for Plugin {}
+
+impl Plugin {
+    pub unsafe fn from_desc(desc: &RiriImguiPluginDesc) -> Result<Self, PluginDescError> {
+        if desc.abi_version != RIRI_IMGUI_ABI_VERSION {
+            return Err(PluginDescError::AbiVersion(desc.abi_version));
+        }
+        check_imgui_version(desc.imgui_version)?;
+        if desc.name.is_null() {
+            return Err(PluginDescError::NoName);
+        }
+        let name = CStr::from_ptr(desc.name).to_string_lossy().into_owned();
+        Ok(Self {
+            handle: PluginHandle::next(),
+            name,
+            priority: desc.priority,
+            user_data: desc.user_data,
+            on_draw: desc.on_draw,
+            on_init: desc.on_init,
+            on_device_lost: desc.on_device_lost,
+            on_shutdown: desc.on_shutdown,
+            callback: None,
+            initialized: desc.on_init.is_none()
+        })
+    }
+
+    /// Wrap a callback from add_gui_callback. These are named after the module they're in.
+    pub fn from_callback(cb: CallbackTypeSignature, name: String) -> Self {
+        Self {
+            handle: PluginHandle::next(),
+            name,
+            priority: 0,
+            user_data: std::ptr::null_mut(),
+            on_draw: None,
+            on_init: None,
+            on_device_lost: None,
+            on_shutdown: None,
+            callback: Some(cb),
+            initialized: true
+        }
+    }
+
+    /// Check if this wraps a callback from add_gui_callback
+    pub fn is_callback(&self, cb: CallbackTypeSignature) -> bool {
+        self.callback.is_some_and(|c| c as usize == cb as usize)
+    }
+
+    pub fn get_handle(&self) -> PluginHandle { self.handle }
+    pub fn get_name(&self) -> &str { &self.name }
+    pub fn get_priority(&self) -> i32 { self.priority }
+    pub fn is_initialized(&self) -> bool { self.initialized }
+
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
+    // Plugins are drawn by priority, then in the order they were registered in. Handles are
+    // given out in registration order, so they break ties.
+    fn get_draw_order(&self) -> (i32, PluginHandle) {
+        (self.priority, self.handle)
+    }
+
+    pub fn set_initialized(&mut self) {
+        self.initialized = true;
+    }
+
+    /// Address of the plugin's code, used to tell plugins apart in the diagnostics window
+    pub fn get_address(&self) -> usize {
+        match (self.callback, self.on_draw) {
+            (Some(cb), _) => cb as usize,
+            (None, Some(f)) => f as usize,
+            (None, None) => 0
+        }
+    }
+
+    pub unsafe fn draw(&self, ui: *mut c_void, ctx: *mut c_void) {
+        match (self.callback, self.on_draw) {
+            (Some(cb), _) => cb(ui as *mut u8, ctx as *mut u8),
+            (None, Some(f)) => f(ui, ctx, self.user_data),
+            (None, None) => ()
+        }
+    }
+
+    pub fn has_draw(&self) -> bool { self.callback.is_some() || self.on_draw.is_some() }
+
+    fn make_hook(&self, hook: Option<PluginHookFn>) -> Option<PluginHook> {
+        hook.map(|hook| PluginHook { handle: self.handle, hook, user_data: self.user_data })
+    }
+
+    /// on_init, if it hasn't been called yet
+    pub fn get_init_hook(&self) -> Option<PluginHook> {
+        match self.initialized {
+            true => None,
+            false => self.make_hook(self.on_init)
+        }
+    }
+
+    pub fn get_device_lost_hook(&self) -> Option<PluginHook> {
+        match self.initialized {
+            true => self.make_hook(self.on_device_lost),
+            false => None
+        }
+    }
+
+    /// on_shutdown, if on_init was called
+    pub fn get_shutdown_hook(&self) -> Option<PluginHook> {
+        match self.initialized {
+            true => self.make_hook(self.on_shutdown),
+            false => None
+        }
+    }
+}
+
+/// Registered plugins in the order they're drawn in. Plugins with the same priority keep the
+/// order they were registered in, so the draw order is the same every time the game runs.
+#[derive(Debug, Default)]
+pub struct PluginList {
+    plugins: Vec<Plugin>
+}
+impl PluginList {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn insert(&mut self, plugin: Plugin) {
+        let index = self.plugins.partition_point(|p| p.get_draw_order() < plugin.get_draw_order());
+        self.plugins.insert(index, plugin);
+    }
+
+    /// Remove the first plugin that matches
+    pub fn remove<F: Fn(&Plugin) -> bool>(&mut self, f: F) -> Option<Plugin> {
+        let index = self.plugins.iter().position(f)?;
+        Some(self.plugins.remove(index))
+    }
+
+    pub fn get_mut(&mut self, handle: PluginHandle) -> Option<&mut Plugin> {
+        self.plugins.iter_mut().find(|p| p.handle == handle)
+    }
+
+    /// Move the first plugin that matches to its place for a new priority. Returns false if
+    /// nothing matched.
+    pub fn set_priority<F: Fn(&Plugin) -> bool>(&mut self, f: F, priority: i32) -> bool {
+        let Some(mut plugin) = self.remove(f) else { return false };
+        plugin.set_priority(priority);
+        self.insert(plugin);
+        true
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Plugin> {
+        self.plugins.iter()
+    }
+
+    pub fn len(&self) -> usize { self.plugins.len() }
+    pub fn is_empty(&self) -> bool { self.plugins.is_empty() }
+
+    pub fn clear(&mut self) {
+        self.plugins.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Different bodies so the two can't be merged into one function
+    unsafe extern "C" fn draw_a(ui: *mut u8, _ctx: *mut u8) { *ui = 1; }
+    unsafe extern "C" fn draw_b(ui: *mut u8, _ctx: *mut u8) { *ui = 2; }
+
+    fn make_plugin(name: &str, priority: i32) -> Plugin {
+        let mut plugin = Plugin::from_callback(draw_a, name.to_owned());
+        plugin.set_priority(priority);
+        plugin
+    }
+
+    fn get_names(list: &PluginList) -> Vec<&str> {
+        list.iter().map(|p| p.get_name()).collect()
+    }
+
+    #[test]
+    fn ordered_by_priority() {
+        let mut list = PluginList::new();
+        list.insert(make_plugin("b", 10));
+        list.insert(make_plugin("c", 20));
+        list.insert(make_plugin("a", -5));
+        assert_eq!(get_names(&list), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn ties_keep_registration_order() {
+        let mut list = PluginList::new();
+        let first = make_plugin("first", 0);
+        let second = make_plugin("second", 0);
+        let third = make_plugin("third", 0);
+        // Inserting out of order still draws them in the order they were created
+        list.insert(third);
+        list.insert(first);
+        list.insert(second);
+        list.insert(make_plugin("low", -1));
+        assert_eq!(get_names(&list), ["low", "first", "second", "third"]);
+    }
+
+    #[test]
+    fn reprioritise() {
+        let mut list = PluginList::new();
+        for (name, priority) in [("a", 0), ("b", 0), ("c", 5)] {
+            list.insert(make_plugin(name, priority));
+        }
+        assert!(list.set_priority(|p| p.get_name() == "a", 10));
+        assert_eq!(get_names(&list), ["b", "c", "a"]);
+        // Moving back to a shared priority puts it back in registration order
+        assert!(list.set_priority(|p| p.get_name() == "a", 0));
+        assert_eq!(get_names(&list), ["a", "b", "c"]);
+        assert!(!list.set_priority(|p| p.get_name() == "d", 0));
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn find_and_remove_callbacks() {
+        let mut list = PluginList::new();
+        list.insert(make_plugin("a", 0));
+        list.insert(Plugin::from_callback(draw_b, "b".to_owned()));
+        let handle = list.iter().find(|p| p.is_callback(draw_b)).unwrap().get_handle();
+        assert_eq!(list.get_mut(handle).unwrap().get_name(), "b");
+        assert_eq!(list.remove(|p| p.is_callback(draw_b)).unwrap().get_name(), "b");
+        assert!(list.remove(|p| p.is_callback(draw_b)).is_none());
+        assert!(list.get_mut(handle).is_none());
+        assert_eq!(get_names(&list), ["a"]);
+    }
+}
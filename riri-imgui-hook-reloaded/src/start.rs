@@ -2,33 +2,97 @@ use crate::backend::Backend;
 use riri_imgui_hook::{
     d3d11_impl::init as d3d11_init,
     d3d12_impl::init as d3d12_init,
-    registry::{ RendererType, RegistryEntry }
+    detect::{ self, ProcessModules },
+    registry::{ self, RendererType, RegistryEntry }
 };
 use riri_mod_tools_proc::riri_mods_loaded_fn;
-use std::sync::{ Mutex, OnceLock };
+use riri_mod_tools_rt::logln;
+use std::sync::{
+    atomic::{ AtomicU32, Ordering },
+    Mutex,
+    OnceLock
+};
 
 pub(crate) static BACKEND: Mutex<Option<Backend>> = Mutex::new(None);
 pub(crate) static TARGET: OnceLock<&'static RegistryEntry<'static>> = OnceLock::new();
+/// Where the backend is in its lifetime, as reported by get_backend_status
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendStatus {
+    /// Waiting for the game to present its first frame
+    Starting = 0,
+    Running = 1,
+    /// Creating hooks or the backend failed. Nothing will be drawn.
+    Failed = 2,
+    /// shutdown was called
+    ShutDown = 3
+}
+impl BackendStatus {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Self::Starting,
+            1 => Self::Running,
+            2 => Self::Failed,
+            _ => Self::ShutDown
+        }
+    }
+}
+
+static STATUS: AtomicU32 = AtomicU32::new(BackendStatus::Starting as u32);
+
+pub fn get_status() -> BackendStatus {
+    BackendStatus::from_u32(STATUS.load(Ordering::Acquire))
+}
+
+pub(crate) fn set_status(status: BackendStatus) {
+    STATUS.store(status as u32, Ordering::Release);
+}
+
+// Hooks are disabled on shutdown or a failed start. A call that was already inside one when it
+// was disabled passes everything through to the original function once this returns false.
+pub(crate) fn hooks_enabled() -> bool {
+    matches!(get_status(), BackendStatus::Starting | BackendStatus::Running)
+}
+
+// This is expected to be run on a separate thread
+unsafe fn make_hooks(renderer: RendererType) {
+    match renderer {
+        RendererType::Direct3D11 => {
+            d3d11_init::start_d3d11();
+            Backend::make_hooks_d3d11();
+        },
+        RendererType::Direct3D12 => {
+            d3d12_init::start_d3d12();
+            Backend::make_hooks_d3d12();
+        }
+    }
+}
 
 // Wait for all other DLLs to load in case they decide to hook the same functions as us
 // (e.g Steam hooks Present and ResizeBuffers for their game overlay)
 // afaik Gamepass does not do this
 #[riri_mods_loaded_fn()]
 fn start() {
-    let _ = TARGET.set(riri_imgui_hook::registry::get_registry_entry());
-    let value = *TARGET.get().unwrap();
-    match value.get_renderer() {
-        RendererType::Direct3D11 => {
-            std::thread::spawn(|| { unsafe { 
-                d3d11_init::start_d3d11();
-                Backend::make_hooks_d3d11();
-            }});
+    match registry::find_registry_entry() {
+        Some(value) => {
+            logln!(Information, "Using registry entry for {} ({:?})", value.get_executable(), value.get_renderer());
+            let _ = TARGET.set(value);
+            std::thread::spawn(move || { unsafe { make_hooks(value.get_renderer()) }});
         },
-        RendererType::Direct3D12 => {
-            std::thread::spawn(|| { unsafe { 
-                d3d12_init::start_d3d12();
-                Backend::make_hooks_d3d12();
-            }});
+        None => {
+            // Unknown game, find out which renderer it uses from the loaded Direct3D DLLs
+            std::thread::spawn(|| {
+                let value = match detect::wait_for_renderer(&ProcessModules) {
+                    Some(d) => registry::get_detected_entry(d.get_renderer()),
+                    None => {
+                        let value = registry::get_default_entry();
+                        logln!(Warning, "Falling back to {:?}", value.get_renderer());
+                        value
+                    }
+                };
+                let _ = TARGET.set(value);
+                unsafe { make_hooks(value.get_renderer()) }
+            });
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,104 @@
+// Helpers for plugins writing GUI callbacks in Rust. Callbacks are plain extern "C" functions,
+// so a panic can't unwind out of them into the backend: it would abort the game, and the
+// backend couldn't catch it anyway since a panic from another DLL's copy of the Rust runtime
+// is a foreign exception to catch_unwind. Plugins catch their own panics with guard_callback.
+use riri_mod_tools_rt::logln;
+use std::{
+    any::Any,
+    panic::AssertUnwindSafe
+};
+
+/// The draw callback taken by add_gui_callback. It's given the frame's imgui::Ui and the
+/// ImGuiContext.
+pub type CallbackTypeSignature = unsafe extern "C" fn(*mut u8, *mut u8);
+
+/// Get the text from a panic payload. panic! with a literal gives a &str, with format
+/// arguments it gives a String.
+pub fn get_panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(v) => v.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(v) => v.clone(),
+            None => "Box<dyn Any>".to_owned()
+        }
+    }
+}
+
+/// Run the body of a plugin function, catching and logging any panic so it doesn't reach the
+/// extern "C" boundary. Returns false if it panicked. Anything the body left open on ImGui's
+/// stacks is cleaned up by the backend and counted as a failure in the diagnostics window.
+pub fn guard_callback<F: FnOnce()>(name: &str, f: F) -> bool {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(_) => true,
+        Err(payload) => {
+            logln!(Error, "{} panicked: {}", name, get_panic_message(payload.as_ref()));
+            false
+        }
+    }
+}
+
+/// Define an extern "C" function for add_gui_callback that calls a Rust function with the same
+/// arguments inside guard_callback:
+///
+/// ```ignore
+/// unsafe fn draw_menu(ui: *mut u8, ctx: *mut u8) { ... }
+/// riri_imgui_hook::gui_callback!(draw_menu_callback, draw_menu);
+/// ```
+#[macro_export]
+macro_rules! gui_callback {
+    ($name:ident, $draw:path) => {
+        pub unsafe extern "C" fn $name(ui: *mut u8, ctx: *mut u8) {
+            #[allow(unused_unsafe)]
+            $crate::callback::guard_callback(stringify!($draw), || unsafe { $draw(ui, ctx) });
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_returns_true() {
+        let mut ran = false;
+        assert!(guard_callback("test", || ran = true));
+        assert!(ran);
+    }
+
+    #[test]
+    fn guard_catches_panic() {
+        assert!(!guard_callback("test", || panic!("in callback")));
+        assert!(!guard_callback("test", || panic!("frame {}", 3)));
+    }
+
+    #[test]
+    fn panic_message() {
+        let payload = std::panic::catch_unwind(|| panic!("literal")).unwrap_err();
+        assert_eq!(get_panic_message(payload.as_ref()), "literal");
+        let payload = std::panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(get_panic_message(payload.as_ref()), "formatted 1");
+        let payload = std::panic::catch_unwind(|| std::panic::panic_any(5)).unwrap_err();
+        assert_eq!(get_panic_message(payload.as_ref()), "Box<dyn Any>");
+    }
+
+    unsafe fn draw_twice(ui: *mut u8, _ctx: *mut u8) {
+        *ui += 2;
+    }
+    crate::gui_callback!(draw_twice_callback, draw_twice);
+
+    unsafe fn draw_panics(_ui: *mut u8, _ctx: *mut u8) {
+        panic!("draw failed");
+    }
+    crate::gui_callback!(draw_panics_callback, draw_panics);
+
+    #[test]
+    fn callback_macro() {
+        let mut value = 1u8;
+        let cb: CallbackTypeSignature = draw_twice_callback;
+        unsafe { cb(&raw mut value, std::ptr::null_mut()) };
+        assert_eq!(value, 3);
+        // The panic is caught inside the callback and doesn't unwind out of it
+        let cb: CallbackTypeSignature = draw_panics_callback;
+        unsafe { cb(std::ptr::null_mut(), std::ptr::null_mut()) };
+    }
+}
@@ -1,14 +1,42 @@
-use crate::registry::RegistryEntry;
+use crate::{
+    fonts,
+    registry::RegistryEntry
+};
 use imgui::{
     ConfigFlags,
-    Context as ImContext,
-    FontConfig,
-    FontGlyphRanges,
-    FontSource
+    Context as ImContext
 };
 use riri_mod_tools_rt::mod_loader_data;
 use riri_mod_tools_rt::logln;
-use std::path::PathBuf;
+use std::{
+    error::Error,
+    fmt::Display,
+    path::PathBuf
+};
+
+/// Error from parsing one of the TOML files in the mod directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigParseError {
+    line: Option<usize>,
+    message: String
+}
+impl ConfigParseError {
+    pub(crate) fn new(source: &str, span: Option<std::ops::Range<usize>>, message: String) -> Self {
+        let line = span.map(|s| source[..s.start.min(source.len())].matches('\n').count() + 1);
+        Self { line, message }
+    }
+    pub fn get_line(&self) -> Option<usize> { self.line }
+    pub fn get_message(&self) -> &str { &self.message }
+}
+impl Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(l) => write!(f, "line {}: {}", l, self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+impl Error for ConfigParseError {}
 
 pub fn imgui_common_init(imgui: &mut ImContext, registry: &RegistryEntry) {
     let mod_dir: String = mod_loader_data::get_directory_for_mod().into();
@@ -18,15 +46,11 @@ pub fn imgui_common_init(imgui: &mut ImContext, registry: &RegistryEntry) {
     // Set per-app flags
     imgui.io_mut().config_flags |= registry.get_config_flags_to_set();
     imgui.io_mut().config_flags |= ConfigFlags::DOCKING_ENABLE;
-    let font_path = mod_dir.join("NotoSansCJKjp-Medium.otf");
-    let font_data = match std::fs::read(font_path) {
-        Ok(f) => f,
-        Err(_) => {
-            logln!(Warning, "Custom font is missing! Falling back to default font");
-            return;
-        }
-    };
-    let mut font_config = FontConfig::default();
-    font_config.glyph_ranges = FontGlyphRanges::japanese();
-    imgui.fonts().add_font(&[FontSource::TtfData { data: font_data.as_slice(), size_pixels: 15., config: Some(font_config) }]);
+    let mut font_stack = fonts::get_font_stack();
+    let font_config = fonts::load_font_config(mod_dir.join(fonts::FONTS_FILE_NAME));
+    if fonts::add_configured_fonts(&mut font_stack, &font_config, &mod_dir, |p| std::fs::read(p)) == 0 {
+        logln!(Warning, "No custom fonts could be loaded! Falling back to default font");
+    }
+    // The renderer uploads the atlas texture when it's created
+    font_stack.build(imgui.fonts());
 }
\ No newline at end of file
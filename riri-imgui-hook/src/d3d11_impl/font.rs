@@ -1,19 +1,9 @@
-use windows::Win32::Graphics::{
-    Dxgi::Common::{
-        DXGI_FORMAT_R8G8B8A8_UNORM,
-        DXGI_SAMPLE_DESC
-    },
-    Direct3D11::{
-        D3D11_SAMPLER_DESC,
-        D3D11_SHADER_RESOURCE_VIEW_DESC,
-        D3D11_SUBRESOURCE_DATA,
-        D3D11_TEXTURE2D_DESC,
-        D3D11_USAGE_DEFAULT,
-        ID3D11Device,
-        ID3D11SamplerState,
-        ID3D11ShaderResourceView,
-        ID3D11Texture2D
-    }
+use crate::d3d11_impl::texture::create_rgba_texture;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11_SAMPLER_DESC,
+    ID3D11Device,
+    ID3D11SamplerState,
+    ID3D11ShaderResourceView
 };
 pub const FONT_TEX_ID: usize = usize::MAX;
 use imgui::TextureId;
@@ -38,36 +28,7 @@ impl FontObjects {
         let mut out = Self::uninit();
         // Build texture atlas and upload to graphics system
         let fa_tex = fonts.build_rgba32_texture();
-        let desc = D3D11_TEXTURE2D_DESC {
-            Width: fa_tex.width,
-            Height: fa_tex.height,
-            MipLevels: 1,
-            ArraySize: 1,
-            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
-            Usage: D3D11_USAGE_DEFAULT,
-            BindFlags: windows::Win32::Graphics::Direct3D11::D3D11_BIND_SHADER_RESOURCE.0 as u32,
-            ..Default::default()
-        };
-        let sub_resource = D3D11_SUBRESOURCE_DATA {
-            pSysMem: fa_tex.data.as_ptr().cast(),
-            SysMemPitch: desc.Width * 4,
-            SysMemSlicePitch: 0,
-        };
-        let mut texture: Option<ID3D11Texture2D> = None;
-        device.CreateTexture2D(
-            &desc, 
-            Some(&sub_resource),
-            Some(&raw mut texture)
-        )?;
-        let mut srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
-            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-            ViewDimension: windows::Win32::Graphics::Direct3D::D3D11_SRV_DIMENSION_TEXTURE2D,
-            ..Default::default()
-        };
-        srv_desc.Anonymous.Texture2D.MipLevels = desc.MipLevels;
-        srv_desc.Anonymous.Texture2D.MostDetailedMip = 0;
-        device.CreateShaderResourceView(texture.as_ref().map(|v| v.into()), Some(&srv_desc), Some(&raw mut out.font_resource_view))?;
+        out.font_resource_view = Some(create_rgba_texture(device, fa_tex.width, fa_tex.height, fa_tex.data)?);
 
         fonts.tex_id = TextureId::from(FONT_TEX_ID);
 
@@ -86,6 +47,17 @@ impl FontObjects {
         device.CreateSamplerState(&desc, Some(&raw mut out.font_sampler))?;
         Ok(out)
     }
+    // Rebuild the atlas texture after the fonts have changed. The sampler is kept as is.
+    pub unsafe fn rebuild_texture(
+        &mut self,
+        fonts: &mut imgui::FontAtlas,
+        device: &ID3D11Device,
+    ) -> windows::core::Result<()> {
+        let fa_tex = fonts.build_rgba32_texture();
+        self.font_resource_view = Some(create_rgba_texture(device, fa_tex.width, fa_tex.height, fa_tex.data)?);
+        fonts.tex_id = TextureId::from(FONT_TEX_ID);
+        Ok(())
+    }
     pub fn get_font_sampler_owned(&self) -> Option<ID3D11SamplerState> {
         self.font_sampler.clone()
     }
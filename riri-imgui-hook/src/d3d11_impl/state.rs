@@ -5,10 +5,14 @@ use crate::{
         devices::DeviceObjects,
         font::{ FONT_TEX_ID, FontObjects },
         shader::{ PixelShader, VertexShader },
+        texture::{ create_rgba_texture, create_texture }
     },
-    registry::RegistryFlags
+    image::CpuImage,
+    rasterizer,
+    registry::RegistryFlags,
+    renderer::RendererBackend
 };
-use glam::{ Mat4, Vec4 };
+use glam::Mat4;
 use imgui::{
     internal::RawWrapper,
     BackendFlags,
@@ -21,13 +25,13 @@ use imgui::{
     Textures,
     TextureId
 };
-// use riri_mod_tools_rt::logln;
+use riri_mod_tools_rt::logln;
 use std::{
-    ffi::c_void,
+    any::Any,
+    collections::HashSet,
     mem::MaybeUninit
 };
 use windows::{
-    core::Interface,
     Win32::{
         Foundation::RECT,
         Graphics::{
@@ -49,13 +53,7 @@ use windows::{
 // Adapted from original C# implementation of riri-imgui-hook:
 // https://github.com/rirurin/riri.imguihook/blob/master/riri.imguihook/D3D11Hook.cs
 
-pub static DLL_NAMES: [&'static str; 5] = [
-    "d3d11.dll\0",
-    "d3d11_1.dll\0",
-    "d3d11_2.dll\0",
-    "d3d11_3.dll\0",
-    "d3d11_4.dll\0"
-];
+pub use crate::detect::D3D11_DLL_NAMES as DLL_NAMES;
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -71,6 +69,8 @@ pub struct D3D11Hook {
     vertex_buffer: Option<VertexBuffer>,
     index_buffer: Option<IndexBuffer>,
     textures: Textures<ID3D11ShaderResourceView>,
+    // Unknown texture IDs that have already been logged
+    reported_textures: HashSet<usize>,
     resized_buffer: bool,
     print_after_resize: bool
 }
@@ -81,7 +81,7 @@ unsafe impl Sync for D3D11Hook {}
 impl D3D11Hook { 
     pub fn new(ctx: &mut ImContext, swapchain: IDXGISwapChain, flags: RegistryFlags) -> windows::core::Result<Self> {
         let mut new = unsafe { Self::new_blank(ctx, swapchain)}?;
-        unsafe { new.create_device_objects(ctx, flags)? }
+        new.init(ctx, flags)?;
         Ok(new)
     }
     pub unsafe fn new_blank(ctx: &mut ImContext, swapchain: IDXGISwapChain) -> windows::core::Result<Self> {
@@ -101,48 +101,11 @@ impl D3D11Hook {
             vertex_buffer: None, 
             index_buffer: None,
             textures: Textures::new(),
+            reported_textures: HashSet::new(),
             resized_buffer: false,
             print_after_resize: false
         })
     }
-    // ImGui_ImplDX11_CreateDeviceObjects
-    pub unsafe fn create_device_objects(&mut self, ctx: &mut ImContext, flags: RegistryFlags) -> windows::core::Result<()> {
-        let back_buffer = self.swapchain.GetBuffer::<ID3D11Texture2D>(0)?;
-        self.device.CreateRenderTargetView(&back_buffer, None, Some(&raw mut self.render_target_view))?;
-        self.vertex_shader = Some(VertexShader::new(&self.device)?);
-        self.pixel_shader = Some(PixelShader::new(&self.device, flags)?);
-        self.device_objects = Some(DeviceObjects::new(&self.device)?);
-        self.font_data = Some(FontObjects::new(ctx.fonts(), &self.device)?);
-        self.vertex_buffer = Some(VertexBuffer::new(&self.device, 0)?);
-        self.index_buffer = Some(IndexBuffer::new(&self.device, 0)?);
-        Ok(())
-    }
-
-    // ImGui_ImplDX11_RenderDrawData
-    pub fn render(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
-        unsafe { self.context.OMSetRenderTargets(Some(&[self.render_target_view.clone()]), None) }
-        if draw_data.display_size[0] <= 0.0 
-        || draw_data.display_size[1] <= 0.0 {
-            return Ok(());
-        }
-        unsafe {
-            if self.vertex_buffer.as_ref().unwrap().len() < draw_data.total_vtx_count as usize {
-                // logln!(Verbose, "VERTEX BUFFER [ len: {}, cap: {} ]", draw_data.total_vtx_count, self.vertex_buffer.as_ref().unwrap().len());
-                self.vertex_buffer = Some(VertexBuffer::new(&self.device, draw_data.total_vtx_count as usize)?);
-            }
-            if self.index_buffer.as_ref().unwrap().len() < draw_data.total_idx_count as usize {
-                // logln!(Verbose, "INDEX BUFFER [ len: {}, cap: {} ]", draw_data.total_idx_count, self.index_buffer.as_ref().unwrap().len());
-                self.index_buffer = Some(IndexBuffer::new(&self.device, draw_data.total_idx_count as usize)?);
-            }
-            let _state_guard = StateBackup::backup(Some(self.context.clone()));
-            self.write_buffers(draw_data)?;
-            self.setup_render_state(draw_data);
-            self.render_impl(draw_data)?;
-            _state_guard.restore(); 
-        }
-        Ok(())
-    }
-
     unsafe fn write_buffers(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
         let mut vtx_resource: MaybeUninit<D3D11_MAPPED_SUBRESOURCE> = MaybeUninit::uninit();
         let mut idx_resource: MaybeUninit<D3D11_MAPPED_SUBRESOURCE> = MaybeUninit::uninit();
@@ -173,16 +136,7 @@ impl D3D11Hook {
             0, D3D11_MAP_WRITE_DISCARD, 0, 
             Some(mapped_resource.as_mut_ptr())
         )?;
-        let l = draw_data.display_pos[0];
-        let r = draw_data.display_pos[0] + draw_data.display_size[0];
-        let t = draw_data.display_pos[1];
-        let b = draw_data.display_pos[1] + draw_data.display_size[1];
-        let mvp = Mat4::from_cols(
-            Vec4::new(2.0 / (r - l), 0., 0., 0.,),
-            Vec4::new(0.0, 2.0 / (t - b), 0.0, 0.0),
-            Vec4::new(0.0, 0.0, 0.5, 0.0),
-            Vec4::new((r + l) / (l - r), (t + b) / (b - t), 0.5, 1.0),
-        );
+        let mvp = rasterizer::get_projection_matrix(draw_data.display_pos, draw_data.display_size);
         *mapped_resource.assume_init_mut().pData.cast::<Mat4>() = mvp;
         self.context.Unmap(vtx_buf.get_constant_buffer().map(|v| v.into()), 0);
         Ok(())
@@ -224,7 +178,7 @@ impl D3D11Hook {
         ctx.RSSetState(self.device_objects.as_ref().unwrap().get_rasterizer_state());
     }
 
-    unsafe fn render_impl(&self, draw_data: &DrawData) -> windows::core::Result<()> {
+    unsafe fn render_impl(&mut self, draw_data: &DrawData) {
         let clip_off = draw_data.display_pos;
         let clip_scale = draw_data.framebuffer_scale;
         let mut vertex_offset = 0;
@@ -240,21 +194,21 @@ impl D3D11Hook {
                         cmd_params: DrawCmdParams { clip_rect, texture_id, .. },
                     } => {
                         if texture_id != last_tex {
-                            /* 
-                            let texture = if texture_id.id() == FONT_TEX_ID {
-                                self.font_data.as_ref().unwrap().get_font_resource_view()
-                            } else {
-                                Some(self.textures
-                                    .get(texture_id)
-                                    .ok_or(DXGI_ERROR_INVALID_CALL)?
-                                    .clone())
-                            };
-                            */
                             let texture = if texture_id.id() == FONT_TEX_ID {
                                 self.font_data.as_ref().unwrap().get_font_resource_view()
                             } else {
-                                Some(ID3D11ShaderResourceView::from_raw(texture_id.id() as *mut c_void))
+                                self.textures.get(texture_id).cloned()
                             };
+                            if texture.is_none() {
+                                // Skip draws using textures that were never registered (or were
+                                // already unregistered). Only the first one for each ID is logged
+                                // since the same draw is likely to be made every frame
+                                if self.reported_textures.insert(texture_id.id()) {
+                                    logln!(Warning, "Texture ID {} is not registered with the renderer", texture_id.id());
+                                }
+                                index_offset += count;
+                                continue;
+                            }
                             context.PSSetShaderResources(0, Some(&[texture]));
                             last_tex = texture_id;
                         }
@@ -281,17 +235,109 @@ impl D3D11Hook {
             }
             vertex_offset += draw_list.vtx_buffer().len();
         }
+    }
+
+    /// Register an existing shader resource view so it can be drawn through imgui::Image.
+    /// The renderer keeps a reference to the view until destroy_texture is called.
+    pub fn register_texture(&mut self, srv: ID3D11ShaderResourceView) -> TextureId {
+        self.textures.insert(srv)
+    }
+    pub fn get_texture(&self, id: TextureId) -> Option<&ID3D11ShaderResourceView> {
+        self.textures.get(id)
+    }
+}
+
+impl RendererBackend for D3D11Hook {
+    // ImGui_ImplDX11_CreateDeviceObjects
+    fn init(&mut self, ctx: &mut ImContext, flags: RegistryFlags) -> windows::core::Result<()> {
+        unsafe {
+            let back_buffer = self.swapchain.GetBuffer::<ID3D11Texture2D>(0)?;
+            self.device.CreateRenderTargetView(&back_buffer, None, Some(&raw mut self.render_target_view))?;
+            self.vertex_shader = Some(VertexShader::new(&self.device)?);
+            self.pixel_shader = Some(PixelShader::new(&self.device, flags)?);
+            self.device_objects = Some(DeviceObjects::new(&self.device)?);
+            self.font_data = Some(FontObjects::new(ctx.fonts(), &self.device)?);
+            self.vertex_buffer = Some(VertexBuffer::new(&self.device, 0)?);
+            self.index_buffer = Some(IndexBuffer::new(&self.device, 0)?);
+        }
         Ok(())
     }
 
+    // ImGui_ImplDX11_RenderDrawData
+    fn render(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
+        unsafe { self.context.OMSetRenderTargets(Some(&[self.render_target_view.clone()]), None) }
+        if draw_data.display_size[0] <= 0.0 
+        || draw_data.display_size[1] <= 0.0 {
+            return Ok(());
+        }
+        unsafe {
+            if self.vertex_buffer.as_ref().unwrap().len() < draw_data.total_vtx_count as usize {
+                // logln!(Verbose, "VERTEX BUFFER [ len: {}, cap: {} ]", draw_data.total_vtx_count, self.vertex_buffer.as_ref().unwrap().len());
+                self.vertex_buffer = Some(VertexBuffer::new(&self.device, draw_data.total_vtx_count as usize)?);
+            }
+            if self.index_buffer.as_ref().unwrap().len() < draw_data.total_idx_count as usize {
+                // logln!(Verbose, "INDEX BUFFER [ len: {}, cap: {} ]", draw_data.total_idx_count, self.index_buffer.as_ref().unwrap().len());
+                self.index_buffer = Some(IndexBuffer::new(&self.device, draw_data.total_idx_count as usize)?);
+            }
+            let _state_guard = StateBackup::backup(Some(self.context.clone()));
+            self.write_buffers(draw_data)?;
+            self.setup_render_state(draw_data);
+            self.render_impl(draw_data);
+            _state_guard.restore(); 
+            Ok(())
+        }
+    }
+
     // ImGui_ImplDX11_InvalidateDeviceObjects
-    pub fn invalidate_render_target_view(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> {
+    fn before_resize(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> {
         self.render_target_view = None;
         Ok(())
     }
-    pub unsafe fn create_render_target_view(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> { 
-        let back_buffer = self.swapchain.GetBuffer::<ID3D11Texture2D>(0)?;
-        self.device.CreateRenderTargetView(&back_buffer, None, Some(&raw mut self.render_target_view))?;
+
+    fn after_resize(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> {
+        unsafe {
+            let back_buffer = self.swapchain.GetBuffer::<ID3D11Texture2D>(0)?;
+            self.device.CreateRenderTargetView(&back_buffer, None, Some(&raw mut self.render_target_view))?;
+        }
+        Ok(())
+    }
+
+    // Swap the font texture for a newly built atlas. Nothing else needs to be recreated.
+    fn rebuild_font_texture(&mut self, ctx: &mut ImContext) -> windows::core::Result<()> {
+        unsafe { self.font_data.as_mut().unwrap().rebuild_texture(ctx.fonts(), &self.device) }
+    }
+
+    fn create_texture(&mut self, image: &CpuImage) -> windows::core::Result<TextureId> {
+        let srv = unsafe { create_texture(&self.device, image)? };
+        Ok(self.textures.insert(srv))
+    }
+
+    fn create_texture_rgba(&mut self, width: u32, height: u32, data: &[u8]) -> windows::core::Result<TextureId> {
+        let srv = unsafe { create_rgba_texture(&self.device, width, height, data)? };
+        Ok(self.textures.insert(srv))
+    }
+
+    // Release the renderer's reference to the view. Views from register_texture are only
+    // destroyed once the caller releases theirs too.
+    fn destroy_texture(&mut self, id: TextureId) -> bool {
+        self.textures.remove(id).is_some()
+    }
+
+    // ImGui_ImplDX11_Shutdown. Our objects are never left bound to the game's context (render
+    // restores its state), so they can be released straight away.
+    fn shutdown(&mut self, ctx: &mut ImContext) -> windows::core::Result<()> {
+        self.textures = Textures::new();
+        self.font_data = None;
+        self.vertex_buffer = None;
+        self.index_buffer = None;
+        self.device_objects = None;
+        self.pixel_shader = None;
+        self.vertex_shader = None;
+        self.render_target_view = None;
+        ctx.set_renderer_name(None);
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
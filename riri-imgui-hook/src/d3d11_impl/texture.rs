@@ -0,0 +1,82 @@
+use crate::image::{ CpuImage, ImageFormat };
+use windows::Win32::Graphics::{
+    Dxgi::Common::{
+        DXGI_FORMAT,
+        DXGI_FORMAT_R8G8B8A8_UNORM,
+        DXGI_SAMPLE_DESC
+    },
+    Direct3D11::{
+        D3D11_SHADER_RESOURCE_VIEW_DESC,
+        D3D11_SUBRESOURCE_DATA,
+        D3D11_TEXTURE2D_DESC,
+        D3D11_USAGE_DEFAULT,
+        ID3D11Device,
+        ID3D11ShaderResourceView,
+        ID3D11Texture2D
+    }
+};
+
+// Upload a tightly packed RGBA8 image into a new texture and create a shader resource view for it
+pub unsafe fn create_rgba_texture(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    data: &[u8]
+) -> windows::core::Result<ID3D11ShaderResourceView> {
+    let row_pitch = ImageFormat::Rgba8.get_row_pitch(width);
+    if data.len() < row_pitch * height as usize {
+        return Err(windows::Win32::Foundation::E_INVALIDARG.into());
+    }
+    create_texture_impl(device, width, height, DXGI_FORMAT_R8G8B8A8_UNORM, row_pitch as u32, data)
+}
+
+// Upload a decoded image, keeping block compressed formats as they are
+pub unsafe fn create_texture(
+    device: &ID3D11Device,
+    image: &CpuImage
+) -> windows::core::Result<ID3D11ShaderResourceView> {
+    create_texture_impl(device, image.get_width(), image.get_height(), 
+        image.get_dxgi_format(), image.get_row_pitch() as u32, image.get_data())
+}
+
+unsafe fn create_texture_impl(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    row_pitch: u32,
+    data: &[u8]
+) -> windows::core::Result<ID3D11ShaderResourceView> {
+    let desc = D3D11_TEXTURE2D_DESC {
+        Width: width,
+        Height: height,
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: format,
+        SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: windows::Win32::Graphics::Direct3D11::D3D11_BIND_SHADER_RESOURCE.0 as u32,
+        ..Default::default()
+    };
+    let sub_resource = D3D11_SUBRESOURCE_DATA {
+        pSysMem: data.as_ptr().cast(),
+        SysMemPitch: row_pitch,
+        SysMemSlicePitch: 0,
+    };
+    let mut texture: Option<ID3D11Texture2D> = None;
+    device.CreateTexture2D(
+        &desc, 
+        Some(&sub_resource),
+        Some(&raw mut texture)
+    )?;
+    let mut srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+        Format: format,
+        ViewDimension: windows::Win32::Graphics::Direct3D::D3D11_SRV_DIMENSION_TEXTURE2D,
+        ..Default::default()
+    };
+    srv_desc.Anonymous.Texture2D.MipLevels = desc.MipLevels;
+    srv_desc.Anonymous.Texture2D.MostDetailedMip = 0;
+    let mut srv: Option<ID3D11ShaderResourceView> = None;
+    device.CreateShaderResourceView(texture.as_ref().map(|v| v.into()), Some(&srv_desc), Some(&raw mut srv))?;
+    Ok(srv.unwrap())
+}
@@ -6,6 +6,7 @@ use windows::{
     core::PCSTR,
     Win32::Graphics::{
         Dxgi::Common::{
+            DXGI_FORMAT,
             DXGI_FORMAT_R32G32_FLOAT,
             DXGI_FORMAT_R8G8B8A8_UNORM
         },
@@ -48,7 +49,8 @@ pub struct GraphicsPipeline {
 impl GraphicsPipeline {
     pub unsafe fn new(
         device: &ID3D12Device, 
-        root: &ID3D12RootSignature
+        root: &ID3D12RootSignature,
+        rtv_format: DXGI_FORMAT
     ) -> windows::core::Result<Self> {
         let mut pipeline = D3D12_GRAPHICS_PIPELINE_STATE_DESC::default();
         pipeline.NodeMask = 1;
@@ -56,7 +58,7 @@ impl GraphicsPipeline {
         pipeline.pRootSignature = ManuallyDrop::new(Some(root.clone()));
         pipeline.SampleMask = u32::MAX;
         pipeline.NumRenderTargets = 1;
-        pipeline.RTVFormats[0] = DXGI_FORMAT_R8G8B8A8_UNORM;
+        pipeline.RTVFormats[0] = rtv_format;
         pipeline.SampleDesc.Count = 1;
         pipeline.Flags = D3D12_PIPELINE_STATE_FLAG_NONE;
         // Create vertex + pixel shader 
@@ -143,4 +145,6 @@ impl GraphicsPipeline {
         let _impl = device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&raw const pipeline)?;
         Ok(Self { _impl })
     }
+
+    pub fn get(&self) -> &ID3D12PipelineState { &self._impl }
 }
\ No newline at end of file
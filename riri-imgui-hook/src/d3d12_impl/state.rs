@@ -1,74 +1,147 @@
-use crate::d3d12_impl::{
-    buffer::{ IndexBuffer, VertexBuffer },
-    font::FontObjects,
-    pipeline::GraphicsPipeline,
-    signature::RootSignature
+use crate::{
+    d3d12_impl::{
+        buffer::{ IndexBuffer, VertexBuffer },
+        font::FontObjects,
+        pipeline::GraphicsPipeline,
+        signature::RootSignature,
+        texture::upload_texture
+    },
+    image::CpuImage,
+    rasterizer,
+    renderer::RendererBackend
 };
-use glam::{ Mat4, Vec4 };
 use imgui::{
+    internal::RawWrapper,
     BackendFlags,
     Context as ImContext,
+    DrawCmd,
+    DrawCmdParams,
     DrawData,
     DrawIdx,
-    DrawVert
+    DrawVert,
+    TextureId
 };
+use riri_mod_tools_rt::logln;
 use std::{
+    any::Any,
+    collections::{ HashMap, HashSet },
     ffi::c_void,
     mem::ManuallyDrop
 };
-use windows::Win32::Graphics::{
-    Direct3D12::{
-        D3D12_COMMAND_LIST_TYPE_DIRECT,
-        D3D12_CPU_DESCRIPTOR_HANDLE,
-        D3D12_DESCRIPTOR_HEAP_DESC,
-        D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-        D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
-        D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-        D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-        D3D12_RANGE,
-        D3D12_RESOURCE_BARRIER,
-        D3D12_RESOURCE_BARRIER_0,
-        D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-        D3D12_RESOURCE_BARRIER_FLAG_NONE,
-        D3D12_RESOURCE_STATE_PRESENT,
-        D3D12_RESOURCE_STATE_RENDER_TARGET,
-        D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-        D3D12_RESOURCE_TRANSITION_BARRIER,
-        D3D12_VIEWPORT,
-        ID3D12CommandAllocator,
-        ID3D12CommandQueue,
-        ID3D12DescriptorHeap,
-        ID3D12Device,
-        ID3D12GraphicsCommandList,
-        ID3D12Resource,
-    },
-    Dxgi::IDXGISwapChain1
+use windows::{
+    core::Interface,
+    Win32::{
+        Foundation::{ CloseHandle, E_OUTOFMEMORY, HANDLE, RECT },
+        Graphics::{
+            Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            Direct3D12::{
+                D3D12_COMMAND_LIST_TYPE_DIRECT,
+                D3D12_CPU_DESCRIPTOR_HANDLE,
+                D3D12_DESCRIPTOR_HEAP_DESC,
+                D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+                D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+                D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+                D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
+                D3D12_FENCE_FLAG_NONE,
+                D3D12_GPU_DESCRIPTOR_HANDLE,
+                D3D12_INDEX_BUFFER_VIEW,
+                D3D12_RANGE,
+                D3D12_RESOURCE_BARRIER,
+                D3D12_RESOURCE_BARRIER_0,
+                D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                D3D12_RESOURCE_STATES,
+                D3D12_RESOURCE_STATE_PRESENT,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                D3D12_RESOURCE_TRANSITION_BARRIER,
+                D3D12_VERTEX_BUFFER_VIEW,
+                D3D12_VIEWPORT,
+                ID3D12CommandAllocator,
+                ID3D12CommandQueue,
+                ID3D12DescriptorHeap,
+                ID3D12Device,
+                ID3D12Fence,
+                ID3D12GraphicsCommandList,
+                ID3D12Resource,
+            },
+            Dxgi::{
+                Common::{ DXGI_FORMAT, DXGI_FORMAT_R16_UINT, DXGI_FORMAT_R32_UINT },
+                IDXGISwapChain1,
+                IDXGISwapChain3
+            }
+        },
+        System::Threading::{
+            CreateEventA,
+            INFINITE,
+            WaitForSingleObject
+        }
+    }
 };
-use riri_mod_tools_rt::logln;
 
-pub static DLL_NAMES: [&'static str; 1] = [ "d3d12.dll\0" ];
+pub use crate::detect::D3D12_DLL_NAMES as DLL_NAMES;
+
+// Number of descriptors in our shader visible heap. The font atlas uses the first slot, the rest
+// are handed out to textures created through create_texture
+pub const SRV_HEAP_SIZE: u32 = 256;
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct FrameContext {
     alloc: Option<ID3D12CommandAllocator>,
     resrc: Option<ID3D12Resource>,
-    desc_handle: D3D12_CPU_DESCRIPTOR_HANDLE
+    desc_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    vertex_buffer: Option<VertexBuffer>,
+    index_buffer: Option<IndexBuffer>,
+    // Value of the shared fence once the GPU has finished with this frame's commands
+    fence_value: u64
 }
 impl Default for FrameContext {
     fn default() -> Self {
         Self {
             alloc: None,
             resrc: None,
-            desc_handle: D3D12_CPU_DESCRIPTOR_HANDLE::default()
+            desc_handle: D3D12_CPU_DESCRIPTOR_HANDLE::default(),
+            vertex_buffer: None,
+            index_buffer: None,
+            fence_value: 0
         }
     }
 }
 
+// Transition a back buffer between states. The resource pointer is copied without an AddRef
+// since the barrier is only used for the duration of ResourceBarrier
+fn transition_barrier(
+    resource: &Option<ID3D12Resource>,
+    before: D3D12_RESOURCE_STATES,
+    after: D3D12_RESOURCE_STATES
+) -> D3D12_RESOURCE_BARRIER {
+    D3D12_RESOURCE_BARRIER {
+        Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+        Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        Anonymous: D3D12_RESOURCE_BARRIER_0 {
+            Transition: ManuallyDrop::new(D3D12_RESOURCE_TRANSITION_BARRIER {
+                pResource: unsafe { std::mem::transmute_copy(resource) },
+                Subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                StateBefore: before,
+                StateAfter: after
+            })
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+struct UserTexture {
+    resource: ID3D12Resource,
+    slot: u32
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct D3D12Hook {
     device: ID3D12Device,
+    swapchain: IDXGISwapChain3,
     frames: Vec<FrameContext>,
     frame_index: usize,
     desc_heap: ID3D12DescriptorHeap,
@@ -77,10 +150,18 @@ pub struct D3D12Hook {
 
     root_signature: RootSignature,
     pipeline: GraphicsPipeline,
+    rtv_format: DXGI_FORMAT,
     font_objects: FontObjects,
+    // Texture IDs are the GPU descriptor handle of the texture's SRV
+    textures: HashMap<usize, UserTexture>,
+    // Unknown texture IDs that have already been logged
+    reported_textures: HashSet<usize>,
+    free_slots: Vec<u32>,
+    srv_desc_size: u32,
     command_queue: ID3D12CommandQueue,
-    vertex_buffer: VertexBuffer,
-    index_buffer: IndexBuffer,
+    fence: ID3D12Fence,
+    fence_event: HANDLE,
+    fence_value: u64
 }
 
 impl D3D12Hook {
@@ -92,33 +173,64 @@ impl D3D12Hook {
         // initialize resources
         let device = swapchain.GetDevice::<ID3D12Device>()?;
         let desc = swapchain.GetDesc1()?;
+        let swapchain = swapchain.cast::<IDXGISwapChain3>()?;
 
-        // create frames
-        let mut frames = Vec::with_capacity(desc.BufferCount as usize);
-        for _ in 0..desc.BufferCount {
-            frames.push(FrameContext::default())
-        }
         // create descriptor heap
         let desc_heap_param = D3D12_DESCRIPTOR_HEAP_DESC {
             Type: D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-            NumDescriptors: desc.BufferCount,
+            NumDescriptors: SRV_HEAP_SIZE,
             Flags: D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
             NodeMask: 0
         };
         let desc_heap = device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&raw const desc_heap_param)?;
-        // make command allocator
-        let cmd_alloc = device.CreateCommandAllocator::<ID3D12CommandAllocator>(D3D12_COMMAND_LIST_TYPE_DIRECT)?;
-        for frame in &mut frames {
-            frame.alloc = Some(cmd_alloc.clone());
-        }
-        // make command queue
+        // create frames
+        let mut frames = Vec::with_capacity(desc.BufferCount as usize);
+        let bb_desc_heap = Self::create_frames(&device, &swapchain, &mut frames, desc.BufferCount as usize)?;
+        let fence = device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAG_NONE)?;
+        let fence_event = CreateEventA(None, false, false, None)?;
 
         // make command list
-        let cmd_list = device.CreateCommandList(0, D3D12_COMMAND_LIST_TYPE_DIRECT, Some(&cmd_alloc), None)?;
+        let cmd_list = device.CreateCommandList::<_, _, ID3D12GraphicsCommandList>(0, D3D12_COMMAND_LIST_TYPE_DIRECT, frames[0].alloc.as_ref(), None)?;
+        // Command lists are created in the recording state, close it so it can be reset on the first frame
+        cmd_list.Close()?;
+
+        // ImGui_ImplDX12_Init
+        let renderer_name = format!("riri-imgui-hook-d3d12");
+        ctx.set_renderer_name(Some(renderer_name));
+        let io = ctx.io_mut();
+        io.backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
+        // ImGui_ImplDX12_CreateDeviceObjects
+        let root_signature = RootSignature::new(&device)?;
+        let pipeline = GraphicsPipeline::new(&device, root_signature.get(), desc.Format)?;
+        let font_objects = FontObjects::new(ctx.fonts(), &desc_heap, &device, &command_queue)?;
+        let srv_desc_size = device.GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
+        Ok(Self { 
+            device, swapchain, frames, frame_index: 0, 
+            desc_heap, cmd_list, bb_desc_heap,
+            root_signature, pipeline, rtv_format: desc.Format, font_objects,
+            textures: HashMap::new(), reported_textures: HashSet::new(), free_slots: (1..SRV_HEAP_SIZE).rev().collect(), srv_desc_size,
+            command_queue, fence, fence_event, fence_value: 0
+        })
+    }
+
+    // Resize the frame list to match the swapchain's buffer count, then create the back buffer
+    // description heap and a render target view for each back buffer
+    unsafe fn create_frames(
+        device: &ID3D12Device,
+        swapchain: &IDXGISwapChain3,
+        frames: &mut Vec<FrameContext>,
+        buffer_count: usize
+    ) -> windows::core::Result<ID3D12DescriptorHeap> {
+        frames.resize_with(buffer_count, FrameContext::default);
+        // make command allocators. Each frame in flight needs it's own allocator since they
+        // can't be reset until the GPU has finished executing the commands recorded into them
+        for frame in frames.iter_mut().filter(|f| f.alloc.is_none()) {
+            frame.alloc = Some(device.CreateCommandAllocator::<ID3D12CommandAllocator>(D3D12_COMMAND_LIST_TYPE_DIRECT)?);
+        }
         // make back buffer description heap
         let desc_heap_param = D3D12_DESCRIPTOR_HEAP_DESC {
             Type: D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-            NumDescriptors: desc.BufferCount,
+            NumDescriptors: buffer_count as u32,
             Flags: D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
             NodeMask: 1
         };
@@ -133,101 +245,63 @@ impl D3D12Hook {
             frame.resrc = Some(resrc);
             rtv_handle.ptr += rtv_desc_size as usize;
         }
-
-        // ImGui_ImplDX12_Init
-        let renderer_name = format!("riri-imgui-hook-d3d12");
-        ctx.set_renderer_name(Some(renderer_name));
-        let io = ctx.io_mut();
-        io.backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
-        // ImGui_ImplDX12_CreateDeviceObjects
-        let root_signature = RootSignature::new(&device)?;
-        let pipeline = GraphicsPipeline::new(&device, root_signature.get())?;
-        let font_objects = FontObjects::new(ctx.fonts(), &desc_heap, &device, &command_queue)?;
-        let vertex_buffer = VertexBuffer::new(&device, 0)?;
-        let index_buffer = IndexBuffer::new(&device, 0)?;
-        Ok(Self { 
-            device, frames, frame_index: 0, 
-            desc_heap, cmd_list, bb_desc_heap,
-            root_signature, pipeline, font_objects,
-            command_queue, vertex_buffer, index_buffer
-        })
+        Ok(bb_desc_heap)
     }
 
-    pub fn prepare(&mut self) -> windows::core::Result<()> {
-        let frame_len = self.frames.len();
-        let curr_frame = &mut self.frames[self.frame_index % frame_len];
-        unsafe {
-            curr_frame.alloc.as_ref().unwrap().Reset()?;
-            let barrier = D3D12_RESOURCE_BARRIER {
-                Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-                Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                Anonymous: D3D12_RESOURCE_BARRIER_0 {
-                    Transition: ManuallyDrop::new(D3D12_RESOURCE_TRANSITION_BARRIER {
-                        pResource: ManuallyDrop::new(curr_frame.resrc.clone()),
-                        Subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-                        StateBefore: D3D12_RESOURCE_STATE_PRESENT,
-                        StateAfter: D3D12_RESOURCE_STATE_RENDER_TARGET
-                    })
-                }
-            };
-            self.cmd_list.ResourceBarrier(&[barrier]);
-            self.cmd_list.OMSetRenderTargets(1, Some(&raw const curr_frame.desc_handle), false.into(), None);
-            self.cmd_list.SetDescriptorHeaps(&[Some(self.desc_heap.clone())]);
+    // Block until the GPU has reached the given value on our fence
+    unsafe fn wait_for_fence(&self, value: u64) -> windows::core::Result<()> {
+        if value != 0 && self.fence.GetCompletedValue() < value {
+            self.fence.SetEventOnCompletion(value, self.fence_event)?;
+            WaitForSingleObject(self.fence_event, INFINITE);
         }
         Ok(())
     }
 
-    // ImGui_ImplDX12_RenderDrawData
-    pub fn render(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
-        logln!(Verbose, "TODO: Render for D3D12!");
-        if draw_data.display_size[0] <= 0.0 
-        || draw_data.display_size[1] <= 0.0 {
-            return Ok(());
-        }
-        self.frame_index += 1;
-        let frame_len = self.frames.len();
-        let curr_frame = &mut self.frames[self.frame_index % frame_len];
-        unsafe {
-            curr_frame.alloc.as_ref().unwrap().Reset()?;
-            let barrier = D3D12_RESOURCE_BARRIER {
-                Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-                Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                Anonymous: D3D12_RESOURCE_BARRIER_0 {
-                    Transition: ManuallyDrop::new(D3D12_RESOURCE_TRANSITION_BARRIER {
-                        pResource: ManuallyDrop::new(curr_frame.resrc.clone()),
-                        Subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-                        StateBefore: D3D12_RESOURCE_STATE_PRESENT,
-                        StateAfter: D3D12_RESOURCE_STATE_RENDER_TARGET
-                    })
-                }
-            };
-            self.cmd_list.ResourceBarrier(&[barrier]);
-            self.cmd_list.OMSetRenderTargets(1, Some(&raw const curr_frame.desc_handle), false.into(), None);
-            self.cmd_list.SetDescriptorHeaps(&[Some(self.desc_heap.clone())]);
+    // Wait until every frame in flight has finished executing
+    pub unsafe fn wait_for_gpu(&mut self) -> windows::core::Result<()> {
+        self.fence_value += 1;
+        self.command_queue.Signal(&self.fence, self.fence_value)?;
+        self.wait_for_fence(self.fence_value)
+    }
 
-        }
-        // Create and grow vertex/index buffers if needed
-        unsafe {
-            if self.vertex_buffer.len() < draw_data.total_vtx_count as usize {
-                self.vertex_buffer = VertexBuffer::new(&self.device, draw_data.total_vtx_count as usize)?;
-            }
-            if self.index_buffer.len() < draw_data.total_idx_count as usize {
-                self.index_buffer = IndexBuffer::new(&self.device, draw_data.total_idx_count as usize)?;
-            }
-        }
-        // Render command lists
-        // (Because we merged all buffers into a single one, we maintain our own offset into them)
+    // Begin recording into the command list for the current back buffer. The GPU has to be done
+    // with the last set of commands recorded for this frame.
+    unsafe fn prepare(&mut self) -> windows::core::Result<()> {
+        let curr_frame = &self.frames[self.frame_index];
+        let alloc = curr_frame.alloc.as_ref().unwrap();
+        alloc.Reset()?;
+        self.cmd_list.Reset(alloc, None)?;
+        let barrier = transition_barrier(&curr_frame.resrc, D3D12_RESOURCE_STATE_PRESENT, D3D12_RESOURCE_STATE_RENDER_TARGET);
+        self.cmd_list.ResourceBarrier(&[barrier]);
+        self.cmd_list.OMSetRenderTargets(1, Some(&raw const curr_frame.desc_handle), false.into(), None);
+        self.cmd_list.SetDescriptorHeaps(&[Some(self.desc_heap.clone())]);
+        Ok(())
+    }
+
+    // Transition the back buffer back to present and submit our commands to the game's queue
+    unsafe fn submit(&mut self) -> windows::core::Result<()> {
+        let curr_frame = &mut self.frames[self.frame_index];
+        let barrier = transition_barrier(&curr_frame.resrc, D3D12_RESOURCE_STATE_RENDER_TARGET, D3D12_RESOURCE_STATE_PRESENT);
+        self.cmd_list.ResourceBarrier(&[barrier]);
+        self.cmd_list.Close()?;
+        self.command_queue.ExecuteCommandLists(&[Some(self.cmd_list.clone().into())]);
+        self.fence_value += 1;
+        self.command_queue.Signal(&self.fence, self.fence_value)?;
+        curr_frame.fence_value = self.fence_value;
         Ok(())
     }
 
     pub unsafe fn upload_buffer_data(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
         // Upload vertex/index data into a single contiguous GPU buffer
+        let curr_frame = &self.frames[self.frame_index];
+        let vertex_buffer = curr_frame.vertex_buffer.as_ref().unwrap();
+        let index_buffer = curr_frame.index_buffer.as_ref().unwrap();
         let mut vtx_resource: *mut c_void = std::ptr::null_mut();
         let mut idx_resource: *mut c_void = std::ptr::null_mut();
         let range = D3D12_RANGE { Begin: 0, End: 0 };
         // During Map() we specify a null read range (as per DX12 API, this is informational and for tooling only)
-        self.vertex_buffer.get_resource().Map(0, Some(&raw const range), Some(&raw mut vtx_resource))?;
-        self.index_buffer.get_resource().Map(0, Some(&raw const range), Some(&raw mut idx_resource))?;
+        vertex_buffer.get_resource().Map(0, Some(&raw const range), Some(&raw mut vtx_resource))?;
+        index_buffer.get_resource().Map(0, Some(&raw const range), Some(&raw mut idx_resource))?;
         let mut vtx_dst = std::slice::from_raw_parts_mut(
             vtx_resource as *mut DrawVert,
             draw_data.total_vtx_count as usize,
@@ -245,27 +319,15 @@ impl D3D12Hook {
             idx_dst = &mut idx_dst[ibuf.len()..];
         }
         // During Unmap() we specify the written range (as per DX12 API, this is informational and for tooling only)
-        self.vertex_buffer.get_resource().Unmap(0,Some(&raw const range));
-        self.index_buffer.get_resource().Unmap(0,Some(&raw const range));
+        vertex_buffer.get_resource().Unmap(0,Some(&raw const range));
+        index_buffer.get_resource().Unmap(0,Some(&raw const range));
         Ok(())
     }
 
-    #[allow(unused_variables)]
     pub unsafe fn setup_render_state(&self, draw_data: &DrawData) {
-        // Setup render state structure (for callbacks and custom texture bindings)
-
         // Setup orthographic projection matrix into our constant buffer
         // Our visible imgui space lies from draw_data->DisplayPos (top left) to draw_data->DisplayPos+data_data->DisplaySize (bottom right).
-        let l = draw_data.display_pos[0];
-        let r = draw_data.display_pos[0] + draw_data.display_size[0];
-        let t = draw_data.display_pos[1];
-        let b = draw_data.display_pos[1] + draw_data.display_size[1];
-        let mvp = Mat4::from_cols(
-            Vec4::new(2.0 / (r - l), 0., 0., 0.,),
-            Vec4::new(0.0, 2.0 / (t - b), 0.0, 0.0),
-            Vec4::new(0.0, 0.0, 0.5, 0.0),
-            Vec4::new((r + l) / (l - r), (t + b) / (b - t), 0.5, 1.0),
-        );
+        let mvp = rasterizer::get_projection_matrix(draw_data.display_pos, draw_data.display_size);
         // Setup viewport
         let vp = D3D12_VIEWPORT {
             TopLeftX: 0.0,
@@ -275,8 +337,217 @@ impl D3D12Hook {
             MinDepth: 0.0,
             MaxDepth: 1.0,
         };
+        let ctx = &self.cmd_list;
+        let curr_frame = &self.frames[self.frame_index];
+        let vertex_buffer = curr_frame.vertex_buffer.as_ref().unwrap();
+        let index_buffer = curr_frame.index_buffer.as_ref().unwrap();
+        ctx.RSSetViewports(&[vp]);
+        // Bind shader and vertex buffers
+        let stride = size_of::<DrawVert>() as u32;
+        let vbv = D3D12_VERTEX_BUFFER_VIEW {
+            BufferLocation: vertex_buffer.get_resource().GetGPUVirtualAddress(),
+            SizeInBytes: vertex_buffer.len() as u32 * stride,
+            StrideInBytes: stride
+        };
+        ctx.IASetVertexBuffers(0, Some(&[vbv]));
+        let ibv = D3D12_INDEX_BUFFER_VIEW {
+            BufferLocation: index_buffer.get_resource().GetGPUVirtualAddress(),
+            SizeInBytes: (index_buffer.len() * size_of::<DrawIdx>()) as u32,
+            Format: if size_of::<DrawIdx>() == 2 { DXGI_FORMAT_R16_UINT } else { DXGI_FORMAT_R32_UINT }
+        };
+        ctx.IASetIndexBuffer(Some(&raw const ibv));
+        ctx.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        ctx.SetPipelineState(self.pipeline.get());
+        ctx.SetGraphicsRootSignature(self.root_signature.get());
+        ctx.SetGraphicsRoot32BitConstants(0, 16, (&raw const mvp).cast::<c_void>(), 0);
+        // Setup blend factor
+        let blend_factor = [0.0; 4];
+        ctx.OMSetBlendFactor(Some(&blend_factor));
+    }
+
+    unsafe fn render_impl(&mut self, draw_data: &DrawData) {
+        let clip_off = draw_data.display_pos;
+        let clip_scale = draw_data.framebuffer_scale;
+        // (Because we merged all buffers into a single one, we maintain our own offset into them)
+        let mut global_vtx_offset = 0;
+        let mut global_idx_offset = 0;
+        let ctx = &self.cmd_list;
+        let font_tex_id = self.desc_heap.GetGPUDescriptorHandleForHeapStart().ptr as usize;
+        for draw_list in draw_data.draw_lists() {
+            for cmd in draw_list.commands() {
+                match cmd {
+                    DrawCmd::Elements {
+                        count,
+                        cmd_params: DrawCmdParams { clip_rect, texture_id, vtx_offset, idx_offset },
+                    } => {
+                        // Project scissor/clipping rectangles into framebuffer space
+                        let clip_min = [(clip_rect[0] - clip_off[0]) * clip_scale[0], (clip_rect[1] - clip_off[1]) * clip_scale[1]];
+                        let clip_max = [(clip_rect[2] - clip_off[0]) * clip_scale[0], (clip_rect[3] - clip_off[1]) * clip_scale[1]];
+                        if clip_max[0] <= clip_min[0] || clip_max[1] <= clip_min[1] {
+                            continue;
+                        }
+                        let r = RECT {
+                            left: clip_min[0] as i32,
+                            top: clip_min[1] as i32,
+                            right: clip_max[0] as i32,
+                            bottom: clip_max[1] as i32,
+                        };
+                        // Texture ids are GPU descriptor handles in our shader visible heap. Skip
+                        // anything that isn't ours, logging each ID once like D3D11Hook does
+                        if texture_id.id() != font_tex_id && !self.textures.contains_key(&texture_id.id()) {
+                            if self.reported_textures.insert(texture_id.id()) {
+                                logln!(Warning, "Texture ID 0x{:x} is not registered with the renderer", texture_id.id());
+                            }
+                            continue;
+                        }
+                        let texture_handle = D3D12_GPU_DESCRIPTOR_HANDLE { ptr: texture_id.id() as u64 };
+                        ctx.SetGraphicsRootDescriptorTable(1, texture_handle);
+                        ctx.RSSetScissorRects(&[r]);
+                        ctx.DrawIndexedInstanced(
+                            count as u32,
+                            1,
+                            (idx_offset + global_idx_offset) as u32,
+                            (vtx_offset + global_vtx_offset) as i32,
+                            0
+                        );
+                    },
+                    DrawCmd::ResetRenderState => self.setup_render_state(draw_data),
+                    DrawCmd::RawCallback { callback, raw_cmd } => {
+                        callback(draw_list.raw(), raw_cmd)
+                    },
+                }
+            }
+            global_idx_offset += draw_list.idx_buffer().len();
+            global_vtx_offset += draw_list.vtx_buffer().len();
+        }
+    }
+}
+
+impl RendererBackend for D3D12Hook {
+    // Everything is created in new, since the font texture needs our descriptor heap and the
+    // game's command queue
+
+    // ImGui_ImplDX12_RenderDrawData
+    fn render(&mut self, draw_data: &DrawData) -> windows::core::Result<()> {
+        if draw_data.display_size[0] <= 0.0 
+        || draw_data.display_size[1] <= 0.0 {
+            return Ok(());
+        }
+        // Back buffers are released while the swapchain is being resized
+        if self.frames.iter().any(|f| f.resrc.is_none()) {
+            return Ok(());
+        }
+        unsafe {
+            self.frame_index = self.swapchain.GetCurrentBackBufferIndex() as usize % self.frames.len();
+            // Wait for the GPU to finish with this frame before touching any of it's resources
+            self.wait_for_fence(self.frames[self.frame_index].fence_value)?;
+            // Create and grow vertex/index buffers if needed. Anything that can fail is done
+            // before the command list is opened, so an error can't leave it open and unsubmitted
+            let curr_frame = &mut self.frames[self.frame_index];
+            if curr_frame.vertex_buffer.as_ref().map_or(true, |v| v.len() < draw_data.total_vtx_count as usize) {
+                curr_frame.vertex_buffer = Some(VertexBuffer::new(&self.device, draw_data.total_vtx_count as usize)?);
+            }
+            if curr_frame.index_buffer.as_ref().map_or(true, |v| v.len() < draw_data.total_idx_count as usize) {
+                curr_frame.index_buffer = Some(IndexBuffer::new(&self.device, draw_data.total_idx_count as usize)?);
+            }
+            self.upload_buffer_data(draw_data)?;
+            self.prepare()?;
+            self.setup_render_state(draw_data);
+            self.render_impl(draw_data);
+            self.submit()
+        }
+    }
+
+    // Called before IDXGISwapChain::ResizeBuffers. The GPU must be done using the back buffers
+    // before they're released.
+    fn before_resize(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> {
+        unsafe { self.wait_for_gpu()?; }
+        for frame in &mut self.frames {
+            frame.resrc = None;
+        }
+        Ok(())
+    }
+
+    // The buffer count and format may have changed
+    fn after_resize(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> {
+        unsafe {
+            let desc = self.swapchain.GetDesc1()?;
+            self.bb_desc_heap = Self::create_frames(&self.device, &self.swapchain, &mut self.frames, desc.BufferCount as usize)?;
+            self.frame_index = 0;
+            if desc.Format != self.rtv_format {
+                self.pipeline = GraphicsPipeline::new(&self.device, self.root_signature.get(), desc.Format)?;
+                self.rtv_format = desc.Format;
+            }
+        }
+        Ok(())
+    }
+
+    // Upload a newly built font atlas into the first slot of our descriptor heap. The GPU has to be
+    // finished with every frame using the old texture's descriptor before it's overwritten.
+    fn rebuild_font_texture(&mut self, ctx: &mut ImContext) -> windows::core::Result<()> {
+        unsafe {
+            self.wait_for_gpu()?;
+            self.font_objects = FontObjects::new(ctx.fonts(), &self.desc_heap, &self.device, &self.command_queue)?;
+        }
+        Ok(())
     }
 
-    pub fn invalidate_device_objects(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> { Ok(()) }
-    pub unsafe fn create_device_objects(&mut self, _ctx: &mut ImContext) -> windows::core::Result<()> { Ok(()) }
+    // Upload a decoded image into a new texture and give it a descriptor in our heap
+    fn create_texture(&mut self, image: &CpuImage) -> windows::core::Result<TextureId> {
+        let slot = self.free_slots.pop()
+            .ok_or_else(|| windows::core::Error::new(E_OUTOFMEMORY,
+                format!("All {} texture descriptors are in use", SRV_HEAP_SIZE - 1)))?;
+        let mut cpu_handle = unsafe { self.desc_heap.GetCPUDescriptorHandleForHeapStart() };
+        let mut gpu_handle = unsafe { self.desc_heap.GetGPUDescriptorHandleForHeapStart() };
+        cpu_handle.ptr += (slot * self.srv_desc_size) as usize;
+        gpu_handle.ptr += (slot * self.srv_desc_size) as u64;
+        match unsafe { upload_texture(&self.device, &self.command_queue, cpu_handle, image) } {
+            Ok(resource) => {
+                self.textures.insert(gpu_handle.ptr as usize, UserTexture { resource, slot });
+                Ok(TextureId::new(gpu_handle.ptr as usize))
+            },
+            Err(e) => {
+                self.free_slots.push(slot);
+                Err(e)
+            }
+        }
+    }
+
+    fn destroy_texture(&mut self, id: TextureId) -> bool {
+        if !self.textures.contains_key(&id.id()) {
+            return false;
+        }
+        // Frames in flight may still be sampling from it
+        if let Err(e) = unsafe { self.wait_for_gpu() } {
+            logln!(Error, "Error waiting for GPU before releasing texture: {}", e);
+        }
+        let texture = self.textures.remove(&id.id()).unwrap();
+        self.free_slots.push(texture.slot);
+        true
+    }
+
+    // ImGui_ImplDX12_Shutdown. Waits for the GPU so nothing is released while a command list
+    // still uses it.
+    fn shutdown(&mut self, ctx: &mut ImContext) -> windows::core::Result<()> {
+        unsafe { self.wait_for_gpu()?; }
+        self.textures.clear();
+        self.free_slots.clear();
+        for frame in &mut self.frames {
+            frame.resrc = None;
+        }
+        ctx.set_renderer_name(None);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+impl Drop for D3D12Hook {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.wait_for_gpu();
+            let _ = CloseHandle(self.fence_event);
+        }
+    }
 }
\ No newline at end of file
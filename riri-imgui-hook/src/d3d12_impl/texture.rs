@@ -0,0 +1,237 @@
+use crate::image::{ CpuImage, ImageFormat };
+use std::{
+    ffi::c_void,
+    mem::ManuallyDrop
+};
+use windows::Win32::{
+    Foundation::CloseHandle,
+    Graphics::{
+        Dxgi::Common::{
+            DXGI_FORMAT,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            DXGI_FORMAT_UNKNOWN,
+        },
+        Direct3D12::{
+            D3D12_COMMAND_LIST_TYPE_DIRECT,
+            D3D12_CPU_DESCRIPTOR_HANDLE,
+            D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+            D3D12_FENCE_FLAG_NONE,
+            D3D12_HEAP_FLAG_NONE,
+            D3D12_HEAP_PROPERTIES,
+            D3D12_HEAP_TYPE_DEFAULT,
+            D3D12_HEAP_TYPE_UPLOAD,
+            D3D12_MEMORY_POOL_UNKNOWN,
+            D3D12_RANGE,
+            D3D12_RESOURCE_BARRIER,
+            D3D12_RESOURCE_BARRIER_0,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+            D3D12_RESOURCE_DESC,
+            D3D12_RESOURCE_DIMENSION_BUFFER,
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            D3D12_RESOURCE_FLAG_NONE,
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            D3D12_RESOURCE_STATE_GENERIC_READ,
+            D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            D3D12_RESOURCE_TRANSITION_BARRIER,
+            D3D12_SHADER_RESOURCE_VIEW_DESC,
+            D3D12_SRV_DIMENSION_TEXTURE2D,
+            D3D12_TEXTURE_COPY_LOCATION,
+            D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            D3D12_TEXTURE_DATA_PITCH_ALIGNMENT,
+            ID3D12CommandAllocator,
+            ID3D12CommandQueue,
+            ID3D12Device,
+            ID3D12Fence,
+            ID3D12GraphicsCommandList,
+            ID3D12Resource
+        }
+    },
+    System::Threading::{
+        CreateEventA,
+        INFINITE,
+        WaitForSingleObject
+    }
+};
+
+// Describes the pixel data to copy into a new texture
+struct TextureSource<'a> {
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    row_pitch: usize,
+    row_count: usize,
+    data: &'a [u8]
+}
+
+// Upload a tightly packed RGBA8 image into a new texture and create a shader resource view for
+// it at cpu_handle. This blocks until the copy has finished on the GPU.
+pub unsafe fn upload_rgba_texture(
+    device: &ID3D12Device,
+    command_queue: &ID3D12CommandQueue,
+    cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    width: u32,
+    height: u32,
+    data: &[u8]
+) -> windows::core::Result<ID3D12Resource> {
+    let row_pitch = ImageFormat::Rgba8.get_row_pitch(width);
+    if data.len() < row_pitch * height as usize {
+        return Err(windows::Win32::Foundation::E_INVALIDARG.into());
+    }
+    upload_texture_impl(device, command_queue, cpu_handle, TextureSource {
+        width, height, format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        row_pitch, row_count: height as usize, data
+    })
+}
+
+// Upload a decoded image, keeping block compressed formats as they are
+pub unsafe fn upload_texture(
+    device: &ID3D12Device,
+    command_queue: &ID3D12CommandQueue,
+    cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    image: &CpuImage
+) -> windows::core::Result<ID3D12Resource> {
+    upload_texture_impl(device, command_queue, cpu_handle, TextureSource {
+        width: image.get_width(),
+        height: image.get_height(),
+        format: image.get_dxgi_format(),
+        row_pitch: image.get_row_pitch(),
+        row_count: image.get_row_count(),
+        data: image.get_data()
+    })
+}
+
+unsafe fn upload_texture_impl(
+    device: &ID3D12Device,
+    command_queue: &ID3D12CommandQueue,
+    cpu_handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    src: TextureSource
+) -> windows::core::Result<ID3D12Resource> {
+    let mut props = D3D12_HEAP_PROPERTIES::default();
+    props.Type = D3D12_HEAP_TYPE_DEFAULT;
+    props.CPUPageProperty = D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
+    props.MemoryPoolPreference = D3D12_MEMORY_POOL_UNKNOWN;
+
+    let mut desc = D3D12_RESOURCE_DESC::default();
+    desc.Dimension = D3D12_RESOURCE_DIMENSION_TEXTURE2D;
+    desc.Alignment = 0;
+    desc.Width = src.width as u64;
+    desc.Height = src.height;
+    desc.DepthOrArraySize = 1;
+    desc.MipLevels = 1;
+    desc.Format = src.format;
+    desc.SampleDesc.Count = 1;
+    desc.SampleDesc.Quality = 0;
+    desc.Layout = D3D12_TEXTURE_LAYOUT_UNKNOWN;
+    desc.Flags = D3D12_RESOURCE_FLAG_NONE;
+
+    let mut texture: Option<ID3D12Resource> = None;
+    device.CreateCommittedResource::<ID3D12Resource>(
+        &raw const props,
+        D3D12_HEAP_FLAG_NONE,
+        &raw const desc,
+        D3D12_RESOURCE_STATE_COPY_DEST,
+        None, &raw mut texture)?;
+    let texture = texture.unwrap();
+
+    let upload_pitch = (src.row_pitch as u32 + D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1)
+        & !(D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1);
+    let upload_size = src.row_count as u32 * upload_pitch;
+    desc.Dimension = D3D12_RESOURCE_DIMENSION_BUFFER;
+    desc.Alignment = 0;
+    desc.Width = upload_size as u64;
+    desc.Height = 1;
+    desc.DepthOrArraySize = 1;
+    desc.MipLevels = 1;
+    desc.Format = DXGI_FORMAT_UNKNOWN;
+    desc.SampleDesc.Count = 1;
+    desc.SampleDesc.Quality = 0;
+    desc.Layout = D3D12_TEXTURE_LAYOUT_ROW_MAJOR;
+    desc.Flags = D3D12_RESOURCE_FLAG_NONE;
+
+    props.Type = D3D12_HEAP_TYPE_UPLOAD;
+    props.CPUPageProperty = D3D12_CPU_PAGE_PROPERTY_UNKNOWN;
+    props.MemoryPoolPreference = D3D12_MEMORY_POOL_UNKNOWN;
+
+    let mut upload_buffer: Option<ID3D12Resource> = None;
+    device.CreateCommittedResource::<ID3D12Resource>(
+        &raw const props,
+        D3D12_HEAP_FLAG_NONE,
+        &raw const desc,
+        D3D12_RESOURCE_STATE_GENERIC_READ,
+        None, &raw mut upload_buffer)?;
+    let upload_buffer = upload_buffer.unwrap();
+
+    let mut mapped: *mut c_void = std::ptr::null_mut();
+    let range = D3D12_RANGE {
+        Begin: 0, End: upload_size as usize
+    };
+    upload_buffer.Map(0, Some(&raw const range), Some(&raw mut mapped))?;
+    for y in 0..src.row_count {
+        std::ptr::copy_nonoverlapping(
+            src.data.as_ptr().add(y * src.row_pitch),
+            (mapped as *mut u8).add(y * upload_pitch as usize),
+            src.row_pitch
+        );
+    }
+    upload_buffer.Unmap(0, Some(&raw const range));
+
+    let mut src_location = D3D12_TEXTURE_COPY_LOCATION::default();
+    let mut dst_location = D3D12_TEXTURE_COPY_LOCATION::default();
+
+    // Resource pointers are copied without an AddRef, both resources outlive the copy
+    src_location.pResource = std::mem::transmute_copy(&upload_buffer);
+    src_location.Type = D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT;
+    src_location.Anonymous.PlacedFootprint.Footprint.Format = src.format;
+    // CpuImage makes sure block compressed images are whole 4x4 blocks, which the footprint and
+    // the texture both need to be
+    src_location.Anonymous.PlacedFootprint.Footprint.Width = src.width;
+    src_location.Anonymous.PlacedFootprint.Footprint.Height = src.height;
+    src_location.Anonymous.PlacedFootprint.Footprint.Depth = 1;
+    src_location.Anonymous.PlacedFootprint.Footprint.RowPitch = upload_pitch;
+
+    dst_location.pResource = std::mem::transmute_copy(&texture);
+    dst_location.Type = D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX;
+    dst_location.Anonymous.SubresourceIndex = 0;
+
+    let barrier = D3D12_RESOURCE_BARRIER {
+        Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+        Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+        Anonymous: D3D12_RESOURCE_BARRIER_0 {
+            Transition: ManuallyDrop::new(D3D12_RESOURCE_TRANSITION_BARRIER {
+                pResource: std::mem::transmute_copy(&texture),
+                Subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                StateBefore: D3D12_RESOURCE_STATE_COPY_DEST,
+                StateAfter: D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE
+            })
+        }
+    };
+
+    let fence = device.CreateFence::<ID3D12Fence>(0, D3D12_FENCE_FLAG_NONE)?;
+    let event = CreateEventA(None, false, false, None)?;
+    let cmd_alloc = device.CreateCommandAllocator::<ID3D12CommandAllocator>(D3D12_COMMAND_LIST_TYPE_DIRECT)?;
+    let cmd_list = device.CreateCommandList::<_, _, ID3D12GraphicsCommandList>(0, D3D12_COMMAND_LIST_TYPE_DIRECT, Some(&cmd_alloc), None)?;
+    cmd_list.CopyTextureRegion(&raw const dst_location, 0, 0, 0, &raw const src_location, None);
+    cmd_list.ResourceBarrier(&[barrier]);
+    cmd_list.Close()?;
+    command_queue.ExecuteCommandLists(&[Some(cmd_list.clone().into())]);
+    command_queue.Signal(Some(&fence), 1)?;
+    fence.SetEventOnCompletion(1, event)?;
+    WaitForSingleObject(event, INFINITE);
+
+    CloseHandle(event)?;
+
+    let mut srv_desc = D3D12_SHADER_RESOURCE_VIEW_DESC::default();
+    srv_desc.Format = src.format;
+    srv_desc.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2D;
+    srv_desc.Anonymous.Texture2D.MipLevels = 1;
+    srv_desc.Anonymous.Texture2D.MostDetailedMip = 0;
+    srv_desc.Shader4ComponentMapping = D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING;
+    device.CreateShaderResourceView(&texture, Some(&srv_desc), cpu_handle);
+    Ok(texture)
+}
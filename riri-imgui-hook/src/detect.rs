@@ -0,0 +1,176 @@
+// Pick a renderer for games that don't have an entry in the registry by checking which
+// Direct3D runtime the game has loaded.
+use crate::registry::RendererType;
+use riri_mod_tools_rt::logln;
+use std::time::{ Duration, Instant };
+#[cfg(windows)]
+use windows::{
+    core::PCSTR,
+    Win32::System::LibraryLoader
+};
+
+// Runtime DLLs for each renderer, null terminated for GetModuleHandleA
+pub static D3D11_DLL_NAMES: [&'static str; 5] = [
+    "d3d11.dll\0",
+    "d3d11_1.dll\0",
+    "d3d11_2.dll\0",
+    "d3d11_3.dll\0",
+    "d3d11_4.dll\0"
+];
+pub static D3D12_DLL_NAMES: [&'static str; 1] = [ "d3d12.dll\0" ];
+
+// How often wait_for_renderer checks the loaded modules, and how long it keeps trying
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+pub const POLL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Queries the modules loaded into a process. Names are passed without a null terminator.
+pub trait LoadedModules {
+    fn is_loaded(&self, name: &str) -> bool;
+}
+
+/// Checks modules loaded into the current process through GetModuleHandleA
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessModules;
+#[cfg(windows)]
+impl LoadedModules for ProcessModules {
+    fn is_loaded(&self, name: &str) -> bool {
+        let name = format!("{}\0", name);
+        match unsafe { LibraryLoader::GetModuleHandleA(PCSTR(name.as_ptr())) } {
+            Ok(h) => !h.is_invalid(),
+            Err(_) => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    renderer: RendererType,
+    reason: String
+}
+impl Detection {
+    pub fn get_renderer(&self) -> RendererType { self.renderer }
+    pub fn get_reason(&self) -> &str { &self.reason }
+}
+
+fn find_loaded<'a, M: LoadedModules>(modules: &M, names: &[&'a str]) -> Option<&'a str> {
+    names.iter()
+        .map(|n| n.trim_end_matches('\0'))
+        .find(|n| modules.is_loaded(n))
+}
+
+pub fn detect_renderer<M: LoadedModules>(modules: &M) -> Option<Detection> {
+    let d3d11 = find_loaded(modules, &D3D11_DLL_NAMES);
+    let d3d12 = find_loaded(modules, &D3D12_DLL_NAMES);
+    match (d3d11, d3d12) {
+        // D3D11 is commonly pulled in next to D3D12 by overlays and D3D11On12 interop,
+        // so the D3D12 runtime being present is the stronger signal
+        (Some(n11), Some(n12)) => Some(Detection {
+            renderer: RendererType::Direct3D12,
+            reason: format!("both {} and {} are loaded, preferring Direct3D12", n11, n12)
+        }),
+        (None, Some(n12)) => Some(Detection {
+            renderer: RendererType::Direct3D12,
+            reason: format!("{} is loaded", n12)
+        }),
+        (Some(n11), None) => Some(Detection {
+            renderer: RendererType::Direct3D11,
+            reason: format!("{} is loaded", n11)
+        }),
+        (None, None) => None
+    }
+}
+
+/// Poll the process's loaded modules until a Direct3D runtime shows up. This is expected to
+/// be run on a separate thread spun up by riri-imgui-hook-reloaded.
+pub fn wait_for_renderer<M: LoadedModules>(modules: &M) -> Option<Detection> {
+    wait_for_renderer_timeout(modules, POLL_TIMEOUT)
+}
+
+/// wait_for_renderer, giving up once timeout has passed
+pub fn wait_for_renderer_timeout<M: LoadedModules>(modules: &M, timeout: Duration) -> Option<Detection> {
+    let start = Instant::now();
+    loop {
+        if let Some(d) = detect_renderer(modules) {
+            logln!(Information, "Detected renderer {:?}: {}", d.get_renderer(), d.get_reason());
+            return Some(d);
+        }
+        if start.elapsed() >= timeout {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    logln!(Error, "Could not detect a renderer: no Direct3D11 or Direct3D12 DLL was loaded after {:?}", timeout);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // Pretends the listed modules are loaded once it's been queried `after` times
+    struct FakeModules {
+        loaded: &'static [&'static str],
+        after: usize,
+        queries: Cell<usize>
+    }
+    impl FakeModules {
+        fn new(loaded: &'static [&'static str]) -> Self {
+            Self::delayed(loaded, 0)
+        }
+        fn delayed(loaded: &'static [&'static str], after: usize) -> Self {
+            Self { loaded, after, queries: Cell::new(0) }
+        }
+    }
+    impl LoadedModules for FakeModules {
+        fn is_loaded(&self, name: &str) -> bool {
+            assert!(!name.ends_with('\0'));
+            self.queries.set(self.queries.get() + 1);
+            self.queries.get() > self.after && self.loaded.contains(&name)
+        }
+    }
+
+    #[test]
+    fn detect_nothing_loaded() {
+        assert_eq!(detect_renderer(&FakeModules::new(&[ "kernel32.dll" ])), None);
+    }
+
+    #[test]
+    fn detect_d3d11() {
+        let d = detect_renderer(&FakeModules::new(&[ "d3d11.dll" ])).unwrap();
+        assert_eq!(d.get_renderer(), RendererType::Direct3D11);
+        assert!(d.get_reason().contains("d3d11.dll"));
+        let d = detect_renderer(&FakeModules::new(&[ "d3d11_4.dll" ])).unwrap();
+        assert_eq!(d.get_renderer(), RendererType::Direct3D11);
+        assert!(d.get_reason().contains("d3d11_4.dll"));
+    }
+
+    #[test]
+    fn detect_d3d12() {
+        let d = detect_renderer(&FakeModules::new(&[ "d3d12.dll" ])).unwrap();
+        assert_eq!(d.get_renderer(), RendererType::Direct3D12);
+        assert!(d.get_reason().contains("d3d12.dll"));
+    }
+
+    #[test]
+    fn detect_prefers_d3d12() {
+        let d = detect_renderer(&FakeModules::new(&[ "d3d11.dll", "d3d12.dll" ])).unwrap();
+        assert_eq!(d.get_renderer(), RendererType::Direct3D12);
+    }
+
+    #[test]
+    fn wait_until_loaded() {
+        // Nothing shows up for the first two polls (six queries each)
+        let modules = FakeModules::delayed(&[ "d3d11.dll" ], 12);
+        let d = wait_for_renderer_timeout(&modules, Duration::from_secs(5)).unwrap();
+        assert_eq!(d.get_renderer(), RendererType::Direct3D11);
+    }
+
+    #[test]
+    fn wait_times_out() {
+        let start = Instant::now();
+        assert_eq!(wait_for_renderer_timeout(&FakeModules::new(&[]), Duration::from_millis(250)), None);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,583 @@
+// Fonts are kept outside of the ImGui context so they can be changed from GUI callbacks, which
+// run while the font atlas is locked. Changes mark the stack as dirty, and the backend rebuilds
+// the atlas and the renderer's font texture at the start of the next frame.
+use crate::config::ConfigParseError;
+use imgui::{
+    FontAtlas,
+    FontConfig,
+    FontGlyphRanges,
+    FontSource
+};
+use riri_mod_tools_rt::logln;
+use serde::Deserialize;
+use std::{
+    path::Path,
+    sync::{ Arc, Mutex, MutexGuard }
+};
+use toml::Spanned;
+
+#[derive(Debug, Clone)]
+pub struct FontEntry {
+    data: Arc<[u8]>,
+    size_pixels: f32,
+    oversample_h: i32,
+    oversample_v: i32,
+    glyph_ranges: FontGlyphRanges,
+    // Merge glyphs into the previous font instead of adding a new one (e.g for icon fonts)
+    merge: bool
+}
+impl FontEntry {
+    pub fn new(data: Arc<[u8]>, size_pixels: f32, glyph_ranges: FontGlyphRanges, merge: bool) -> Self {
+        Self { data, size_pixels, oversample_h: 3, oversample_v: 1, glyph_ranges, merge }
+    }
+    pub fn with_oversample(self, oversample_h: i32, oversample_v: i32) -> Self {
+        Self { oversample_h, oversample_v, ..self }
+    }
+    pub fn get_data(&self) -> &[u8] { &self.data }
+    pub fn get_size_pixels(&self) -> f32 { self.size_pixels }
+    pub fn get_oversample(&self) -> (i32, i32) { (self.oversample_h, self.oversample_v) }
+    pub fn get_glyph_ranges(&self) -> &FontGlyphRanges { &self.glyph_ranges }
+    pub fn is_merged(&self) -> bool { self.merge }
+}
+
+#[derive(Debug)]
+pub struct FontStack {
+    entries: Vec<FontEntry>,
+    // Multiplies every font's size when the atlas is built, so DPI scaling gets sharp glyphs
+    // instead of stretching the atlas
+    scale: f32,
+    dirty: bool
+}
+impl FontStack {
+    pub const fn new() -> Self {
+        Self { entries: vec![], scale: 1., dirty: false }
+    }
+    pub fn get_entries(&self) -> &[FontEntry] {
+        &self.entries
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+    /// Request a rebuild of the font atlas on the next frame
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+    pub fn add_font(&mut self, entry: FontEntry) {
+        self.entries.push(entry);
+        self.dirty = true;
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = true;
+    }
+    /// Change the size of every font in the stack
+    pub fn set_size_pixels(&mut self, size_pixels: f32) {
+        for entry in &mut self.entries {
+            entry.size_pixels = size_pixels;
+        }
+        self.dirty = true;
+    }
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+    /// Change the scale that font sizes are multiplied by
+    pub fn set_scale(&mut self, scale: f32) {
+        if self.scale != scale {
+            self.scale = scale;
+            self.dirty = true;
+        }
+    }
+    /// Add extra glyph ranges to a font. This is done the same way as ImGui does it, by merging
+    /// the same font file again with only the new ranges.
+    pub fn add_glyph_ranges(&mut self, font: usize, glyph_ranges: FontGlyphRanges) -> bool {
+        // Find the entry that the merged copy should follow: the last entry merged into the
+        // given font, so that it ends up in the same group when built
+        let index = match self.get_group_range(font) {
+            Some(v) => v,
+            None => return false
+        };
+        let base = self.entries[index.start].clone();
+        self.entries.insert(index.end, FontEntry { glyph_ranges, merge: true, ..base });
+        self.dirty = true;
+        true
+    }
+
+    // Range of entries that make up the nth font (a font and everything merged into it)
+    fn get_group_range(&self, font: usize) -> Option<std::ops::Range<usize>> {
+        let mut starts = self.entries.iter().enumerate()
+            .filter(|(i, e)| *i == 0 || !e.merge)
+            .map(|(i, _)| i)
+            .skip(font);
+        let start = starts.next()?;
+        let end = starts.next().unwrap_or(self.entries.len());
+        Some(start..end)
+    }
+
+    /// Clear the atlas and add every font in the stack. The atlas texture is built by the
+    /// renderer afterwards. Any FontId taken from the old atlas is invalid after this.
+    pub fn build(&mut self, atlas: &mut FontAtlas) {
+        atlas.clear();
+        if self.entries.is_empty() {
+            atlas.add_font(&[FontSource::DefaultFontData { config: Some(FontConfig {
+                size_pixels: 13. * self.scale, ..FontConfig::default()
+            }) }]);
+        }
+        let mut font = 0;
+        while let Some(range) = self.get_group_range(font) {
+            let sources: Vec<FontSource> = self.entries[range].iter().map(|e| {
+                let mut config = FontConfig::default();
+                config.glyph_ranges = e.glyph_ranges.clone();
+                config.oversample_h = e.oversample_h;
+                config.oversample_v = e.oversample_v;
+                FontSource::TtfData { data: &e.data, size_pixels: e.size_pixels * self.scale, config: Some(config) }
+            }).collect();
+            atlas.add_font(&sources);
+            font += 1;
+        }
+        self.dirty = false;
+    }
+}
+
+static FONT_STACK: Mutex<FontStack> = Mutex::new(FontStack::new());
+
+pub fn get_font_stack() -> MutexGuard<'static, FontStack> {
+    FONT_STACK.lock().unwrap()
+}
+
+/// Check that a zero terminated list of glyph ranges is something ImGui will accept
+pub fn validate_glyph_ranges(ranges: &[u32]) -> Result<(), String> {
+    if ranges.last() != Some(&0) || ranges.len() % 2 != 1 {
+        return Err("Glyph ranges must be pairs of codepoints followed by a zero".to_owned());
+    }
+    let mut pairs: Vec<(u32, u32)> = ranges[..ranges.len() - 1].chunks_exact(2).map(|p| (p[0], p[1])).collect();
+    for (start, end) in &pairs {
+        if *start == 0 || *end > char::MAX as u32 || start > end {
+            return Err(format!("Invalid glyph range 0x{:x}-0x{:x}", start, end));
+        }
+    }
+    pairs.sort_unstable();
+    if let Some(p) = pairs.windows(2).find(|p| p[0].1 >= p[1].0) {
+        return Err(format!("Glyph ranges 0x{:x}-0x{:x} and 0x{:x}-0x{:x} overlap", p[0].0, p[0].1, p[1].0, p[1].1));
+    }
+    Ok(())
+}
+
+pub const FONTS_FILE_NAME: &'static str = "fonts.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlyphRangeSet {
+    Default,
+    Japanese,
+    Korean,
+    ChineseFull,
+    ChineseSimplifiedCommon,
+    Cyrillic,
+    Thai,
+    Vietnamese
+}
+impl GlyphRangeSet {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Default" => Some(Self::Default),
+            "Japanese" => Some(Self::Japanese),
+            "Korean" => Some(Self::Korean),
+            "ChineseFull" => Some(Self::ChineseFull),
+            "ChineseSimplifiedCommon" => Some(Self::ChineseSimplifiedCommon),
+            "Cyrillic" => Some(Self::Cyrillic),
+            "Thai" => Some(Self::Thai),
+            "Vietnamese" => Some(Self::Vietnamese),
+            _ => None
+        }
+    }
+    pub fn get_ranges(&self) -> FontGlyphRanges {
+        match self {
+            Self::Default => FontGlyphRanges::default(),
+            Self::Japanese => FontGlyphRanges::japanese(),
+            Self::Korean => FontGlyphRanges::korean(),
+            Self::ChineseFull => FontGlyphRanges::chinese_full(),
+            Self::ChineseSimplifiedCommon => FontGlyphRanges::chinese_simplified_common(),
+            Self::Cyrillic => FontGlyphRanges::cyrillic(),
+            Self::Thai => FontGlyphRanges::thai(),
+            Self::Vietnamese => FontGlyphRanges::vietnamese()
+        }
+    }
+}
+
+/// A font listed in fonts.toml. The font file is read when it's added to the font stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontConfigEntry {
+    file: String,
+    size_pixels: f32,
+    oversample_h: i32,
+    oversample_v: i32,
+    glyph_ranges: Vec<GlyphRangeSet>,
+    // Zero terminated, in the format expected by FontGlyphRanges::from_slice
+    custom_ranges: Vec<u32>,
+    merge: bool
+}
+impl FontConfigEntry {
+    pub fn get_file(&self) -> &str { &self.file }
+    pub fn get_size_pixels(&self) -> f32 { self.size_pixels }
+    pub fn get_oversample(&self) -> (i32, i32) { (self.oversample_h, self.oversample_v) }
+    pub fn get_glyph_ranges(&self) -> &[GlyphRangeSet] { &self.glyph_ranges }
+    pub fn get_custom_ranges(&self) -> &[u32] { &self.custom_ranges }
+    pub fn is_merged(&self) -> bool { self.merge }
+}
+
+// Layout of fonts.toml. Fonts are added in order, and entries with merge = true have their
+// glyphs merged into the font before them.
+//
+// [[font]]
+// file = "NotoSansCJKjp-Medium.otf"
+// size = 15.0
+// oversample_h = 3
+// oversample_v = 1
+// glyph_ranges = [ "Japanese", "Cyrillic" ]
+// custom_ranges = [ [ 0x2000, 0x206f ] ]
+//
+// [[font]]
+// file = "fa-solid-900.ttf"
+// size = 13.0
+// merge = true
+// custom_ranges = [ [ 0xf000, 0xf8ff ] ]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FontFile {
+    #[serde(default)]
+    font: Vec<FontFileEntry>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FontFileEntry {
+    file: String,
+    size: Spanned<f32>,
+    #[serde(default)]
+    oversample_h: Option<Spanned<i32>>,
+    #[serde(default)]
+    oversample_v: Option<Spanned<i32>>,
+    #[serde(default)]
+    glyph_ranges: Vec<Spanned<String>>,
+    #[serde(default)]
+    custom_ranges: Option<Spanned<Vec<Vec<u32>>>>,
+    #[serde(default)]
+    merge: bool
+}
+
+/// Parse the contents of a fonts.toml file
+pub fn parse_font_config(source: &str) -> Result<Vec<FontConfigEntry>, ConfigParseError> {
+    let file: FontFile = toml::from_str(source)
+        .map_err(|e| ConfigParseError::new(source, e.span(), e.message().to_owned()))?;
+    let mut out = Vec::with_capacity(file.font.len());
+    for entry in file.font {
+        if !(*entry.size.get_ref() > 0.) {
+            return Err(ConfigParseError::new(source, Some(entry.size.span()),
+                format!("Font size must be above zero, got {}", entry.size.get_ref())));
+        }
+        let mut oversample = [3, 1];
+        for (i, v) in [entry.oversample_h, entry.oversample_v].into_iter().enumerate() {
+            if let Some(v) = v {
+                if !(1..=8).contains(v.get_ref()) {
+                    return Err(ConfigParseError::new(source, Some(v.span()),
+                        format!("Oversampling must be between 1 and 8, got {}", v.get_ref())));
+                }
+                oversample[i] = *v.get_ref();
+            }
+        }
+        let mut glyph_ranges = Vec::with_capacity(entry.glyph_ranges.len());
+        for name in &entry.glyph_ranges {
+            match GlyphRangeSet::from_name(name.get_ref()) {
+                Some(v) => glyph_ranges.push(v),
+                None => return Err(ConfigParseError::new(source, Some(name.span()),
+                    format!("Unknown glyph range \"{}\"", name.get_ref())))
+            }
+        }
+        let mut custom_ranges = vec![];
+        if let Some(ranges) = entry.custom_ranges.filter(|r| !r.get_ref().is_empty()) {
+            // Not deserialized as [u32; 2], since that silently drops anything after the second value
+            if ranges.get_ref().iter().any(|r| r.len() != 2) {
+                return Err(ConfigParseError::new(source, Some(ranges.span()),
+                    "Custom ranges must be pairs of [ first, last ] codepoints".to_owned()));
+            }
+            custom_ranges.extend(ranges.get_ref().iter().flatten());
+            custom_ranges.push(0);
+            validate_glyph_ranges(&custom_ranges)
+                .map_err(|e| ConfigParseError::new(source, Some(ranges.span()), e))?;
+        }
+        out.push(FontConfigEntry {
+            file: entry.file,
+            size_pixels: *entry.size.get_ref(),
+            oversample_h: oversample[0],
+            oversample_v: oversample[1],
+            glyph_ranges, custom_ranges,
+            merge: entry.merge
+        });
+    }
+    Ok(out)
+}
+
+/// Fonts used when there's no fonts.toml
+pub fn get_default_font_config() -> Vec<FontConfigEntry> {
+    vec![FontConfigEntry {
+        file: "NotoSansCJKjp-Medium.otf".to_owned(),
+        size_pixels: 15.,
+        oversample_h: 3,
+        oversample_v: 1,
+        glyph_ranges: vec![GlyphRangeSet::Japanese],
+        custom_ranges: vec![],
+        merge: false
+    }]
+}
+
+pub fn load_font_config<P: AsRef<Path>>(path: P) -> Vec<FontConfigEntry> {
+    let source = match std::fs::read_to_string(path.as_ref()) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return get_default_font_config(),
+        Err(e) => {
+            logln!(Error, "Could not read {}: {}. Using default fonts", path.as_ref().display(), e);
+            return get_default_font_config();
+        }
+    };
+    match parse_font_config(&source) {
+        Ok(v) => v,
+        Err(e) => {
+            logln!(Error, "Error in {}: {}. Using default fonts", path.as_ref().display(), e);
+            get_default_font_config()
+        }
+    }
+}
+
+/// Read the font files listed in the config and add them to the stack. Relative paths are
+/// resolved from base_dir. A font that can't be read is skipped along with any fonts merged
+/// into it, the rest are still added. Returns the number of entries that were added.
+pub fn add_configured_fonts<F>(stack: &mut FontStack, config: &[FontConfigEntry], base_dir: &Path, read: F) -> usize
+where F: Fn(&Path) -> std::io::Result<Vec<u8>>
+{
+    let mut added = 0;
+    let mut parent_missing = false;
+    for entry in config {
+        if entry.merge && parent_missing {
+            logln!(Warning, "Skipping font {} since the font it's merged into is missing", entry.file);
+            continue;
+        }
+        let data: Arc<[u8]> = match read(&base_dir.join(&entry.file)) {
+            Ok(v) => v.into(),
+            Err(e) => {
+                logln!(Warning, "Could not read font {}: {}. Skipping it", entry.file, e);
+                parent_missing = !entry.merge;
+                continue;
+            }
+        };
+        parent_missing = false;
+        let mut ranges: Vec<FontGlyphRanges> = entry.glyph_ranges.iter().map(|r| r.get_ranges()).collect();
+        if !entry.custom_ranges.is_empty() {
+            // ImGui keeps a pointer to the ranges for as long as the font exists. Config entries
+            // are only loaded once, so leaking them here is fine.
+            let custom: &'static [u32] = Box::leak(entry.custom_ranges.clone().into_boxed_slice());
+            ranges.push(FontGlyphRanges::from_slice(custom));
+        }
+        if ranges.is_empty() {
+            ranges.push(FontGlyphRanges::default());
+        }
+        // Each extra set of ranges is added as a merged copy of the same font
+        for (i, r) in ranges.into_iter().enumerate() {
+            stack.add_font(FontEntry::new(data.clone(), entry.size_pixels, r, entry.merge || i > 0)
+                .with_oversample(entry.oversample_h, entry.oversample_v));
+        }
+        added += 1;
+    }
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ Error as IoError, ErrorKind };
+
+    // A base font with an icon font merged into it, then a second font with its own icons
+    const TWO_FONTS: &str = "[[font]]\nfile = \"base.ttf\"\nsize = 15.0\n\n\
+        [[font]]\nfile = \"icons.ttf\"\nsize = 13.0\nmerge = true\n\n\
+        [[font]]\nfile = \"other.ttf\"\nsize = 15.0\n\n\
+        [[font]]\nfile = \"other_icons.ttf\"\nsize = 13.0\nmerge = true\n";
+
+    // Font files are read as their own name, so entries in the stack can be told apart
+    fn read_present<'a>(present: &'a [&'a str]) -> impl Fn(&Path) -> std::io::Result<Vec<u8>> + 'a {
+        move |path| {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            match present.contains(&name) {
+                true => Ok(name.as_bytes().to_vec()),
+                false => Err(IoError::from(ErrorKind::NotFound))
+            }
+        }
+    }
+
+    fn get_summary(stack: &FontStack) -> Vec<(&str, bool)> {
+        stack.get_entries().iter().map(|e| (std::str::from_utf8(e.get_data()).unwrap(), e.is_merged())).collect()
+    }
+
+    fn font(name: &str, merge: bool) -> FontEntry {
+        FontEntry::new(name.as_bytes().into(), 13., FontGlyphRanges::default(), merge)
+    }
+
+    #[test]
+    fn parse_full_entry() {
+        let source = "[[font]]\nfile = \"NotoSansCJKjp-Medium.otf\"\nsize = 15.0\noversample_h = 2\noversample_v = 4\n\
+            glyph_ranges = [ \"Japanese\", \"Cyrillic\" ]\ncustom_ranges = [ [ 0x2000, 0x206f ], [ 0x20, 0x7f ] ]\n";
+        let entries = parse_font_config(source).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_file(), "NotoSansCJKjp-Medium.otf");
+        assert_eq!(entries[0].get_size_pixels(), 15.);
+        assert_eq!(entries[0].get_oversample(), (2, 4));
+        assert_eq!(entries[0].get_glyph_ranges(), &[GlyphRangeSet::Japanese, GlyphRangeSet::Cyrillic]);
+        assert_eq!(entries[0].get_custom_ranges(), &[0x2000, 0x206f, 0x20, 0x7f, 0]);
+        assert!(!entries[0].is_merged());
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert!(parse_font_config("").unwrap().is_empty());
+        let entries = parse_font_config(TWO_FONTS).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[1].get_oversample(), (3, 1));
+        assert!(entries[1].get_glyph_ranges().is_empty());
+        assert!(entries[1].get_custom_ranges().is_empty());
+        assert!(!entries[0].is_merged() && entries[1].is_merged());
+    }
+
+    #[test]
+    fn parse_size() {
+        for size in [ "0.0", "-2.0", "nan" ] {
+            let source = format!("[[font]]\nfile = \"a.ttf\"\nsize = {}\n", size);
+            let err = parse_font_config(&source).unwrap_err();
+            assert_eq!(err.get_line(), Some(3), "size = {}", size);
+        }
+        // Errors in later entries report their own line
+        let err = parse_font_config("[[font]]\nfile = \"a.ttf\"\nsize = 13.0\n\n[[font]]\nfile = \"b.ttf\"\nsize = 0.0\n").unwrap_err();
+        assert_eq!(err.get_line(), Some(7));
+    }
+
+    #[test]
+    fn parse_oversample_range() {
+        for line in [ "oversample_h = 0", "oversample_h = 9", "oversample_v = 0", "oversample_v = 9" ] {
+            let source = format!("[[font]]\nfile = \"a.ttf\"\nsize = 13.0\n{}\n", line);
+            let err = parse_font_config(&source).unwrap_err();
+            assert_eq!(err.get_line(), Some(4), "{}", line);
+        }
+        let source = "[[font]]\nfile = \"a.ttf\"\nsize = 13.0\noversample_h = 1\noversample_v = 8\n";
+        assert_eq!(parse_font_config(source).unwrap()[0].get_oversample(), (1, 8));
+    }
+
+    #[test]
+    fn parse_unknown_glyph_range() {
+        let source = "[[font]]\nfile = \"a.ttf\"\nsize = 13.0\nglyph_ranges = [ \"Japanese\", \"Klingon\" ]\n";
+        let err = parse_font_config(source).unwrap_err();
+        assert_eq!(err.get_line(), Some(4));
+        assert!(err.get_message().contains("Klingon"));
+    }
+
+    #[test]
+    fn parse_custom_ranges() {
+        for ranges in [
+            // Odd number of codepoints
+            "[ [ 0x20 ] ]",
+            "[ [ 0x20, 0x7f, 0xff ] ]",
+            // Start after end
+            "[ [ 0x7f, 0x20 ] ]",
+            // Overlapping
+            "[ [ 0x100, 0x200 ], [ 0x20, 0x7f ], [ 0x150, 0x300 ] ]",
+            // Zero would end the list early
+            "[ [ 0, 0x7f ] ]",
+            "[ [ 0x20, 0x110000 ] ]"
+        ] {
+            let source = format!("[[font]]\nfile = \"a.ttf\"\nsize = 13.0\ncustom_ranges = {}\n", ranges);
+            let err = parse_font_config(&source).unwrap_err();
+            assert_eq!(err.get_line(), Some(4), "custom_ranges = {}", ranges);
+        }
+        // Ranges don't have to be sorted as long as they don't overlap
+        let source = "[[font]]\nfile = \"a.ttf\"\nsize = 13.0\ncustom_ranges = [ [ 0x100, 0x200 ], [ 0x20, 0x7f ] ]\n";
+        assert!(parse_font_config(source).is_ok());
+    }
+
+    #[test]
+    fn glyph_range_validation() {
+        assert!(validate_glyph_ranges(&[0x20, 0x7f, 0]).is_ok());
+        assert!(validate_glyph_ranges(&[0x20, 0x7f]).is_err());
+        assert!(validate_glyph_ranges(&[0x20, 0]).is_err());
+        assert!(validate_glyph_ranges(&[0x20, 0x7f, 0x7f, 0x100, 0]).is_err());
+    }
+
+    #[test]
+    fn add_all_fonts() {
+        let config = parse_font_config(TWO_FONTS).unwrap();
+        let mut stack = FontStack::new();
+        let base_dir = Path::new("mods").join("imgui");
+        let read = |path: &Path| {
+            assert_eq!(path.parent(), Some(base_dir.as_path()));
+            read_present(&["base.ttf", "icons.ttf", "other.ttf", "other_icons.ttf"])(path)
+        };
+        assert_eq!(add_configured_fonts(&mut stack, &config, &base_dir, read), 4);
+        assert_eq!(get_summary(&stack), [("base.ttf", false), ("icons.ttf", true), ("other.ttf", false), ("other_icons.ttf", true)]);
+        assert!(stack.is_dirty());
+    }
+
+    #[test]
+    fn missing_base_font_skips_merged() {
+        let config = parse_font_config(TWO_FONTS).unwrap();
+        let mut stack = FontStack::new();
+        let read = read_present(&["icons.ttf", "other.ttf", "other_icons.ttf"]);
+        assert_eq!(add_configured_fonts(&mut stack, &config, Path::new(""), read), 2);
+        assert_eq!(get_summary(&stack), [("other.ttf", false), ("other_icons.ttf", true)]);
+    }
+
+    #[test]
+    fn missing_merged_font_keeps_base() {
+        let config = parse_font_config(TWO_FONTS).unwrap();
+        let mut stack = FontStack::new();
+        let read = read_present(&["base.ttf", "other.ttf", "other_icons.ttf"]);
+        assert_eq!(add_configured_fonts(&mut stack, &config, Path::new(""), read), 3);
+        assert_eq!(get_summary(&stack), [("base.ttf", false), ("other.ttf", false), ("other_icons.ttf", true)]);
+    }
+
+    #[test]
+    fn no_fonts_found() {
+        let config = parse_font_config(TWO_FONTS).unwrap();
+        let mut stack = FontStack::new();
+        assert_eq!(add_configured_fonts(&mut stack, &config, Path::new(""), read_present(&[])), 0);
+        assert!(stack.get_entries().is_empty());
+    }
+
+    #[test]
+    fn range_sets_are_merged_copies() {
+        let source = "[[font]]\nfile = \"base.ttf\"\nsize = 15.0\n\
+            glyph_ranges = [ \"Japanese\", \"Cyrillic\" ]\ncustom_ranges = [ [ 0x2000, 0x206f ] ]\n";
+        let config = parse_font_config(source).unwrap();
+        let mut stack = FontStack::new();
+        assert_eq!(add_configured_fonts(&mut stack, &config, Path::new(""), read_present(&["base.ttf"])), 1);
+        assert_eq!(get_summary(&stack), [("base.ttf", false), ("base.ttf", true), ("base.ttf", true)]);
+        assert_eq!(stack.get_entries()[0].get_glyph_ranges(), &FontGlyphRanges::japanese());
+        assert_eq!(stack.get_entries()[1].get_glyph_ranges(), &FontGlyphRanges::cyrillic());
+    }
+
+    #[test]
+    fn glyph_ranges_join_their_font() {
+        let mut stack = FontStack::new();
+        stack.add_font(font("a", false));
+        stack.add_font(font("a_icons", true));
+        stack.add_font(font("b", false));
+        // The copy goes after everything already merged into font 0, so it's built with it
+        assert!(stack.add_glyph_ranges(0, FontGlyphRanges::korean()));
+        assert_eq!(get_summary(&stack), [("a", false), ("a_icons", true), ("a", true), ("b", false)]);
+        assert_eq!(stack.get_entries()[2].get_glyph_ranges(), &FontGlyphRanges::korean());
+        assert!(stack.add_glyph_ranges(1, FontGlyphRanges::thai()));
+        assert_eq!(get_summary(&stack), [("a", false), ("a_icons", true), ("a", true), ("b", false), ("b", true)]);
+        assert!(!stack.add_glyph_ranges(2, FontGlyphRanges::thai()));
+        assert_eq!(stack.get_entries().len(), 5);
+    }
+
+    #[test]
+    fn glyph_ranges_empty_stack() {
+        let mut stack = FontStack::new();
+        assert!(!stack.add_glyph_ranges(0, FontGlyphRanges::default()));
+        assert!(!stack.is_dirty());
+    }
+}
@@ -0,0 +1,140 @@
+// Runs GUI code for a number of frames on the headless platform and renderer, so plugins can
+// be checked without the game or a GPU
+use crate::{
+    callback::CallbackTypeSignature,
+    headless_impl::{
+        platform::{ HeadlessPlatform, InputEvent },
+        renderer::{ DrawStats, HeadlessRenderer }
+    }
+};
+use imgui::{
+    Context as ImContext,
+    Ui as ImUI
+};
+
+#[derive(Debug)]
+pub struct Harness {
+    imgui: ImContext,
+    platform: HeadlessPlatform,
+    renderer: HeadlessRenderer
+}
+
+impl Harness {
+    pub fn new(display_size: [f32; 2]) -> Self {
+        let mut imgui = ImContext::create();
+        // Don't leave an imgui.ini behind, and always start from the default layout
+        imgui.set_ini_filename(None);
+        let platform = HeadlessPlatform::new(&mut imgui, display_size);
+        let renderer = HeadlessRenderer::new(&mut imgui);
+        Self { imgui, platform, renderer }
+    }
+
+    pub fn get_context(&self) -> &ImContext { &self.imgui }
+    pub fn get_context_mut(&mut self) -> &mut ImContext { &mut self.imgui }
+    pub fn get_platform(&self) -> &HeadlessPlatform { &self.platform }
+    pub fn get_platform_mut(&mut self) -> &mut HeadlessPlatform { &mut self.platform }
+    pub fn get_renderer(&self) -> &HeadlessRenderer { &self.renderer }
+    pub fn get_renderer_mut(&mut self) -> &mut HeadlessRenderer { &mut self.renderer }
+
+    /// See HeadlessPlatform::add_event
+    pub fn add_event(&mut self, frame: u64, event: InputEvent) {
+        self.platform.add_event(frame, event);
+    }
+
+    /// Run one frame and return what was drawn
+    pub fn run_frame<F: FnOnce(&ImUI)>(&mut self, draw: F) -> DrawStats {
+        self.platform.new_frame(&mut self.imgui);
+        let ui = self.imgui.new_frame();
+        draw(ui);
+        let draw_data = self.imgui.render();
+        self.renderer.render(draw_data)
+    }
+
+    /// Run count frames, returning what was drawn in each
+    pub fn run_frames<F: FnMut(&ImUI)>(&mut self, count: usize, mut draw: F) -> Vec<DrawStats> {
+        (0..count).map(|_| self.run_frame(&mut draw)).collect()
+    }
+
+    /// Run a C GUI callback (the same signature add_gui_callback takes) for count frames
+    pub unsafe fn run_callback(&mut self, count: usize, cb: CallbackTypeSignature) -> Vec<DrawStats> {
+        let ctx_ptr = imgui::sys::igGetCurrentContext() as *mut u8;
+        self.run_frames(count, |ui| cb(ui as *const ImUI as *mut u8, ctx_ptr))
+    }
+}
+
+// ImGui has a single current context for the whole process, so tests that create one take
+// turns
+#[cfg(test)]
+pub(crate) fn lock_context() -> std::sync::MutexGuard<'static, ()> {
+    static CONTEXT_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    CONTEXT_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless_impl::renderer::FONT_TEX_ID;
+    use imgui::{ Condition, MouseButton, TextureId };
+
+    fn draw_window(ui: &ImUI) {
+        ui.window("Test")
+            .position([10., 10.], Condition::Always)
+            .size([200., 100.], Condition::Always)
+            .build(|| ui.text("Hello"));
+    }
+
+    #[test]
+    fn run_n_frames() {
+        let _lock = lock_context();
+        let mut harness = Harness::new([640., 480.]);
+        let stats = harness.run_frames(5, draw_window);
+        assert_eq!(stats.len(), 5);
+        assert_eq!(harness.get_platform().get_frame(), 5);
+        assert_eq!(harness.get_renderer().get_frame_count(), 5);
+        for frame in &stats {
+            assert_eq!(frame.display_size, [640., 480.]);
+            assert!(!frame.is_empty());
+            assert!(frame.vertex_count > 0 && frame.index_count > 0);
+            assert!(frame.uses_texture(TextureId::from(FONT_TEX_ID)));
+        }
+        // Nothing changes between frames, so every frame draws the same thing
+        assert!(stats.windows(2).all(|w| w[0] == w[1]));
+        assert_eq!(harness.get_renderer().get_last_stats(), stats.last());
+    }
+
+    #[test]
+    fn empty_frame() {
+        let _lock = lock_context();
+        let mut harness = Harness::new([640., 480.]);
+        let stats = harness.run_frame(|_| ());
+        assert!(stats.is_empty());
+        assert!(stats.texture_ids.is_empty());
+    }
+
+    #[test]
+    fn scripted_input() {
+        let _lock = lock_context();
+        let mut harness = Harness::new([640., 480.]);
+        harness.add_event(0, InputEvent::MousePos([50., 50.]));
+        harness.add_event(2, InputEvent::MouseButton(MouseButton::Left, true));
+        harness.add_event(4, InputEvent::MouseButton(MouseButton::Left, false));
+        let mut down = vec![];
+        harness.run_frames(6, |ui| down.push(ui.is_mouse_down(MouseButton::Left)));
+        assert_eq!(down, [false, false, true, true, false, false]);
+        assert_eq!(harness.get_platform().get_pending_event_count(), 0);
+    }
+
+    unsafe extern "C" fn draw_callback(ui: *mut u8, _ctx: *mut u8) {
+        draw_window(&*(ui as *const ImUI));
+    }
+
+    #[test]
+    fn run_c_callback() {
+        let _lock = lock_context();
+        let mut harness = Harness::new([640., 480.]);
+        let expected = harness.run_frames(3, draw_window);
+        let stats = unsafe { harness.run_callback(3, draw_callback) };
+        assert_eq!(stats, expected);
+        assert_eq!(harness.get_renderer().get_frame_count(), 6);
+    }
+}
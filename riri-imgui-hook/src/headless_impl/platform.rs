@@ -0,0 +1,136 @@
+// Platform for running ImGui without a window, e.g in tests. The display size and time step
+// are fixed, and input comes from a script of events instead of window messages.
+use imgui::{
+    internal::RawCast,
+    Context as ImContext,
+    FontConfig,
+    FontSource,
+    Io,
+    Key,
+    MouseButton
+};
+use std::collections::VecDeque;
+
+/// Input sent to ImGui at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    MousePos([f32; 2]),
+    MouseButton(MouseButton, bool),
+    MouseWheel([f32; 2]),
+    Key(Key, bool),
+    Char(char),
+    Focus(bool)
+}
+impl InputEvent {
+    fn send(&self, io: &mut Io) {
+        match *self {
+            Self::MousePos(pos) => io.add_mouse_pos_event(pos),
+            Self::MouseButton(button, down) => io.add_mouse_button_event(button, down),
+            Self::MouseWheel(wheel) => io.add_mouse_wheel_event(wheel),
+            Self::Key(key, down) => io.add_key_event(key, down),
+            Self::Char(c) => io.add_input_character(c),
+            Self::Focus(focused) => unsafe { imgui::sys::ImGuiIO_AddFocusEvent(io.raw_mut(), focused) }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HeadlessPlatform {
+    display_size: [f32; 2],
+    delta_time: f32,
+    // Number of frames started so far
+    frame: u64,
+    // Events and the frame to send them on, in frame order
+    script: VecDeque<(u64, InputEvent)>,
+    dpi_scale: f32,
+    // Set when the scale changes, until the backend picks it up
+    dpi_scale_changed: bool
+}
+
+impl HeadlessPlatform {
+    pub const DEFAULT_DELTA_TIME: f32 = 1. / 60.;
+
+    pub fn new(ctx: &mut ImContext, display_size: [f32; 2]) -> Self {
+        let platform_name = format!("riri-imgui-hook-headless");
+        ctx.set_platform_name(Some(platform_name));
+        // Same font as Win32Impl so layouts match what's drawn in game
+        ctx.fonts().add_font(&[FontSource::DefaultFontData {
+            config: Some(FontConfig { size_pixels: 13., ..FontConfig::default() })
+        }]);
+        Self {
+            display_size,
+            delta_time: Self::DEFAULT_DELTA_TIME,
+            frame: 0,
+            script: VecDeque::new(),
+            dpi_scale: 1.,
+            dpi_scale_changed: false
+        }
+    }
+
+    pub fn get_display_size(&self) -> [f32; 2] { self.display_size }
+    pub fn set_display_size(&mut self, size: [f32; 2]) {
+        self.display_size = size;
+    }
+
+    /// Seconds between frames. Every frame takes the same time so animations are repeatable.
+    pub fn get_delta_time(&self) -> f32 { self.delta_time }
+    pub fn set_delta_time(&mut self, delta_time: f32) {
+        self.delta_time = delta_time;
+    }
+
+    /// Number of frames started so far. The next call to new_frame starts this frame.
+    pub fn get_frame(&self) -> u64 { self.frame }
+
+    /// Send an event at the start of a frame. Frames count up from 0, events for a frame that's
+    /// already started are sent on the next one. Events for the same frame are sent in the
+    /// order they were added.
+    pub fn add_event(&mut self, frame: u64, event: InputEvent) {
+        let index = self.script.partition_point(|(f, _)| *f <= frame);
+        self.script.insert(index, (frame, event));
+    }
+
+    pub fn add_events<I: IntoIterator<Item = (u64, InputEvent)>>(&mut self, events: I) {
+        for (frame, event) in events {
+            self.add_event(frame, event);
+        }
+    }
+
+    /// Send an event at the start of the next frame
+    pub fn add_event_next_frame(&mut self, event: InputEvent) {
+        self.add_event(self.frame, event);
+    }
+
+    pub fn get_pending_event_count(&self) -> usize { self.script.len() }
+
+    pub fn new_frame(&mut self, ctx: &mut ImContext) {
+        let io = ctx.io_mut();
+        io.display_size = self.display_size;
+        io.delta_time = self.delta_time;
+        while self.script.front().is_some_and(|(f, _)| *f <= self.frame) {
+            let (_, event) = self.script.pop_front().unwrap();
+            event.send(io);
+        }
+        self.frame += 1;
+    }
+
+    pub fn get_dpi_scale(&self) -> f32 { self.dpi_scale }
+
+    /// Pretend the window moved to a monitor with a different scale
+    pub fn set_dpi_scale(&mut self, scale: f32) {
+        self.dpi_scale = scale;
+        self.dpi_scale_changed = true;
+    }
+
+    /// Returns the new scale if it changed since this was last called
+    pub fn take_dpi_scale_change(&mut self) -> Option<f32> {
+        match std::mem::replace(&mut self.dpi_scale_changed, false) {
+            true => Some(self.dpi_scale),
+            false => None
+        }
+    }
+
+    pub fn shutdown(&mut self, ctx: &mut ImContext) {
+        self.script.clear();
+        ctx.set_platform_name(None);
+    }
+}
@@ -0,0 +1,185 @@
+// Renderer that doesn't draw anything by default. Textures are kept in memory and each
+// frame's DrawData is summarized, so tests can check what a GUI callback drew without a GPU.
+// With a rasterizer set, frames are also drawn in software.
+use crate::{
+    image::{ CpuImage, ImageError },
+    rasterizer::{ FrameData, Rasterizer, RenderTarget },
+    renderer::RendererBackend
+};
+use imgui::{
+    BackendFlags,
+    Context as ImContext,
+    DrawCmd,
+    DrawData,
+    Textures,
+    TextureId
+};
+use riri_mod_tools_rt::logln;
+use std::any::Any;
+
+pub const FONT_TEX_ID: usize = usize::MAX;
+
+/// What was in a frame's DrawData
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrawStats {
+    pub display_size: [f32; 2],
+    pub draw_list_count: usize,
+    pub vertex_count: usize,
+    pub index_count: usize,
+    /// Number of DrawCmd::Elements
+    pub command_count: usize,
+    /// Number of DrawCmd::ResetRenderState and DrawCmd::RawCallback
+    pub callback_count: usize,
+    /// Textures used by draw commands, in the order they were first used
+    pub texture_ids: Vec<TextureId>
+}
+impl DrawStats {
+    pub fn from_draw_data(draw_data: &DrawData) -> Self {
+        let mut out = Self {
+            display_size: draw_data.display_size,
+            draw_list_count: draw_data.draw_lists_count(),
+            vertex_count: draw_data.total_vtx_count as usize,
+            index_count: draw_data.total_idx_count as usize,
+            ..Self::default()
+        };
+        for draw_list in draw_data.draw_lists() {
+            for cmd in draw_list.commands() {
+                match cmd {
+                    DrawCmd::Elements { cmd_params, .. } => {
+                        out.command_count += 1;
+                        if !out.texture_ids.contains(&cmd_params.texture_id) {
+                            out.texture_ids.push(cmd_params.texture_id);
+                        }
+                    },
+                    DrawCmd::ResetRenderState | DrawCmd::RawCallback { .. } => out.callback_count += 1
+                }
+            }
+        }
+        out
+    }
+
+    pub fn uses_texture(&self, id: TextureId) -> bool {
+        self.texture_ids.contains(&id)
+    }
+
+    /// True if nothing was drawn
+    pub fn is_empty(&self) -> bool {
+        self.command_count == 0
+    }
+}
+
+#[derive(Debug)]
+pub struct HeadlessRenderer {
+    textures: Textures<CpuImage>,
+    font_atlas: Option<CpuImage>,
+    last_stats: Option<DrawStats>,
+    frame_count: u64,
+    rasterizer: Option<Rasterizer>,
+    last_image: Option<RenderTarget>
+}
+
+impl HeadlessRenderer {
+    pub fn new(ctx: &mut ImContext) -> Self {
+        let renderer_name = format!("riri-imgui-hook-headless");
+        ctx.set_renderer_name(Some(renderer_name));
+        ctx.io_mut().backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
+        let mut new = Self { textures: Textures::new(), font_atlas: None, last_stats: None, frame_count: 0,
+            rasterizer: None, last_image: None };
+        new.rebuild_font_texture(ctx);
+        new
+    }
+
+    /// Keep a copy of the font atlas, so it can be looked up with FONT_TEX_ID like any other
+    /// texture
+    pub fn rebuild_font_texture(&mut self, ctx: &mut ImContext) {
+        let fonts = ctx.fonts();
+        let fa_tex = fonts.build_rgba32_texture();
+        self.font_atlas = CpuImage::from_rgba(fa_tex.width, fa_tex.height, fa_tex.data).ok();
+        fonts.tex_id = TextureId::from(FONT_TEX_ID);
+    }
+
+    pub fn render(&mut self, draw_data: &DrawData) -> DrawStats {
+        let stats = DrawStats::from_draw_data(draw_data);
+        self.last_stats = Some(stats.clone());
+        self.frame_count += 1;
+        if let Some(rasterizer) = &self.rasterizer {
+            let (image, result) = rasterizer.render(&FrameData::from_draw_data(draw_data), |id| self.get_texture(id));
+            if let Err(e) = result {
+                logln!(Error, "Error while rasterizing: {}", e);
+            }
+            self.last_image = Some(image);
+        }
+        stats
+    }
+
+    /// Draw each frame in software, or pass None to only keep stats
+    pub fn set_rasterizer(&mut self, rasterizer: Option<Rasterizer>) {
+        self.rasterizer = rasterizer;
+        self.last_image = None;
+    }
+
+    /// The last frame drawn by the rasterizer
+    pub fn get_last_image(&self) -> Option<&RenderTarget> { self.last_image.as_ref() }
+
+    /// Stats from the last frame that was rendered
+    pub fn get_last_stats(&self) -> Option<&DrawStats> { self.last_stats.as_ref() }
+    pub fn get_frame_count(&self) -> u64 { self.frame_count }
+
+    pub fn create_texture(&mut self, image: &CpuImage) -> TextureId {
+        self.textures.insert(image.clone())
+    }
+
+    pub fn create_texture_rgba(&mut self, width: u32, height: u32, data: &[u8]) -> Result<TextureId, ImageError> {
+        Ok(self.textures.insert(CpuImage::from_rgba(width, height, data)?))
+    }
+
+    pub fn unregister_texture(&mut self, id: TextureId) -> bool {
+        self.textures.remove(id).is_some()
+    }
+
+    /// Get a texture's pixels, including the font atlas
+    pub fn get_texture(&self, id: TextureId) -> Option<&CpuImage> {
+        match id.id() {
+            FONT_TEX_ID => self.font_atlas.as_ref(),
+            _ => self.textures.get(id)
+        }
+    }
+
+    pub fn shutdown(&mut self, ctx: &mut ImContext) {
+        self.textures = Textures::new();
+        self.font_atlas = None;
+        self.last_stats = None;
+        self.last_image = None;
+        ctx.set_renderer_name(None);
+    }
+}
+
+// The inherent methods can't fail and render returns what was drawn, so tests can use them
+// without going through the trait
+impl RendererBackend for HeadlessRenderer {
+    fn render(&mut self, draw_data: &DrawData) -> windows_core::Result<()> {
+        HeadlessRenderer::render(self, draw_data);
+        Ok(())
+    }
+
+    fn rebuild_font_texture(&mut self, ctx: &mut ImContext) -> windows_core::Result<()> {
+        HeadlessRenderer::rebuild_font_texture(self, ctx);
+        Ok(())
+    }
+
+    fn create_texture(&mut self, image: &CpuImage) -> windows_core::Result<TextureId> {
+        Ok(HeadlessRenderer::create_texture(self, image))
+    }
+
+    fn destroy_texture(&mut self, id: TextureId) -> bool {
+        self.unregister_texture(id)
+    }
+
+    fn shutdown(&mut self, ctx: &mut ImContext) -> windows_core::Result<()> {
+        HeadlessRenderer::shutdown(self, ctx);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
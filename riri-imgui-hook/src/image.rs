@@ -0,0 +1,716 @@
+// Decode PNG, TGA and DDS files into images that can be uploaded by either renderer. Nothing
+// in here touches the graphics API so it can be used (and tested) outside of the game.
+use std::{
+    error::Error,
+    fmt::Display,
+    path::Path
+};
+#[cfg(windows)]
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT,
+    DXGI_FORMAT_BC1_UNORM,
+    DXGI_FORMAT_BC2_UNORM,
+    DXGI_FORMAT_BC3_UNORM,
+    DXGI_FORMAT_BC4_UNORM,
+    DXGI_FORMAT_BC5_UNORM,
+    DXGI_FORMAT_BC7_UNORM,
+    DXGI_FORMAT_R8G8B8A8_UNORM
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Rgba8,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc7
+}
+impl ImageFormat {
+    pub fn is_compressed(&self) -> bool {
+        *self != Self::Rgba8
+    }
+    // Size of one pixel for uncompressed formats, or one 4x4 block for BC formats
+    pub fn get_block_size(&self) -> usize {
+        match self {
+            Self::Rgba8 => 4,
+            Self::Bc1 | Self::Bc4 => 8,
+            Self::Bc2 | Self::Bc3 | Self::Bc5 | Self::Bc7 => 16
+        }
+    }
+    pub fn get_row_pitch(&self, width: u32) -> usize {
+        match self.is_compressed() {
+            true => (width as usize).div_ceil(4) * self.get_block_size(),
+            false => width as usize * self.get_block_size()
+        }
+    }
+    pub fn get_row_count(&self, height: u32) -> usize {
+        match self.is_compressed() {
+            true => (height as usize).div_ceil(4),
+            false => height as usize
+        }
+    }
+    // Always UNORM, even for sRGB images. The pixel shaders treat every texture sample as gamma
+    // space, the same as the font atlas, so sampling through an _SRGB view would draw darker.
+    #[cfg(windows)]
+    pub fn get_dxgi_format(&self) -> DXGI_FORMAT {
+        match self {
+            Self::Rgba8 => DXGI_FORMAT_R8G8B8A8_UNORM,
+            Self::Bc1 => DXGI_FORMAT_BC1_UNORM,
+            Self::Bc2 => DXGI_FORMAT_BC2_UNORM,
+            Self::Bc3 => DXGI_FORMAT_BC3_UNORM,
+            Self::Bc4 => DXGI_FORMAT_BC4_UNORM,
+            Self::Bc5 => DXGI_FORMAT_BC5_UNORM,
+            Self::Bc7 => DXGI_FORMAT_BC7_UNORM
+        }
+    }
+}
+
+// width * height * pixel_size, checked before anything is allocated for the image
+fn get_image_size(width: u32, height: u32, pixel_size: usize) -> Result<usize, ImageError> {
+    (width as usize).checked_mul(height as usize)
+        .and_then(|v| v.checked_mul(pixel_size))
+        .ok_or_else(|| ImageError::Invalid(format!("Image is too large ({}x{})", width, height)))
+}
+
+/// A decoded image ready to be uploaded to the GPU. Rows are tightly packed, so the data is
+/// always `format.get_row_pitch(width) * format.get_row_count(height)` bytes long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuImage {
+    width: u32,
+    height: u32,
+    format: ImageFormat,
+    // The file said its color data is sRGB encoded. This is only kept for reference, textures
+    // are uploaded as UNORM either way (see ImageFormat::get_dxgi_format).
+    srgb: bool,
+    data: Vec<u8>
+}
+impl CpuImage {
+    pub fn new(width: u32, height: u32, format: ImageFormat, data: Vec<u8>) -> Result<Self, ImageError> {
+        if width == 0 || height == 0 {
+            return Err(ImageError::Invalid(format!("Image has no size ({}x{})", width, height)));
+        }
+        // Direct3D needs the top level of a block compressed texture to be whole blocks
+        if format.is_compressed() && (width % 4 != 0 || height % 4 != 0) {
+            return Err(ImageError::Unsupported(format!("Block compressed images that aren't a multiple of 4 pixels wide and high ({}x{})", width, height)));
+        }
+        let expected = format.get_row_pitch(width).checked_mul(format.get_row_count(height))
+            .ok_or_else(|| ImageError::Invalid(format!("Image is too large ({}x{})", width, height)))?;
+        if data.len() < expected {
+            return Err(ImageError::Truncated);
+        }
+        let mut data = data;
+        data.truncate(expected);
+        Ok(Self { width, height, format, srgb: false, data })
+    }
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+    pub fn from_rgba(width: u32, height: u32, data: &[u8]) -> Result<Self, ImageError> {
+        Self::new(width, height, ImageFormat::Rgba8, data.to_vec())
+    }
+    pub fn get_width(&self) -> u32 { self.width }
+    pub fn get_height(&self) -> u32 { self.height }
+    pub fn get_format(&self) -> ImageFormat { self.format }
+    pub fn is_srgb(&self) -> bool { self.srgb }
+    #[cfg(windows)]
+    pub fn get_dxgi_format(&self) -> DXGI_FORMAT { self.format.get_dxgi_format() }
+    pub fn get_data(&self) -> &[u8] { &self.data }
+    pub fn get_row_pitch(&self) -> usize { self.format.get_row_pitch(self.width) }
+    pub fn get_row_count(&self) -> usize { self.format.get_row_count(self.height) }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    UnknownFormat,
+    Truncated,
+    Invalid(String),
+    Unsupported(String),
+    Png(png::DecodingError)
+}
+impl Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::UnknownFormat => write!(f, "Not a PNG, TGA or DDS file"),
+            Self::Truncated => write!(f, "Image data is truncated"),
+            Self::Invalid(s) => write!(f, "Invalid image: {}", s),
+            Self::Unsupported(s) => write!(f, "Unsupported image: {}", s),
+            Self::Png(e) => write!(f, "PNG error: {}", e)
+        }
+    }
+}
+impl Error for ImageError {}
+impl From<std::io::Error> for ImageError {
+    fn from(value: std::io::Error) -> Self { Self::Io(value) }
+}
+impl From<png::DecodingError> for ImageError {
+    fn from(value: png::DecodingError) -> Self { Self::Png(value) }
+}
+
+const PNG_MAGIC: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+const DDS_MAGIC: &'static [u8] = b"DDS ";
+
+/// Decode a PNG, TGA or DDS image. PNG and DDS are detected from their magic, anything else is
+/// read as TGA since it doesn't have one.
+pub fn decode_image(bytes: &[u8]) -> Result<CpuImage, ImageError> {
+    if bytes.starts_with(PNG_MAGIC) {
+        decode_png(bytes)
+    } else if bytes.starts_with(DDS_MAGIC) {
+        decode_dds(bytes)
+    } else {
+        decode_tga(bytes)
+    }
+}
+
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<CpuImage, ImageError> {
+    decode_image(&std::fs::read(path)?)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ImageError> {
+    bytes.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(ImageError::Truncated)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ImageError> {
+    bytes.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(ImageError::Truncated)
+}
+
+// PNG
+
+pub fn decode_png(bytes: &[u8]) -> Result<CpuImage, ImageError> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Expand palettes, low bit depths and tRNS chunks, and drop 16-bit channels down to 8 bits
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let pixels = &buf[..info.buffer_size()];
+    let data = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 0xff]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|p| [*p, *p, *p, 0xff]).collect(),
+        png::ColorType::Indexed => return Err(ImageError::Unsupported("PNG palette was not expanded".to_owned()))
+    };
+    CpuImage::new(info.width, info.height, ImageFormat::Rgba8, data)
+}
+
+// TGA
+
+const TGA_HEADER_SIZE: usize = 18;
+
+// Read a single pixel of the given bit depth into RGBA. TGA stores color channels as BGR(A).
+fn tga_pixel(src: &[u8], depth: u8) -> [u8; 4] {
+    match depth {
+        8 => [src[0], src[0], src[0], 0xff],
+        15 | 16 => {
+            let v = u16::from_le_bytes([src[0], src[1]]);
+            let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+            // The attribute bit is unreliable in practice, treat 16-bit images as opaque
+            [expand((v >> 10) & 0x1f), expand((v >> 5) & 0x1f), expand(v & 0x1f), 0xff]
+        },
+        24 => [src[2], src[1], src[0], 0xff],
+        _ => [src[2], src[1], src[0], src[3]]
+    }
+}
+
+pub fn decode_tga(bytes: &[u8]) -> Result<CpuImage, ImageError> {
+    if bytes.len() < TGA_HEADER_SIZE {
+        return Err(ImageError::UnknownFormat);
+    }
+    let id_length = bytes[0] as usize;
+    let color_map_type = bytes[1];
+    let image_type = bytes[2];
+    let map_first = read_u16(bytes, 3)? as usize;
+    let map_length = read_u16(bytes, 5)? as usize;
+    let map_depth = bytes[7];
+    let width = read_u16(bytes, 12)? as u32;
+    let height = read_u16(bytes, 14)? as u32;
+    let depth = bytes[16];
+    let descriptor = bytes[17];
+    if color_map_type > 1 {
+        return Err(ImageError::UnknownFormat);
+    }
+    // 1: color mapped, 2: true color, 3: grayscale, 9-11: RLE compressed versions of those
+    let rle = image_type & 8 != 0;
+    let valid_depth = match image_type & !8 {
+        1 => color_map_type == 1 && depth == 8 && matches!(map_depth, 15 | 16 | 24 | 32),
+        2 => matches!(depth, 15 | 16 | 24 | 32),
+        3 => depth == 8,
+        _ => return Err(ImageError::UnknownFormat)
+    };
+    if !valid_depth {
+        return Err(ImageError::Unsupported(format!("TGA image type {} with {} bits per pixel", image_type, depth)));
+    }
+    let mut cursor = TGA_HEADER_SIZE + id_length;
+    // Read the color map, if there is one. Index values start at map_first. True color images
+    // are allowed to carry a color map too, which is skipped.
+    let map_size = map_length * (map_depth as usize).div_ceil(8);
+    let map_bytes = match color_map_type {
+        1 => bytes.get(cursor..cursor + map_size).ok_or(ImageError::Truncated)?,
+        _ => &[]
+    };
+    let palette: Vec<[u8; 4]> = match image_type & !8 {
+        1 => map_bytes.chunks_exact((map_depth as usize).div_ceil(8)).map(|p| tga_pixel(p, map_depth)).collect(),
+        _ => vec![]
+    };
+    cursor += map_bytes.len();
+    let pixel_size = (depth as usize).div_ceil(8);
+    let pixel_count = get_image_size(width, height, 1)?;
+    let remaining = bytes.len().saturating_sub(cursor);
+    // Make sure the file could hold that many pixels before reserving space for them. At best,
+    // a run-length packet encodes 128 pixels in 1 + pixel_size bytes.
+    let max_pixels = match rle {
+        true => remaining / (1 + pixel_size) * 128,
+        false => remaining / pixel_size
+    };
+    if pixel_count > max_pixels {
+        return Err(ImageError::Truncated);
+    }
+    let read_pixel = |src: &[u8]| -> Result<[u8; 4], ImageError> {
+        if image_type & !8 == 1 {
+            let index = (src[0] as usize).checked_sub(map_first)
+                .ok_or_else(|| ImageError::Invalid(format!("Color map index {} is out of range", src[0])))?;
+            palette.get(index).copied()
+                .ok_or_else(|| ImageError::Invalid(format!("Color map index {} is out of range", src[0])))
+        } else {
+            Ok(tga_pixel(src, depth))
+        }
+    };
+    let mut pixels: Vec<[u8; 4]> = Vec::with_capacity(pixel_count);
+    if rle {
+        while pixels.len() < pixel_count {
+            let packet = *bytes.get(cursor).ok_or(ImageError::Truncated)?;
+            cursor += 1;
+            let count = (packet & 0x7f) as usize + 1;
+            if packet & 0x80 != 0 {
+                // Run-length packet: one pixel value repeated
+                let src = bytes.get(cursor..cursor + pixel_size).ok_or(ImageError::Truncated)?;
+                let pixel = read_pixel(src)?;
+                pixels.extend(std::iter::repeat_n(pixel, count));
+                cursor += pixel_size;
+            } else {
+                // Raw packet
+                let src = bytes.get(cursor..cursor + pixel_size * count).ok_or(ImageError::Truncated)?;
+                for p in src.chunks_exact(pixel_size) {
+                    pixels.push(read_pixel(p)?);
+                }
+                cursor += src.len();
+            }
+        }
+        pixels.truncate(pixel_count);
+    } else {
+        let src = bytes.get(cursor..cursor + pixel_size * pixel_count).ok_or(ImageError::Truncated)?;
+        for p in src.chunks_exact(pixel_size) {
+            pixels.push(read_pixel(p)?);
+        }
+    }
+    // Images are stored bottom to top unless bit 5 of the descriptor is set. Bit 4 flips the
+    // image horizontally.
+    let (w, h) = (width as usize, height as usize);
+    let mut data = Vec::with_capacity(get_image_size(width, height, 4)?);
+    for y in 0..h {
+        let src_y = if descriptor & 0x20 != 0 { y } else { h - 1 - y };
+        for x in 0..w {
+            let src_x = if descriptor & 0x10 != 0 { w - 1 - x } else { x };
+            data.extend_from_slice(&pixels[src_y * w + src_x]);
+        }
+    }
+    CpuImage::new(width, height, ImageFormat::Rgba8, data)
+}
+
+// DDS
+
+const DDS_HEADER_SIZE: usize = 124;
+const DDS_HEADER_DXT10_SIZE: usize = 20;
+// Offsets from the start of the file, including the 4 byte magic
+const DDS_HEIGHT: usize = 12;
+const DDS_WIDTH: usize = 16;
+const DDS_PF_FLAGS: usize = 80;
+const DDS_PF_FOURCC: usize = 84;
+const DDS_PF_BIT_COUNT: usize = 88;
+const DDS_PF_MASKS: usize = 92;
+const DDS_CAPS2: usize = 112;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+fn dds_format_from_fourcc(fourcc: &[u8]) -> Option<ImageFormat> {
+    match fourcc {
+        b"DXT1" => Some(ImageFormat::Bc1),
+        b"DXT2" | b"DXT3" => Some(ImageFormat::Bc2),
+        b"DXT4" | b"DXT5" => Some(ImageFormat::Bc3),
+        b"ATI1" | b"BC4U" => Some(ImageFormat::Bc4),
+        b"ATI2" | b"BC5U" => Some(ImageFormat::Bc5),
+        _ => None
+    }
+}
+
+// Formats that can appear in a DX10 extended header, and whether they're sRGB. Returns None
+// for RGBA8 since that is handled separately depending on the channel order.
+fn dds_format_from_dxgi(format: u32) -> Option<(ImageFormat, bool)> {
+    match format {
+        71 => Some((ImageFormat::Bc1, false)),
+        72 => Some((ImageFormat::Bc1, true)),
+        74 => Some((ImageFormat::Bc2, false)),
+        75 => Some((ImageFormat::Bc2, true)),
+        77 => Some((ImageFormat::Bc3, false)),
+        78 => Some((ImageFormat::Bc3, true)),
+        80 => Some((ImageFormat::Bc4, false)),
+        83 => Some((ImageFormat::Bc5, false)),
+        98 => Some((ImageFormat::Bc7, false)),
+        99 => Some((ImageFormat::Bc7, true)),
+        _ => None
+    }
+}
+
+// Extract a channel described by a bit mask and scale it to 8 bits
+fn extract_channel(pixel: u32, mask: u32) -> Option<u8> {
+    if mask == 0 {
+        return None;
+    }
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+    let value = (pixel & mask) >> shift;
+    Some(((value as u64 * 255 + max as u64 / 2) / max as u64) as u8)
+}
+
+// Convert an uncompressed pixel format described by bit masks into RGBA8
+fn dds_convert_masked(
+    src: &[u8], width: u32, height: u32, bit_count: u32, masks: [u32; 4], flags: u32
+) -> Result<Vec<u8>, ImageError> {
+    let pixel_size = match bit_count {
+        8 | 16 | 24 | 32 => bit_count as usize / 8,
+        _ => return Err(ImageError::Unsupported(format!("DDS with {} bits per pixel", bit_count)))
+    };
+    let src = src.get(..get_image_size(width, height, pixel_size)?).ok_or(ImageError::Truncated)?;
+    let alpha_mask = if flags & DDPF_ALPHAPIXELS != 0 { masks[3] } else { 0 };
+    let mut out = Vec::with_capacity(get_image_size(width, height, 4)?);
+    for p in src.chunks_exact(pixel_size) {
+        let mut bytes = [0u8; 4];
+        bytes[..pixel_size].copy_from_slice(p);
+        let pixel = u32::from_le_bytes(bytes);
+        let a = extract_channel(pixel, alpha_mask).unwrap_or(0xff);
+        if flags & DDPF_LUMINANCE != 0 {
+            let l = extract_channel(pixel, masks[0]).unwrap_or(0);
+            out.extend_from_slice(&[l, l, l, a]);
+        } else {
+            out.extend_from_slice(&[
+                extract_channel(pixel, masks[0]).unwrap_or(0),
+                extract_channel(pixel, masks[1]).unwrap_or(0),
+                extract_channel(pixel, masks[2]).unwrap_or(0),
+                a
+            ]);
+        }
+    }
+    Ok(out)
+}
+
+/// Decode the top level of a 2D DDS texture. Block compressed data is kept as is so it can be
+/// uploaded directly, uncompressed data is converted to RGBA8.
+pub fn decode_dds(bytes: &[u8]) -> Result<CpuImage, ImageError> {
+    if !bytes.starts_with(DDS_MAGIC) {
+        return Err(ImageError::UnknownFormat);
+    }
+    if bytes.len() < 4 + DDS_HEADER_SIZE {
+        return Err(ImageError::Truncated);
+    }
+    if read_u32(bytes, 4)? as usize != DDS_HEADER_SIZE {
+        return Err(ImageError::Invalid("DDS header has the wrong size".to_owned()));
+    }
+    let height = read_u32(bytes, DDS_HEIGHT)?;
+    let width = read_u32(bytes, DDS_WIDTH)?;
+    let pf_flags = read_u32(bytes, DDS_PF_FLAGS)?;
+    let fourcc = &bytes[DDS_PF_FOURCC..DDS_PF_FOURCC + 4];
+    let caps2 = read_u32(bytes, DDS_CAPS2)?;
+    if caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+        return Err(ImageError::Unsupported("DDS cubemaps and volume textures".to_owned()));
+    }
+    let mut data_start = 4 + DDS_HEADER_SIZE;
+    let (format, srgb) = if pf_flags & DDPF_FOURCC != 0 && fourcc == b"DX10" {
+        data_start += DDS_HEADER_DXT10_SIZE;
+        let dxgi_format = read_u32(bytes, 4 + DDS_HEADER_SIZE)?;
+        let dimension = read_u32(bytes, 4 + DDS_HEADER_SIZE + 4)?;
+        let array_size = read_u32(bytes, 4 + DDS_HEADER_SIZE + 12)?;
+        // D3D10_RESOURCE_DIMENSION_TEXTURE2D
+        if dimension != 3 || array_size > 1 {
+            return Err(ImageError::Unsupported("DDS texture arrays and non-2D textures".to_owned()));
+        }
+        let src = bytes.get(data_start..).ok_or(ImageError::Truncated)?;
+        match dxgi_format {
+            // R8G8B8A8_UNORM(_SRGB)
+            28 | 29 => return CpuImage::new(width, height, ImageFormat::Rgba8, src.to_vec())
+                .map(|i| i.with_srgb(dxgi_format == 29)),
+            // B8G8R8A8_UNORM(_SRGB)
+            87 | 91 => return CpuImage::new(width, height, ImageFormat::Rgba8,
+                dds_convert_masked(src, width, height, 32, [0xff0000, 0xff00, 0xff, 0xff000000], DDPF_ALPHAPIXELS)?)
+                .map(|i| i.with_srgb(dxgi_format == 91)),
+            // B8G8R8X8_UNORM(_SRGB)
+            88 | 93 => return CpuImage::new(width, height, ImageFormat::Rgba8,
+                dds_convert_masked(src, width, height, 32, [0xff0000, 0xff00, 0xff, 0], 0)?)
+                .map(|i| i.with_srgb(dxgi_format == 93)),
+            v => dds_format_from_dxgi(v)
+                .ok_or_else(|| ImageError::Unsupported(format!("DDS with DXGI format {}", v)))?
+        }
+    } else if pf_flags & DDPF_FOURCC != 0 {
+        (dds_format_from_fourcc(fourcc)
+            .ok_or_else(|| ImageError::Unsupported(format!("DDS with FourCC {}", String::from_utf8_lossy(fourcc))))?, false)
+    } else if pf_flags & (DDPF_RGB | DDPF_LUMINANCE) != 0 {
+        let bit_count = read_u32(bytes, DDS_PF_BIT_COUNT)?;
+        let masks = [
+            read_u32(bytes, DDS_PF_MASKS)?,
+            read_u32(bytes, DDS_PF_MASKS + 4)?,
+            read_u32(bytes, DDS_PF_MASKS + 8)?,
+            read_u32(bytes, DDS_PF_MASKS + 12)?
+        ];
+        let src = bytes.get(data_start..).ok_or(ImageError::Truncated)?;
+        return CpuImage::new(width, height, ImageFormat::Rgba8,
+            dds_convert_masked(src, width, height, bit_count, masks, pf_flags)?);
+    } else {
+        return Err(ImageError::Unsupported(format!("DDS pixel format flags 0x{:x}", pf_flags)));
+    };
+    let src = bytes.get(data_start..).ok_or(ImageError::Truncated)?;
+    CpuImage::new(width, height, format, src.to_vec()).map(|i| i.with_srgb(srgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    fn tga_header(image_type: u8, width: u16, height: u16, depth: u8, descriptor: u8) -> Vec<u8> {
+        let mut out = vec![0u8; TGA_HEADER_SIZE];
+        out[2] = image_type;
+        out[12..14].copy_from_slice(&width.to_le_bytes());
+        out[14..16].copy_from_slice(&height.to_le_bytes());
+        out[16] = depth;
+        out[17] = descriptor;
+        out
+    }
+
+    fn dds_header(width: u32, height: u32, pf_flags: u32, fourcc: &[u8; 4]) -> Vec<u8> {
+        let mut out = DDS_MAGIC.to_vec();
+        out.resize(4 + DDS_HEADER_SIZE, 0);
+        out[4..8].copy_from_slice(&(DDS_HEADER_SIZE as u32).to_le_bytes());
+        out[DDS_HEIGHT..DDS_HEIGHT + 4].copy_from_slice(&height.to_le_bytes());
+        out[DDS_WIDTH..DDS_WIDTH + 4].copy_from_slice(&width.to_le_bytes());
+        out[DDS_PF_FLAGS..DDS_PF_FLAGS + 4].copy_from_slice(&pf_flags.to_le_bytes());
+        out[DDS_PF_FOURCC..DDS_PF_FOURCC + 4].copy_from_slice(fourcc);
+        out
+    }
+
+    fn dds_dx10_header(width: u32, height: u32, dxgi_format: u32) -> Vec<u8> {
+        let mut out = dds_header(width, height, DDPF_FOURCC, b"DX10");
+        for v in [dxgi_format, 3, 0, 1, 0] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn png_rgba() {
+        let pixels = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        let image = decode_image(&encode_png(2, 2, png::ColorType::Rgba, &pixels)).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (2, 2));
+        assert_eq!(image.get_format(), ImageFormat::Rgba8);
+        assert_eq!(image.get_data(), &pixels);
+    }
+
+    #[test]
+    fn png_rgb_and_grayscale() {
+        let image = decode_png(&encode_png(2, 1, png::ColorType::Rgb, &[1, 2, 3, 4, 5, 6])).unwrap();
+        assert_eq!(image.get_data(), &[1, 2, 3, 0xff, 4, 5, 6, 0xff]);
+        let image = decode_png(&encode_png(2, 1, png::ColorType::GrayscaleAlpha, &[1, 2, 3, 4])).unwrap();
+        assert_eq!(image.get_data(), &[1, 1, 1, 2, 3, 3, 3, 4]);
+    }
+
+    #[test]
+    fn png_truncated() {
+        let bytes = encode_png(4, 4, png::ColorType::Rgba, &[0x80; 64]);
+        assert!(decode_image(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn tga_true_color() {
+        // Bottom to top, so the first pixel in the file is the bottom left
+        let mut bytes = tga_header(2, 2, 2, 32, 0);
+        bytes.extend_from_slice(&[
+            3, 2, 1, 4,  7, 6, 5, 8,
+            11, 10, 9, 12,  15, 14, 13, 16
+        ]);
+        let image = decode_image(&bytes).unwrap();
+        assert_eq!(image.get_data(), &[9, 10, 11, 12, 13, 14, 15, 16, 1, 2, 3, 4, 5, 6, 7, 8]);
+        // Top to bottom, flipped horizontally
+        bytes[17] = 0x30;
+        let image = decode_image(&bytes).unwrap();
+        assert_eq!(image.get_data(), &[5, 6, 7, 8, 1, 2, 3, 4, 13, 14, 15, 16, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn tga_rle() {
+        let mut bytes = tga_header(10, 3, 1, 24, 0x20);
+        // Run of two, then one raw pixel
+        bytes.extend_from_slice(&[0x81, 3, 2, 1, 0x00, 6, 5, 4]);
+        let image = decode_tga(&bytes).unwrap();
+        assert_eq!(image.get_data(), &[1, 2, 3, 0xff, 1, 2, 3, 0xff, 4, 5, 6, 0xff]);
+    }
+
+    #[test]
+    fn tga_color_mapped() {
+        let mut bytes = tga_header(1, 2, 1, 8, 0x20);
+        bytes[1] = 1;
+        bytes[5..7].copy_from_slice(&2u16.to_le_bytes());
+        bytes[7] = 24;
+        bytes.extend_from_slice(&[3, 2, 1, 6, 5, 4]);
+        bytes.extend_from_slice(&[1, 0]);
+        let image = decode_tga(&bytes).unwrap();
+        assert_eq!(image.get_data(), &[4, 5, 6, 0xff, 1, 2, 3, 0xff]);
+        // Index past the end of the color map
+        *bytes.last_mut().unwrap() = 2;
+        assert!(matches!(decode_tga(&bytes), Err(ImageError::Invalid(_))));
+    }
+
+    #[test]
+    fn tga_truncated() {
+        assert!(matches!(decode_image(&[0u8; 4]), Err(ImageError::UnknownFormat)));
+        let mut bytes = tga_header(2, 2, 2, 24, 0);
+        bytes.extend_from_slice(&[0; 11]);
+        assert!(matches!(decode_tga(&bytes), Err(ImageError::Truncated)));
+        let mut bytes = tga_header(10, 2, 2, 24, 0);
+        bytes.extend_from_slice(&[0x82, 0, 0, 0]);
+        assert!(matches!(decode_tga(&bytes), Err(ImageError::Truncated)));
+    }
+
+    #[test]
+    fn tga_header_larger_than_file() {
+        // 65535x65535 would need 17GB for the pixels. This has to fail before anything is
+        // reserved for them.
+        for image_type in [2, 10] {
+            let mut bytes = tga_header(image_type, u16::MAX, u16::MAX, 32, 0);
+            bytes.extend_from_slice(&[0xff; 64]);
+            assert!(matches!(decode_tga(&bytes), Err(ImageError::Truncated)));
+        }
+    }
+
+    #[test]
+    fn dds_block_compressed() {
+        let mut bytes = dds_header(8, 4, DDPF_FOURCC, b"DXT1");
+        bytes.extend_from_slice(&[0xab; 16]);
+        let image = decode_image(&bytes).unwrap();
+        assert_eq!(image.get_format(), ImageFormat::Bc1);
+        assert_eq!((image.get_row_pitch(), image.get_row_count()), (16, 1));
+        assert!(!image.is_srgb());
+        assert_eq!(image.get_data().len(), 16);
+    }
+
+    #[test]
+    fn dds_block_compressed_partial_blocks() {
+        let mut bytes = dds_header(30, 30, DDPF_FOURCC, b"DXT1");
+        bytes.extend_from_slice(&[0; 512]);
+        assert!(matches!(decode_dds(&bytes), Err(ImageError::Unsupported(_))));
+        let mut bytes = dds_dx10_header(32, 6, 98);
+        bytes.extend_from_slice(&[0; 512]);
+        assert!(matches!(decode_dds(&bytes), Err(ImageError::Unsupported(_))));
+        // Uncompressed images can be any size
+        let mut bytes = dds_dx10_header(3, 3, 28);
+        bytes.extend_from_slice(&[0; 36]);
+        assert!(decode_dds(&bytes).is_ok());
+    }
+
+    #[test]
+    fn dds_srgb() {
+        for (dxgi_format, format, srgb) in [
+            (28, ImageFormat::Rgba8, false),
+            (29, ImageFormat::Rgba8, true),
+            (87, ImageFormat::Rgba8, false),
+            (91, ImageFormat::Rgba8, true),
+            (88, ImageFormat::Rgba8, false),
+            (93, ImageFormat::Rgba8, true),
+            (71, ImageFormat::Bc1, false),
+            (72, ImageFormat::Bc1, true),
+            (98, ImageFormat::Bc7, false),
+            (99, ImageFormat::Bc7, true),
+        ] {
+            let mut bytes = dds_dx10_header(4, 4, dxgi_format);
+            bytes.extend_from_slice(&[0x40; 64]);
+            let image = decode_dds(&bytes).unwrap();
+            assert_eq!((image.get_format(), image.is_srgb()), (format, srgb), "DXGI format {}", dxgi_format);
+        }
+    }
+
+    #[test]
+    fn dds_bgra_swizzle() {
+        let mut bytes = dds_dx10_header(1, 1, 87);
+        bytes.extend_from_slice(&[3, 2, 1, 4]);
+        assert_eq!(decode_dds(&bytes).unwrap().get_data(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn dds_masked() {
+        // R5G6B5
+        let mut bytes = dds_header(1, 1, DDPF_RGB, &[0; 4]);
+        bytes[DDS_PF_BIT_COUNT..DDS_PF_BIT_COUNT + 4].copy_from_slice(&16u32.to_le_bytes());
+        for (i, mask) in [0xf800u32, 0x7e0, 0x1f, 0].into_iter().enumerate() {
+            bytes[DDS_PF_MASKS + i * 4..DDS_PF_MASKS + i * 4 + 4].copy_from_slice(&mask.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0xf81fu16.to_le_bytes());
+        assert_eq!(decode_dds(&bytes).unwrap().get_data(), &[0xff, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn dds_truncated() {
+        let bytes = dds_header(4, 4, DDPF_FOURCC, b"DXT5");
+        assert!(matches!(decode_dds(&bytes[..64]), Err(ImageError::Truncated)));
+        let mut bytes = dds_header(8, 8, DDPF_FOURCC, b"DXT5");
+        bytes.extend_from_slice(&[0; 32]);
+        assert!(matches!(decode_dds(&bytes), Err(ImageError::Truncated)));
+        let mut bytes = dds_dx10_header(2, 2, 28);
+        bytes.extend_from_slice(&[0; 15]);
+        assert!(matches!(decode_dds(&bytes), Err(ImageError::Truncated)));
+    }
+
+    #[test]
+    fn dds_header_larger_than_file() {
+        let mut bytes = dds_dx10_header(u32::MAX, u32::MAX, 87);
+        bytes.extend_from_slice(&[0; 64]);
+        assert!(decode_dds(&bytes).is_err());
+        let mut bytes = dds_header(u32::MAX & !3, u32::MAX & !3, DDPF_FOURCC, b"DXT1");
+        bytes.extend_from_slice(&[0; 64]);
+        assert!(decode_dds(&bytes).is_err());
+    }
+
+    #[test]
+    fn dds_unsupported() {
+        let mut bytes = dds_dx10_header(4, 4, 2);
+        bytes.extend_from_slice(&[0; 256]);
+        assert!(matches!(decode_dds(&bytes), Err(ImageError::Unsupported(_))));
+    }
+
+    #[test]
+    fn rgba_size_check() {
+        assert!(CpuImage::from_rgba(2, 2, &[0; 16]).is_ok());
+        assert!(matches!(CpuImage::from_rgba(2, 2, &[0; 15]), Err(ImageError::Truncated)));
+        assert!(matches!(CpuImage::from_rgba(0, 2, &[]), Err(ImageError::Invalid(_))));
+    }
+}
@@ -1,4 +1,6 @@
+pub mod callback;
 pub mod config;
+#[cfg(windows)]
 pub mod d3d11_impl {
     pub mod backup;
     pub mod buffer;
@@ -7,7 +9,9 @@ pub mod d3d11_impl {
     pub mod init;
     pub mod shader;
     pub mod state;
+    pub mod texture;
 }
+#[cfg(windows)]
 pub mod d3d12_impl {
     pub mod buffer;
     pub mod font;
@@ -15,10 +19,31 @@ pub mod d3d12_impl {
     pub mod pipeline;
     pub mod signature;
     pub mod state;
+    pub mod texture;
 }
+pub mod detect;
+pub mod fonts;
 pub mod globals;
+pub mod headless_impl {
+    pub mod harness;
+    pub mod platform;
+    pub mod renderer;
+}
+pub mod image;
+pub mod overlay;
+pub mod rasterizer;
 pub mod registry;
+pub mod renderer;
 pub mod win32_impl {
+    pub mod clipboard;
+    pub mod cursor;
+    pub mod dpi;
+    pub mod gamepad;
+    pub mod ime;
+    pub mod keys;
+    pub mod mouse;
+    #[cfg(windows)]
     pub mod state;
+    #[cfg(windows)]
     pub mod window;
 }
\ No newline at end of file
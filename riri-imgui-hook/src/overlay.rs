@@ -0,0 +1,353 @@
+use crate::config::ConfigParseError;
+use imgui::Key;
+use riri_mod_tools_rt::logln;
+use serde::Deserialize;
+use std::path::Path;
+use toml::Spanned;
+
+/// How the overlay is shown and whether it takes input from the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OverlayMode {
+    /// Drawn, and ImGui receives all mouse and keyboard input. None of it reaches the game.
+    Interactive,
+    /// Drawn, but all input goes to the game
+    Passive,
+    /// GUI callbacks aren't run and all input goes to the game
+    Hidden
+}
+impl OverlayMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Interactive" => Some(Self::Interactive),
+            "Passive" => Some(Self::Passive),
+            "Hidden" => Some(Self::Hidden),
+            _ => None
+        }
+    }
+    pub fn is_visible(&self) -> bool { *self != Self::Hidden }
+    pub fn is_interactive(&self) -> bool { *self == Self::Interactive }
+    /// Returns true if a window message should be kept from the game in this mode
+    pub fn captures_message(&self, umsg: u32) -> bool {
+        self.is_interactive() && is_input_message(umsg)
+    }
+}
+
+// Message ranges from WinUser.h
+const WM_INPUT: u32 = 0x00FF;
+const WM_KEYFIRST: u32 = 0x0100;
+const WM_KEYLAST: u32 = 0x0109;
+const WM_MOUSEFIRST: u32 = 0x0200;
+const WM_MOUSELAST: u32 = 0x020E;
+
+/// Returns true for mouse and keyboard messages in the client area, including raw input
+/// and character messages
+pub fn is_input_message(umsg: u32) -> bool {
+    umsg == WM_INPUT || (WM_KEYFIRST..=WM_KEYLAST).contains(&umsg) || (WM_MOUSEFIRST..=WM_MOUSELAST).contains(&umsg)
+}
+
+/// Modifier keys held when a hotkey was pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyModifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool
+}
+
+/// A key plus the modifiers that must be held with it. Modifiers have to match exactly, so
+/// Insert doesn't also trigger on Shift+Insert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    key: Key,
+    modifiers: KeyModifiers
+}
+impl Hotkey {
+    pub const fn new(key: Key, modifiers: KeyModifiers) -> Self {
+        Self { key, modifiers }
+    }
+
+    /// Parse a hotkey written like "Ctrl+Shift+F1". Key names are ImGuiKey names without
+    /// the prefix (Insert, F1, A, Keypad0...)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::default();
+        let mut parts: Vec<&str> = name.split('+').map(|p| p.trim()).collect();
+        let key_name = parts.pop()?;
+        for part in parts {
+            let modifier = match part {
+                "Ctrl" => &mut modifiers.ctrl,
+                "Shift" => &mut modifiers.shift,
+                "Alt" => &mut modifiers.alt,
+                _ => return None
+            };
+            if *modifier {
+                return None;
+            }
+            *modifier = true;
+        }
+        let key = Key::VARIANTS.iter().find(|k| format!("{:?}", k) == key_name)?;
+        Some(Self { key: *key, modifiers })
+    }
+
+    pub fn get_key(&self) -> Key { self.key }
+    pub fn get_modifiers(&self) -> KeyModifiers { self.modifiers }
+
+    pub fn matches(&self, key: Key, modifiers: KeyModifiers) -> bool {
+        self.key == key && self.modifiers == modifiers
+    }
+}
+
+/// Settings from overlay.toml
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayConfig {
+    toggle_key: Hotkey,
+    mode_key: Option<Hotkey>,
+    start_mode: OverlayMode
+}
+impl OverlayConfig {
+    pub fn get_toggle_key(&self) -> Hotkey { self.toggle_key }
+    pub fn get_mode_key(&self) -> Option<Hotkey> { self.mode_key }
+    pub fn get_start_mode(&self) -> OverlayMode { self.start_mode }
+}
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            toggle_key: Hotkey::new(Key::Insert, KeyModifiers { ctrl: false, shift: false, alt: false }),
+            mode_key: Some(Hotkey::new(Key::Insert, KeyModifiers { ctrl: false, shift: true, alt: false })),
+            // Input keeps going to the game until the user switches to interactive
+            start_mode: OverlayMode::Passive
+        }
+    }
+}
+
+pub const OVERLAY_FILE_NAME: &'static str = "overlay.toml";
+
+// Layout of overlay.toml. toggle_key shows and hides the overlay, mode_key switches between
+// interactive and passive while it's shown.
+//
+// toggle_key = "Insert"
+// mode_key = "Shift+Insert"
+// start_mode = "Passive"
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverlayFile {
+    toggle_key: Option<Spanned<String>>,
+    mode_key: Option<Spanned<String>>,
+    start_mode: Option<Spanned<String>>
+}
+
+pub fn parse_overlay_config(source: &str) -> Result<OverlayConfig, ConfigParseError> {
+    let file: OverlayFile = toml::from_str(source)
+        .map_err(|e| ConfigParseError::new(source, e.span(), e.message().to_owned()))?;
+    let parse_hotkey = |name: &Spanned<String>| Hotkey::from_name(name.get_ref())
+        .ok_or_else(|| ConfigParseError::new(source, Some(name.span()),
+            format!("Unknown hotkey \"{}\"", name.get_ref())));
+    let mut out = OverlayConfig::default();
+    if let Some(name) = &file.toggle_key {
+        out.toggle_key = parse_hotkey(name)?;
+    }
+    if let Some(name) = &file.mode_key {
+        // An empty string turns the mode switch off
+        out.mode_key = match name.get_ref().is_empty() {
+            true => None,
+            false => Some(parse_hotkey(name)?)
+        };
+    }
+    if out.mode_key == Some(out.toggle_key) {
+        return Err(ConfigParseError::new(source, file.mode_key.as_ref().map(|n| n.span()),
+            "toggle_key and mode_key must be different".to_owned()));
+    }
+    if let Some(name) = &file.start_mode {
+        out.start_mode = OverlayMode::from_name(name.get_ref())
+            .ok_or_else(|| ConfigParseError::new(source, Some(name.span()),
+                format!("Unknown mode \"{}\", expected Interactive, Passive or Hidden", name.get_ref())))?;
+    }
+    Ok(out)
+}
+
+pub fn load_overlay_config<P: AsRef<Path>>(path: P) -> OverlayConfig {
+    let source = match std::fs::read_to_string(path.as_ref()) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return OverlayConfig::default(),
+        Err(e) => {
+            logln!(Error, "Could not read {}: {}. Using default overlay settings", path.as_ref().display(), e);
+            return OverlayConfig::default();
+        }
+    };
+    match parse_overlay_config(&source) {
+        Ok(v) => v,
+        Err(e) => {
+            logln!(Error, "Error in {}: {}. Using default overlay settings", path.as_ref().display(), e);
+            OverlayConfig::default()
+        }
+    }
+}
+
+/// Tracks the overlay mode and switches it when hotkeys are pressed
+#[derive(Debug, Clone)]
+pub struct OverlayState {
+    config: OverlayConfig,
+    mode: OverlayMode,
+    // Mode to go back to when the overlay is shown again
+    last_visible_mode: OverlayMode
+}
+impl OverlayState {
+    pub fn new(config: OverlayConfig) -> Self {
+        let mode = config.start_mode;
+        let last_visible_mode = match mode {
+            OverlayMode::Hidden => OverlayMode::Interactive,
+            v => v
+        };
+        Self { config, mode, last_visible_mode }
+    }
+
+    pub fn get_mode(&self) -> OverlayMode { self.mode }
+    pub fn get_config(&self) -> &OverlayConfig { &self.config }
+
+    /// Returns true if the mode changed
+    pub fn set_mode(&mut self, mode: OverlayMode) -> bool {
+        if mode.is_visible() {
+            self.last_visible_mode = mode;
+        }
+        std::mem::replace(&mut self.mode, mode) != mode
+    }
+
+    /// Show the overlay in the mode it was last shown in, or hide it
+    pub fn toggle_visible(&mut self) -> bool {
+        match self.mode {
+            OverlayMode::Hidden => self.set_mode(self.last_visible_mode),
+            _ => self.set_mode(OverlayMode::Hidden)
+        }
+    }
+
+    /// Switch between interactive and passive. Does nothing while hidden.
+    pub fn toggle_interactive(&mut self) -> bool {
+        match self.mode {
+            OverlayMode::Interactive => self.set_mode(OverlayMode::Passive),
+            OverlayMode::Passive => self.set_mode(OverlayMode::Interactive),
+            OverlayMode::Hidden => false
+        }
+    }
+
+    /// Handle a key press. Returns true if it was one of the overlay's hotkeys, in which case
+    /// the key shouldn't be passed on to ImGui or the game. Auto-repeated presses are consumed
+    /// without toggling again.
+    pub fn on_key_down(&mut self, key: Key, modifiers: KeyModifiers, is_repeat: bool) -> bool {
+        if self.config.toggle_key.matches(key, modifiers) {
+            if !is_repeat {
+                self.toggle_visible();
+            }
+            true
+        } else if self.config.mode_key.is_some_and(|k| k.matches(key, modifiers)) {
+            if !is_repeat {
+                self.toggle_interactive();
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: KeyModifiers = KeyModifiers { ctrl: false, shift: false, alt: false };
+    const SHIFT: KeyModifiers = KeyModifiers { ctrl: false, shift: true, alt: false };
+
+    #[test]
+    fn parse_hotkey() {
+        assert_eq!(Hotkey::from_name("F1"), Some(Hotkey::new(Key::F1, NONE)));
+        assert_eq!(Hotkey::from_name("Ctrl + Alt+Insert"),
+            Some(Hotkey::new(Key::Insert, KeyModifiers { ctrl: true, shift: false, alt: true })));
+        assert_eq!(Hotkey::from_name("Shift+Shift+F1"), None);
+        assert_eq!(Hotkey::from_name("Super+F1"), None);
+        assert_eq!(Hotkey::from_name("NotAKey"), None);
+        assert_eq!(Hotkey::from_name(""), None);
+    }
+
+    #[test]
+    fn hotkey_modifiers_match_exactly() {
+        let hotkey = Hotkey::new(Key::Insert, NONE);
+        assert!(hotkey.matches(Key::Insert, NONE));
+        assert!(!hotkey.matches(Key::Insert, SHIFT));
+        assert!(!hotkey.matches(Key::Delete, NONE));
+    }
+
+    #[test]
+    fn parse_empty_config() {
+        assert_eq!(parse_overlay_config("").unwrap(), OverlayConfig::default());
+    }
+
+    #[test]
+    fn parse_full_config() {
+        let config = parse_overlay_config("toggle_key = \"F2\"\nmode_key = \"\"\nstart_mode = \"Passive\"").unwrap();
+        assert_eq!(config.get_toggle_key(), Hotkey::new(Key::F2, NONE));
+        assert_eq!(config.get_mode_key(), None);
+        assert_eq!(config.get_start_mode(), OverlayMode::Passive);
+    }
+
+    #[test]
+    fn parse_config_errors() {
+        let err = parse_overlay_config("toggle_key = \"F2\"\nstart_mode = \"Fullscreen\"").unwrap_err();
+        assert_eq!(err.get_line(), Some(2));
+        let err = parse_overlay_config("\ntoggle_key = \"Ctrl+Nothing\"").unwrap_err();
+        assert_eq!(err.get_line(), Some(2));
+        let err = parse_overlay_config("toggle_key = \"F3\"\nmode_key = \"F3\"").unwrap_err();
+        assert_eq!(err.get_line(), Some(2));
+        assert!(parse_overlay_config("visible = true").is_err());
+    }
+
+    #[test]
+    fn toggle_remembers_mode() {
+        let mut state = OverlayState::new(OverlayConfig::default());
+        assert_eq!(state.get_mode(), OverlayMode::Passive);
+        assert!(state.toggle_interactive());
+        assert_eq!(state.get_mode(), OverlayMode::Interactive);
+        assert!(state.toggle_visible());
+        assert_eq!(state.get_mode(), OverlayMode::Hidden);
+        assert!(!state.toggle_interactive());
+        assert_eq!(state.get_mode(), OverlayMode::Hidden);
+        assert!(state.toggle_visible());
+        assert_eq!(state.get_mode(), OverlayMode::Interactive);
+        assert!(!state.set_mode(OverlayMode::Interactive));
+    }
+
+    #[test]
+    fn start_hidden() {
+        let config = parse_overlay_config("start_mode = \"Hidden\"").unwrap();
+        let mut state = OverlayState::new(config);
+        assert!(!state.get_mode().is_visible());
+        assert!(state.toggle_visible());
+        assert_eq!(state.get_mode(), OverlayMode::Interactive);
+    }
+
+    #[test]
+    fn hotkeys_switch_mode() {
+        let mut state = OverlayState::new(OverlayConfig::default());
+        assert!(state.on_key_down(Key::Insert, SHIFT, false));
+        assert_eq!(state.get_mode(), OverlayMode::Interactive);
+        assert!(state.on_key_down(Key::Insert, NONE, false));
+        assert_eq!(state.get_mode(), OverlayMode::Hidden);
+        // Held keys are consumed without toggling back
+        assert!(state.on_key_down(Key::Insert, NONE, true));
+        assert_eq!(state.get_mode(), OverlayMode::Hidden);
+        assert!(!state.on_key_down(Key::F1, NONE, false));
+        assert!(!state.on_key_down(Key::Insert, KeyModifiers { ctrl: true, shift: false, alt: false }, false));
+    }
+
+    #[test]
+    fn interactive_captures_all_input() {
+        // WM_INPUT, WM_KEYDOWN, WM_CHAR, WM_SYSKEYUP, WM_MOUSEMOVE, WM_LBUTTONDOWN, WM_MOUSEWHEEL
+        let input = [0x00FF, 0x0100, 0x0102, 0x0105, 0x0200, 0x0201, 0x020A];
+        // WM_SIZE, WM_SETFOCUS, WM_KILLFOCUS, WM_NCMOUSEMOVE, WM_MOUSELEAVE
+        let other = [0x0005, 0x0007, 0x0008, 0x00A0, 0x02A3];
+        for umsg in input {
+            assert!(OverlayMode::Interactive.captures_message(umsg));
+            assert!(!OverlayMode::Passive.captures_message(umsg));
+            assert!(!OverlayMode::Hidden.captures_message(umsg));
+        }
+        for umsg in other {
+            assert!(!OverlayMode::Interactive.captures_message(umsg));
+        }
+    }
+}
@@ -0,0 +1,502 @@
+// Reference rasterizer for DrawData. This follows D3D11Hook's pipeline as closely as possible
+// (projection matrix, vertex/index offsets, scissor rects, sampler, pixel shader and blend
+// state) so images drawn with it can be compared against golden images on machines without
+// Direct3D.
+use crate::{
+    image::{ CpuImage, ImageError, ImageFormat },
+    registry::RegistryFlags
+};
+use glam::{ Mat4, Vec4 };
+use imgui::{
+    DrawCmd,
+    DrawCmdParams,
+    DrawData,
+    DrawIdx,
+    DrawVert,
+    TextureId
+};
+use std::{
+    error::Error,
+    fmt::Display,
+    path::Path
+};
+
+/// Orthographic projection from ImGui's display space to clip space. Our visible imgui space
+/// lies from display_pos (top left) to display_pos + display_size (bottom right). This is the
+/// matrix both renderers upload to the vertex shader's constant buffer.
+pub fn get_projection_matrix(display_pos: [f32; 2], display_size: [f32; 2]) -> Mat4 {
+    let l = display_pos[0];
+    let r = display_pos[0] + display_size[0];
+    let t = display_pos[1];
+    let b = display_pos[1] + display_size[1];
+    Mat4::from_cols(
+        Vec4::new(2.0 / (r - l), 0., 0., 0.,),
+        Vec4::new(0.0, 2.0 / (t - b), 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 0.5, 0.0),
+        Vec4::new((r + l) / (l - r), (t + b) / (b - t), 0.5, 1.0),
+    )
+}
+
+/// One DrawIndexed call. Offsets are into FrameData's combined vertex and index buffers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawElements {
+    pub count: usize,
+    pub index_offset: usize,
+    pub vertex_offset: usize,
+    pub clip_rect: [f32; 4],
+    pub texture_id: TextureId
+}
+
+/// Everything D3D11Hook::render takes from DrawData. Vertex and index buffers from every draw
+/// list are joined together the same way write_buffers uploads them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameData {
+    pub display_pos: [f32; 2],
+    pub display_size: [f32; 2],
+    pub framebuffer_scale: [f32; 2],
+    pub vertices: Vec<DrawVert>,
+    pub indices: Vec<DrawIdx>,
+    pub draws: Vec<DrawElements>
+}
+impl FrameData {
+    pub fn from_draw_data(draw_data: &DrawData) -> Self {
+        let mut out = Self {
+            display_pos: draw_data.display_pos,
+            display_size: draw_data.display_size,
+            framebuffer_scale: draw_data.framebuffer_scale,
+            vertices: Vec::with_capacity(draw_data.total_vtx_count as usize),
+            indices: Vec::with_capacity(draw_data.total_idx_count as usize),
+            draws: vec![]
+        };
+        // Offsets are tracked the same way as in D3D11Hook::render_impl
+        let mut index_offset = 0;
+        for draw_list in draw_data.draw_lists() {
+            let vertex_offset = out.vertices.len();
+            out.vertices.extend_from_slice(draw_list.vtx_buffer());
+            out.indices.extend_from_slice(draw_list.idx_buffer());
+            for cmd in draw_list.commands() {
+                // Render state resets and callbacks are for the GPU renderers
+                if let DrawCmd::Elements { count, cmd_params: DrawCmdParams { clip_rect, texture_id, .. } } = cmd {
+                    out.draws.push(DrawElements { count, index_offset, vertex_offset, clip_rect, texture_id });
+                    index_offset += count;
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug)]
+pub enum RasterError {
+    UnknownTexture(TextureId),
+    UnsupportedTexture(TextureId, ImageFormat)
+}
+impl Display for RasterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTexture(id) => write!(f, "Texture ID {} is not registered with the renderer", id.id()),
+            Self::UnsupportedTexture(id, fmt) => write!(f, "Texture ID {} is {:?}, only Rgba8 can be sampled", id.id(), fmt)
+        }
+    }
+}
+impl Error for RasterError {}
+
+/// RGBA8 image that's drawn into, the equivalent of the swapchain's back buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderTarget {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>
+}
+impl RenderTarget {
+    pub fn new(width: u32, height: u32, clear_color: [u8; 4]) -> Self {
+        Self { width, height, pixels: clear_color.repeat((width * height) as usize) }
+    }
+
+    pub fn get_width(&self) -> u32 { self.width }
+    pub fn get_height(&self) -> u32 { self.height }
+    pub fn get_pixels(&self) -> &[u8] { &self.pixels }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    pub fn clear(&mut self, color: [u8; 4]) {
+        self.pixels.chunks_exact_mut(4).for_each(|p| p.copy_from_slice(&color));
+    }
+
+    pub fn to_image(&self) -> Result<CpuImage, ImageError> {
+        CpuImage::from_rgba(self.width, self.height, &self.pixels)
+    }
+
+    pub fn encode_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut out = vec![];
+        let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(out)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.encode_png()?)?;
+        Ok(())
+    }
+
+    fn blend(&mut self, x: u32, y: u32, src: [f32; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        let dst = &mut self.pixels[i..i + 4];
+        let a = src[3];
+        // SrcBlend = SRC_ALPHA, DestBlend = INV_SRC_ALPHA, SrcBlendAlpha = ONE,
+        // DestBlendAlpha = INV_SRC_ALPHA (see DeviceObjects::new)
+        for (d, s) in dst[..3].iter_mut().zip(&src[..3]) {
+            *d = to_unorm8(s * a + from_unorm8(*d) * (1. - a));
+        }
+        dst[3] = to_unorm8(a + from_unorm8(dst[3]) * (1. - a));
+    }
+}
+
+fn from_unorm8(v: u8) -> f32 { v as f32 / 255. }
+fn to_unorm8(v: f32) -> u8 { (v.clamp(0., 1.) * 255. + 0.5) as u8 }
+
+// MIN_MAG_MIP_LINEAR with WRAP addressing, as in FontObjects::new. ImGui textures don't have
+// mips, so this is plain bilinear filtering.
+fn sample_linear_wrap(image: &CpuImage, uv: [f32; 2]) -> [f32; 4] {
+    let (w, h) = (image.get_width() as i64, image.get_height() as i64);
+    let data = image.get_data();
+    let texel = |x: i64, y: i64| {
+        let i = ((y.rem_euclid(h) * w + x.rem_euclid(w)) * 4) as usize;
+        [from_unorm8(data[i]), from_unorm8(data[i + 1]), from_unorm8(data[i + 2]), from_unorm8(data[i + 3])]
+    };
+    let x = uv[0] * w as f32 - 0.5;
+    let y = uv[1] * h as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let (t00, t10, t01, t11) = (texel(x0, y0), texel(x0 + 1, y0), texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+    std::array::from_fn(|c| {
+        let top = t00[c] + (t10[c] - t00[c]) * fx;
+        let bottom = t01[c] + (t11[c] - t01[c]) * fx;
+        top + (bottom - top) * fy
+    })
+}
+
+// ps_srgb.hlsl's sRGBToLinear
+fn srgb_to_linear(color: [f32; 4]) -> [f32; 4] {
+    [color[0].powf(2.2), color[1].powf(2.2), color[2].powf(2.2), color[3]]
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScreenVertex {
+    pos: [f32; 2],
+    col: [f32; 4],
+    uv: [f32; 2]
+}
+
+fn edge(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+// Top-left fill rule, so pixels on an edge shared by two triangles are only drawn once. For
+// triangles wound so that edge() is positive, top edges point right and left edges point up.
+fn is_top_left(a: [f32; 2], b: [f32; 2]) -> bool {
+    (a[1] == b[1] && b[0] > a[0]) || b[1] < a[1]
+}
+
+/// Draws FrameData the way D3D11Hook does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rasterizer {
+    flags: RegistryFlags
+}
+impl Rasterizer {
+    /// Only RegistryFlags::USE_SRGB changes how things are drawn
+    pub fn new(flags: RegistryFlags) -> Self {
+        Self { flags }
+    }
+
+    /// Draw a frame into a new target that's the size of the display, cleared to transparent
+    /// black
+    pub fn render<'a, F>(&self, frame: &FrameData, get_texture: F) -> (RenderTarget, Result<(), RasterError>)
+    where F: Fn(TextureId) -> Option<&'a CpuImage>
+    {
+        let mut target = RenderTarget::new(frame.display_size[0].max(0.) as u32, frame.display_size[1].max(0.) as u32, [0; 4]);
+        let result = self.render_to(&mut target, frame, get_texture);
+        (target, result)
+    }
+
+    /// Draw a frame on top of what's already in target. Like D3D11Hook::render_impl, draws
+    /// using a missing texture are skipped and reported once everything else has been drawn.
+    pub fn render_to<'a, F>(&self, target: &mut RenderTarget, frame: &FrameData, get_texture: F) -> Result<(), RasterError>
+    where F: Fn(TextureId) -> Option<&'a CpuImage>
+    {
+        if frame.display_size[0] <= 0.0 || frame.display_size[1] <= 0.0 {
+            return Ok(());
+        }
+        let mvp = get_projection_matrix(frame.display_pos, frame.display_size);
+        let clip_off = frame.display_pos;
+        let clip_scale = frame.framebuffer_scale;
+        let mut error = None;
+        for draw in &frame.draws {
+            let texture = match get_texture(draw.texture_id) {
+                Some(t) if t.get_format() == ImageFormat::Rgba8 => t,
+                Some(t) => {
+                    error.get_or_insert(RasterError::UnsupportedTexture(draw.texture_id, t.get_format()));
+                    continue;
+                },
+                None => {
+                    error.get_or_insert(RasterError::UnknownTexture(draw.texture_id));
+                    continue;
+                }
+            };
+            // Same conversion as the RECT passed to RSSetScissorRects
+            let scissor = [
+                ((draw.clip_rect[0] - clip_off[0]) * clip_scale[0]) as i32,
+                ((draw.clip_rect[1] - clip_off[1]) * clip_scale[1]) as i32,
+                ((draw.clip_rect[2] - clip_off[0]) * clip_scale[0]) as i32,
+                ((draw.clip_rect[3] - clip_off[1]) * clip_scale[1]) as i32
+            ];
+            let indices = &frame.indices[draw.index_offset..draw.index_offset + draw.count];
+            for tri in indices.chunks_exact(3) {
+                let [v0, v1, v2] = [tri[0], tri[1], tri[2]].map(|i|
+                    self.transform_vertex(&mvp, frame, &frame.vertices[draw.vertex_offset + i as usize]));
+                self.draw_triangle(target, scissor, texture, v0, v1, v2);
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    // vs.hlsl, then the viewport transform. The viewport covers display_size starting at 0, 0.
+    fn transform_vertex(&self, mvp: &Mat4, frame: &FrameData, vertex: &DrawVert) -> ScreenVertex {
+        let clip = *mvp * Vec4::new(vertex.pos[0], vertex.pos[1], 0., 1.);
+        let ndc = [clip.x / clip.w, clip.y / clip.w];
+        ScreenVertex {
+            pos: [(ndc[0] + 1.) * 0.5 * frame.display_size[0], (1. - ndc[1]) * 0.5 * frame.display_size[1]],
+            col: vertex.col.map(from_unorm8),
+            uv: vertex.uv
+        }
+    }
+
+    // ps.hlsl/ps_srgb.hlsl
+    fn shade(&self, texture: &CpuImage, col: [f32; 4], uv: [f32; 2]) -> [f32; 4] {
+        let tex = sample_linear_wrap(texture, uv);
+        let out = std::array::from_fn(|c| col[c] * tex[c]);
+        match self.flags.contains(RegistryFlags::USE_SRGB) {
+            true => srgb_to_linear(out),
+            false => out
+        }
+    }
+
+    fn draw_triangle(&self, target: &mut RenderTarget, scissor: [i32; 4], texture: &CpuImage,
+        v0: ScreenVertex, v1: ScreenVertex, v2: ScreenVertex) {
+        // CullMode is NONE, so wind every triangle the same way
+        let (v1, v2) = match edge(v0.pos, v1.pos, v2.pos) < 0. {
+            true => (v2, v1),
+            false => (v1, v2)
+        };
+        let area = edge(v0.pos, v1.pos, v2.pos);
+        if area == 0. {
+            return;
+        }
+        let min_x = v0.pos[0].min(v1.pos[0]).min(v2.pos[0]).floor() as i32;
+        let max_x = v0.pos[0].max(v1.pos[0]).max(v2.pos[0]).ceil() as i32;
+        let min_y = v0.pos[1].min(v1.pos[1]).min(v2.pos[1]).floor() as i32;
+        let max_y = v0.pos[1].max(v1.pos[1]).max(v2.pos[1]).ceil() as i32;
+        let x_start = min_x.max(scissor[0]).max(0);
+        let x_end = max_x.min(scissor[2]).min(target.width as i32);
+        let y_start = min_y.max(scissor[1]).max(0);
+        let y_end = max_y.min(scissor[3]).min(target.height as i32);
+        let top_left = [is_top_left(v1.pos, v2.pos), is_top_left(v2.pos, v0.pos), is_top_left(v0.pos, v1.pos)];
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                // Sample at the pixel's center
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let w = [edge(v1.pos, v2.pos, p), edge(v2.pos, v0.pos, p), edge(v0.pos, v1.pos, p)];
+                if (0..3).any(|i| w[i] < 0. || (w[i] == 0. && !top_left[i])) {
+                    continue;
+                }
+                let w = w.map(|v| v / area);
+                let col = std::array::from_fn(|c| v0.col[c] * w[0] + v1.col[c] * w[1] + v2.col[c] * w[2]);
+                let uv = std::array::from_fn(|c| v0.uv[c] * w[0] + v1.uv[c] * w[1] + v2.uv[c] * w[2]);
+                target.blend(x as u32, y as u32, self.shade(texture, col, uv));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ decode_png, load_image };
+
+    // Golden images are checked in here. Run with RIRI_UPDATE_GOLDEN=1 to rewrite them after
+    // an intended change, then look over the diff before committing.
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/rasterizer");
+
+    const WHITE: TextureId = TextureId::new(1);
+    const CHECKER: TextureId = TextureId::new(2);
+
+    fn white_texture() -> CpuImage {
+        CpuImage::from_rgba(1, 1, &[0xff; 4]).unwrap()
+    }
+
+    // 4x4 texels, alternating white and dark red
+    fn checker_texture() -> CpuImage {
+        let data: Vec<u8> = (0..16).flat_map(|i| match (i % 4 + i / 4) % 2 {
+            0 => [0xff, 0xff, 0xff, 0xff],
+            _ => [0x80, 0x10, 0x10, 0xff]
+        }).collect();
+        CpuImage::from_rgba(4, 4, &data).unwrap()
+    }
+
+    fn vert(pos: [f32; 2], uv: [f32; 2], col: [u8; 4]) -> DrawVert {
+        DrawVert { pos, uv, col }
+    }
+
+    fn quad(min: [f32; 2], max: [f32; 2], uv_max: [f32; 2], col: [u8; 4]) -> [DrawVert; 4] {
+        [
+            vert(min, [0., 0.], col),
+            vert([max[0], min[1]], [uv_max[0], 0.], col),
+            vert(max, uv_max, col),
+            vert([min[0], max[1]], [0., uv_max[1]], col)
+        ]
+    }
+
+    // A 32x32 display. Each draw's vertices are appended with their own vertex offset, the
+    // same way separate draw lists are joined in from_draw_data.
+    fn frame(display_pos: [f32; 2]) -> FrameData {
+        FrameData {
+            display_pos,
+            display_size: [32., 32.],
+            framebuffer_scale: [1., 1.],
+            vertices: vec![],
+            indices: vec![],
+            draws: vec![]
+        }
+    }
+
+    fn add_draw(frame: &mut FrameData, vertices: &[DrawVert], indices: &[DrawIdx], clip_rect: [f32; 4], texture_id: TextureId) {
+        frame.draws.push(DrawElements {
+            count: indices.len(),
+            index_offset: frame.indices.len(),
+            vertex_offset: frame.vertices.len(),
+            clip_rect,
+            texture_id
+        });
+        frame.vertices.extend_from_slice(vertices);
+        frame.indices.extend_from_slice(indices);
+    }
+
+    const QUAD_INDICES: [DrawIdx; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn render(flags: RegistryFlags, frame: &FrameData) -> RenderTarget {
+        let (white, checker) = (white_texture(), checker_texture());
+        let (target, result) = Rasterizer::new(flags).render(frame, |id| match id {
+            WHITE => Some(&white),
+            CHECKER => Some(&checker),
+            _ => None
+        });
+        result.unwrap();
+        target
+    }
+
+    fn check_golden(name: &str, target: &RenderTarget) {
+        let path = Path::new(GOLDEN_DIR).join(format!("{}.png", name));
+        if std::env::var_os("RIRI_UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+            target.save_png(&path).unwrap();
+            return;
+        }
+        let expected = load_image(&path).unwrap_or_else(|e| panic!("Couldn't load {}: {}", path.display(), e));
+        let matches = (expected.get_width(), expected.get_height()) == (target.get_width(), target.get_height())
+            && expected.get_data() == target.get_pixels();
+        if !matches {
+            let actual = Path::new(GOLDEN_DIR).join(format!("{}.actual.png", name));
+            target.save_png(&actual).unwrap();
+            panic!("{} doesn't match {}, wrote what was drawn to {}", name, path.display(), actual.display());
+        }
+    }
+
+    #[test]
+    fn encode_png_round_trip() {
+        let mut target = RenderTarget::new(3, 2, [0x10, 0x20, 0x30, 0x40]);
+        target.blend(2, 1, [1., 0., 0., 1.]);
+        let image = decode_png(&target.encode_png().unwrap()).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (3, 2));
+        assert_eq!(image.get_format(), ImageFormat::Rgba8);
+        assert_eq!(image.get_data(), target.get_pixels());
+    }
+
+    #[test]
+    fn solid_quad() {
+        let mut frame = frame([0., 0.]);
+        add_draw(&mut frame, &quad([4., 6.], [20., 26.], [1., 1.], [0x20, 0xc0, 0x40, 0xff]), &QUAD_INDICES, [0., 0., 32., 32.], WHITE);
+        check_golden("solid_quad", &render(RegistryFlags::empty(), &frame));
+    }
+
+    #[test]
+    fn display_pos_offset() {
+        // Moving both the display and the geometry shouldn't change the image
+        let mut frame = frame([100., 50.]);
+        add_draw(&mut frame, &quad([104., 56.], [120., 76.], [1., 1.], [0x20, 0xc0, 0x40, 0xff]), &QUAD_INDICES, [100., 50., 132., 82.], WHITE);
+        check_golden("solid_quad", &render(RegistryFlags::empty(), &frame));
+    }
+
+    #[test]
+    fn scissored_blend() {
+        let mut frame = frame([0., 0.]);
+        add_draw(&mut frame, &quad([0., 0.], [32., 32.], [1., 1.], [0x30, 0x30, 0xa0, 0xff]), &QUAD_INDICES, [0., 0., 32., 32.], WHITE);
+        // Half transparent and cut down to 8..24 on both axes
+        add_draw(&mut frame, &quad([2., 2.], [30., 30.], [1., 1.], [0xff, 0xe0, 0x20, 0x80]), &QUAD_INDICES, [8., 8., 24., 24.], WHITE);
+        check_golden("scissored_blend", &render(RegistryFlags::empty(), &frame));
+    }
+
+    #[test]
+    fn gradient_triangle() {
+        let mut frame = frame([0., 0.]);
+        let vertices = [
+            vert([16., 2.], [0., 0.], [0xff, 0, 0, 0xff]),
+            vert([30., 29.], [0., 0.], [0, 0xff, 0, 0xff]),
+            vert([2., 29.], [0., 0.], [0, 0, 0xff, 0xff])
+        ];
+        add_draw(&mut frame, &vertices, &[0, 1, 2], [0., 0., 32., 32.], WHITE);
+        check_golden("gradient_triangle", &render(RegistryFlags::empty(), &frame));
+    }
+
+    #[test]
+    fn gradient_triangle_srgb() {
+        let mut frame = frame([0., 0.]);
+        let vertices = [
+            vert([16., 2.], [0., 0.], [0xff, 0, 0, 0xff]),
+            vert([30., 29.], [0., 0.], [0, 0xff, 0, 0xff]),
+            vert([2., 29.], [0., 0.], [0, 0, 0xff, 0xff])
+        ];
+        add_draw(&mut frame, &vertices, &[0, 1, 2], [0., 0., 32., 32.], WHITE);
+        check_golden("gradient_triangle_srgb", &render(RegistryFlags::USE_SRGB, &frame));
+    }
+
+    #[test]
+    fn textured_checker() {
+        // The UVs go past 1 so the texture wraps twice across the quad
+        let mut frame = frame([0., 0.]);
+        add_draw(&mut frame, &quad([0., 0.], [32., 32.], [2., 2.], [0xff; 4]), &QUAD_INDICES, [0., 0., 32., 32.], CHECKER);
+        check_golden("textured_checker", &render(RegistryFlags::empty(), &frame));
+    }
+
+    #[test]
+    fn missing_texture_skipped() {
+        let mut frame = frame([0., 0.]);
+        add_draw(&mut frame, &quad([0., 0.], [16., 16.], [1., 1.], [0xff; 4]), &QUAD_INDICES, [0., 0., 32., 32.], TextureId::new(99));
+        add_draw(&mut frame, &quad([4., 6.], [20., 26.], [1., 1.], [0x20, 0xc0, 0x40, 0xff]), &QUAD_INDICES, [0., 0., 32., 32.], WHITE);
+        let white = white_texture();
+        let (target, result) = Rasterizer::new(RegistryFlags::empty())
+            .render(&frame, |id| (id == WHITE).then_some(&white));
+        assert!(matches!(result, Err(RasterError::UnknownTexture(id)) if id == TextureId::new(99)));
+        check_golden("solid_quad", &target);
+    }
+}
@@ -1,17 +1,35 @@
+use crate::config::ConfigParseError;
 use bitflags::bitflags;
 use imgui::ConfigFlags;
-use riri_mod_tools_rt::address::ProcessInfo;
+use riri_mod_tools_rt::{
+    address::ProcessInfo,
+    logln,
+    mod_loader_data
+};
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    path::{ Path, PathBuf },
+    sync::OnceLock
+};
+use toml::Spanned;
+#[cfg(windows)]
 use windows::Win32::Foundation::HMODULE;
 
+#[cfg(windows)]
 #[derive(Debug)]
 pub struct ModuleWrapper(HMODULE);
+#[cfg(windows)]
 impl ModuleWrapper {
     pub fn get(&self) -> HMODULE { self.0 }
 }
+#[cfg(windows)]
 impl From<HMODULE> for ModuleWrapper {
     fn from(value: HMODULE) -> Self { Self(value) }
 }
+#[cfg(windows)]
 unsafe impl Sync for ModuleWrapper {}
+#[cfg(windows)]
 unsafe impl Send for ModuleWrapper {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -19,6 +37,15 @@ pub enum RendererType {
     Direct3D11,
     Direct3D12
 }
+impl RendererType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Direct3D11" => Some(Self::Direct3D11),
+            "Direct3D12" => Some(Self::Direct3D12),
+            _ => None
+        }
+    }
+}
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -27,22 +54,28 @@ bitflags! {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct RegistryEntry<'a> {
-    executable: &'a str,
+    executable: Cow<'a, str>,
     renderer: RendererType,
     io_config_flags_set: ConfigFlags,
-    flags: RegistryFlags
+    flags: RegistryFlags,
+    // Overrides the UI scale from the window's DPI
+    dpi_scale: Option<f32>
 }
 impl<'a> RegistryEntry<'a> {
     const fn new(
-        executable: &'a str, 
-        renderer: RendererType, 
+        executable: &'a str,
+        renderer: RendererType,
         io_config_flags_set: ConfigFlags,
         flags: RegistryFlags
     ) -> Self {
-        Self { executable, renderer, io_config_flags_set, flags }
+        Self { executable: Cow::Borrowed(executable), renderer, io_config_flags_set, flags, dpi_scale: None }
     }
 
+    pub fn get_executable(&self) -> &str {
+        &self.executable
+    }
     pub fn get_renderer(&self) -> RendererType {
         self.renderer
     }
@@ -52,6 +85,9 @@ impl<'a> RegistryEntry<'a> {
     pub fn get_flags(&self) -> RegistryFlags {
         self.flags
     }
+    pub fn get_dpi_scale(&self) -> Option<f32> {
+        self.dpi_scale
+    }
 }
 
 pub(crate) static REGISTRY_BY_EXE_NAME: &'static [RegistryEntry<'static>] = &[
@@ -60,14 +96,272 @@ pub(crate) static REGISTRY_BY_EXE_NAME: &'static [RegistryEntry<'static>] = &[
     RegistryEntry::new("P3R.exe", RendererType::Direct3D12, ConfigFlags::empty(), RegistryFlags::empty()),
     RegistryEntry::new("SMT5V-Win64-Shipping.exe", RendererType::Direct3D12, ConfigFlags::empty(), RegistryFlags::empty()),
 ];
-pub(crate) static DEFAULT_REGISTRY: RegistryEntry<'static> = 
+pub(crate) static DEFAULT_REGISTRY: RegistryEntry<'static> =
     RegistryEntry::new("P5R.exe", RendererType::Direct3D11, ConfigFlags::empty(), RegistryFlags::empty());
 
-pub fn get_registry_entry() -> &'static RegistryEntry<'static> {
+// ImGuiConfigFlags that can be set from registry.toml
+static CONFIG_FLAG_NAMES: &'static [(&'static str, ConfigFlags)] = &[
+    ("NAV_ENABLE_KEYBOARD", ConfigFlags::NAV_ENABLE_KEYBOARD),
+    ("NAV_ENABLE_GAMEPAD", ConfigFlags::NAV_ENABLE_GAMEPAD),
+    ("NAV_ENABLE_SET_MOUSE_POS", ConfigFlags::NAV_ENABLE_SET_MOUSE_POS),
+    ("NAV_NO_CAPTURE_KEYBOARD", ConfigFlags::NAV_NO_CAPTURE_KEYBOARD),
+    ("NO_MOUSE", ConfigFlags::NO_MOUSE),
+    ("NO_MOUSE_CURSOR_CHANGE", ConfigFlags::NO_MOUSE_CURSOR_CHANGE),
+    ("IS_SRGB", ConfigFlags::IS_SRGB),
+    ("IS_TOUCH_SCREEN", ConfigFlags::IS_TOUCH_SCREEN),
+    ("DOCKING_ENABLE", ConfigFlags::DOCKING_ENABLE),
+    ("VIEWPORTS_ENABLE", ConfigFlags::VIEWPORTS_ENABLE),
+];
+
+pub const REGISTRY_FILE_NAME: &'static str = "registry.toml";
+
+// Layout of registry.toml:
+//
+// [[game]]
+// executable = "P5R.exe"
+// renderer = "Direct3D11"
+// config_flags = [ "NAV_ENABLE_KEYBOARD", "NAV_ENABLE_GAMEPAD" ]
+// flags = [ "USE_SRGB" ]
+// dpi_scale = 1.5
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(default)]
+    game: Vec<RegistryFileEntry>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFileEntry {
+    executable: Spanned<String>,
+    renderer: Spanned<String>,
+    #[serde(default)]
+    config_flags: Vec<Spanned<String>>,
+    #[serde(default)]
+    flags: Vec<Spanned<String>>,
+    dpi_scale: Option<Spanned<f32>>
+}
+
+/// Parse the contents of a registry.toml file. Any unknown key, renderer or flag name is
+/// treated as an error.
+pub fn parse_registry(source: &str) -> Result<Vec<RegistryEntry<'static>>, ConfigParseError> {
+    let file: RegistryFile = toml::from_str(source)
+        .map_err(|e| ConfigParseError::new(source, e.span(), e.message().to_owned()))?;
+    let mut out: Vec<RegistryEntry<'static>> = Vec::with_capacity(file.game.len());
+    for entry in file.game {
+        if out.iter().any(|e| e.executable == entry.executable.get_ref().as_str()) {
+            return Err(ConfigParseError::new(source, Some(entry.executable.span()),
+                format!("Duplicate entry for executable \"{}\"", entry.executable.get_ref())));
+        }
+        let renderer = RendererType::from_name(entry.renderer.get_ref())
+            .ok_or_else(|| ConfigParseError::new(source, Some(entry.renderer.span()),
+                format!("Unknown renderer \"{}\", expected Direct3D11 or Direct3D12", entry.renderer.get_ref())))?;
+        let mut io_config_flags_set = ConfigFlags::empty();
+        for flag in &entry.config_flags {
+            match CONFIG_FLAG_NAMES.iter().find(|(n, _)| *n == flag.get_ref().as_str()) {
+                Some((_, v)) => io_config_flags_set |= *v,
+                None => return Err(ConfigParseError::new(source, Some(flag.span()),
+                    format!("Unknown config flag \"{}\"", flag.get_ref())))
+            }
+        }
+        let mut flags = RegistryFlags::empty();
+        for flag in &entry.flags {
+            match RegistryFlags::from_name(flag.get_ref()) {
+                Some(v) => flags |= v,
+                None => return Err(ConfigParseError::new(source, Some(flag.span()),
+                    format!("Unknown registry flag \"{}\"", flag.get_ref())))
+            }
+        }
+        if let Some(scale) = &entry.dpi_scale {
+            if !(0.25..=8.).contains(scale.get_ref()) {
+                return Err(ConfigParseError::new(source, Some(scale.span()),
+                    format!("DPI scale must be between 0.25 and 8, got {}", scale.get_ref())));
+            }
+        }
+        out.push(RegistryEntry {
+            executable: Cow::Owned(entry.executable.into_inner()),
+            renderer, io_config_flags_set, flags,
+            dpi_scale: entry.dpi_scale.map(|s| s.into_inner())
+        });
+    }
+    Ok(out)
+}
+
+/// Merge externally defined entries on top of the built-in table. An external entry
+/// replaces a built-in one with the same executable name.
+pub fn merge_registry(builtin: &[RegistryEntry<'static>], external: Vec<RegistryEntry<'static>>) -> Vec<RegistryEntry<'static>> {
+    let mut out: Vec<RegistryEntry<'static>> = builtin.iter()
+        .filter(|b| !external.iter().any(|e| e.executable == b.executable))
+        .cloned()
+        .collect();
+    out.extend(external);
+    out
+}
+
+fn load_registry<P: AsRef<Path>>(path: P) -> Vec<RegistryEntry<'static>> {
+    let source = match std::fs::read_to_string(path.as_ref()) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return REGISTRY_BY_EXE_NAME.to_vec(),
+        Err(e) => {
+            logln!(Error, "Could not read {}: {}. Using built-in registry", path.as_ref().display(), e);
+            return REGISTRY_BY_EXE_NAME.to_vec();
+        }
+    };
+    match parse_registry(&source) {
+        Ok(v) => {
+            logln!(Verbose, "Loaded {} entries from {}", v.len(), path.as_ref().display());
+            merge_registry(REGISTRY_BY_EXE_NAME, v)
+        },
+        Err(e) => {
+            logln!(Error, "Error in {}: {}. Using built-in registry", path.as_ref().display(), e);
+            REGISTRY_BY_EXE_NAME.to_vec()
+        }
+    }
+}
+
+static REGISTRY: OnceLock<Vec<RegistryEntry<'static>>> = OnceLock::new();
+
+pub fn get_registry() -> &'static [RegistryEntry<'static>] {
+    REGISTRY.get_or_init(|| {
+        let mod_dir: String = mod_loader_data::get_directory_for_mod().into();
+        load_registry(PathBuf::from(mod_dir).join(REGISTRY_FILE_NAME))
+    })
+}
+
+pub fn find_registry_entry() -> Option<&'static RegistryEntry<'static>> {
     let process = ProcessInfo::get_current_process().unwrap();
     let name = process.get_executable_name();
-    match REGISTRY_BY_EXE_NAME.iter().find(|p| p.executable == &name) {
+    get_registry().iter().find(|p| p.executable == name.as_str())
+}
+
+pub fn get_registry_entry() -> &'static RegistryEntry<'static> {
+    match find_registry_entry() {
         Some(v) => v,
         None => &DEFAULT_REGISTRY
     }
-}
\ No newline at end of file
+}
+
+static DETECTED_REGISTRY: OnceLock<RegistryEntry<'static>> = OnceLock::new();
+
+/// Create an entry for an executable that isn't in the registry, using a renderer found
+/// through crate::detect.
+pub fn get_detected_entry(renderer: RendererType) -> &'static RegistryEntry<'static> {
+    DETECTED_REGISTRY.get_or_init(|| {
+        let process = ProcessInfo::get_current_process().unwrap();
+        RegistryEntry {
+            executable: Cow::Owned(process.get_executable_name()),
+            renderer,
+            io_config_flags_set: ConfigFlags::empty(),
+            flags: RegistryFlags::empty(),
+            dpi_scale: None
+        }
+    })
+}
+
+pub fn get_default_entry() -> &'static RegistryEntry<'static> {
+    &DEFAULT_REGISTRY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_full_entry() {
+        let source = r#"
+[[game]]
+executable = "P5R.exe"
+renderer = "Direct3D12"
+config_flags = [ "NAV_ENABLE_KEYBOARD", "NAV_ENABLE_GAMEPAD" ]
+flags = [ "USE_SRGB" ]
+dpi_scale = 1.5
+
+[[game]]
+executable = "Other.exe"
+renderer = "Direct3D11"
+"#;
+        let entries = parse_registry(source).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_executable(), "P5R.exe");
+        assert_eq!(entries[0].get_renderer(), RendererType::Direct3D12);
+        assert_eq!(entries[0].get_config_flags_to_set(),
+            ConfigFlags::NAV_ENABLE_KEYBOARD | ConfigFlags::NAV_ENABLE_GAMEPAD);
+        assert_eq!(entries[0].get_flags(), RegistryFlags::USE_SRGB);
+        assert_eq!(entries[0].get_dpi_scale(), Some(1.5));
+        assert_eq!(entries[1].get_executable(), "Other.exe");
+        assert_eq!(entries[1].get_renderer(), RendererType::Direct3D11);
+        assert_eq!(entries[1].get_config_flags_to_set(), ConfigFlags::empty());
+        assert_eq!(entries[1].get_flags(), RegistryFlags::empty());
+        assert_eq!(entries[1].get_dpi_scale(), None);
+    }
+
+    #[test]
+    fn parse_empty_file() {
+        assert!(parse_registry("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_unknown_renderer() {
+        let source = "[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Vulkan\"\n";
+        let err = parse_registry(source).unwrap_err();
+        assert_eq!(err.get_line(), Some(3));
+        assert!(err.get_message().contains("Vulkan"));
+    }
+
+    #[test]
+    fn parse_unknown_flags() {
+        let source = "[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D11\"\nconfig_flags = [ \"NOT_A_FLAG\" ]\n";
+        let err = parse_registry(source).unwrap_err();
+        assert_eq!(err.get_line(), Some(4));
+        assert!(err.get_message().contains("NOT_A_FLAG"));
+        let source = "[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D11\"\nflags = [ \"USE_HDR\" ]\n";
+        let err = parse_registry(source).unwrap_err();
+        assert_eq!(err.get_line(), Some(4));
+        assert!(err.get_message().contains("USE_HDR"));
+    }
+
+    #[test]
+    fn parse_unknown_key() {
+        let source = "[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D11\"\nscale = 2.0\n";
+        assert!(parse_registry(source).is_err());
+    }
+
+    #[test]
+    fn parse_missing_renderer() {
+        assert!(parse_registry("[[game]]\nexecutable = \"P5R.exe\"\n").is_err());
+    }
+
+    #[test]
+    fn parse_duplicate_executable() {
+        let source = "[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D11\"\n\n\
+            [[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D12\"\n";
+        let err = parse_registry(source).unwrap_err();
+        assert_eq!(err.get_line(), Some(6));
+    }
+
+    #[test]
+    fn parse_dpi_scale_range() {
+        for scale in [ "0.1", "9.0" ] {
+            let source = format!("[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D11\"\ndpi_scale = {}\n", scale);
+            let err = parse_registry(&source).unwrap_err();
+            assert_eq!(err.get_line(), Some(4));
+        }
+        for scale in [ "0.25", "8.0" ] {
+            let source = format!("[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D11\"\ndpi_scale = {}\n", scale);
+            assert!(parse_registry(&source).is_ok());
+        }
+    }
+
+    #[test]
+    fn merge_replaces_builtin() {
+        let external = parse_registry("[[game]]\nexecutable = \"P5R.exe\"\nrenderer = \"Direct3D12\"\n\n\
+            [[game]]\nexecutable = \"New.exe\"\nrenderer = \"Direct3D11\"\n").unwrap();
+        let merged = merge_registry(REGISTRY_BY_EXE_NAME, external);
+        assert_eq!(merged.len(), REGISTRY_BY_EXE_NAME.len() + 1);
+        assert_eq!(merged.iter().filter(|e| e.get_executable() == "P5R.exe").count(), 1);
+        let p5r = merged.iter().find(|e| e.get_executable() == "P5R.exe").unwrap();
+        assert_eq!(p5r.get_renderer(), RendererType::Direct3D12);
+        assert!(merged.iter().any(|e| e.get_executable() == "New.exe"));
+        assert!(merged.iter().any(|e| e.get_executable() == "METAPHOR.exe"));
+    }
+}
@@ -0,0 +1,76 @@
+// Lifecycle shared by every renderer. Each renderer has its own constructor, since what it
+// needs from the game differs (Direct3D 12 also needs the game's command queue), and from
+// then on it's only used through this trait:
+//
+// init -> (new_frame -> render)* -> before_resize -> after_resize -> ... -> shutdown
+//
+// Textures can be created and destroyed at any point between init and shutdown.
+use crate::{
+    image::CpuImage,
+    registry::RegistryFlags
+};
+use imgui::{
+    Context as ImContext,
+    DrawData,
+    TextureId
+};
+use std::{
+    any::Any,
+    fmt::Debug
+};
+use windows_core::HRESULT;
+
+// windows::Win32::Foundation::E_INVALIDARG, which isn't available off Windows
+const E_INVALIDARG: HRESULT = HRESULT(0x80070057_u32 as i32);
+
+pub trait RendererBackend: Debug {
+    /// Create anything that couldn't be made in the constructor (ImGui_ImplXXX_CreateDeviceObjects).
+    /// Called once before the first frame. Flags that don't apply to the renderer are ignored.
+    fn init(&mut self, _ctx: &mut ImContext, _flags: RegistryFlags) -> windows_core::Result<()> {
+        Ok(())
+    }
+
+    /// Called before ImGui's NewFrame
+    fn new_frame(&mut self, _ctx: &mut ImContext) -> windows_core::Result<()> {
+        Ok(())
+    }
+
+    fn render(&mut self, draw_data: &DrawData) -> windows_core::Result<()>;
+
+    /// Called before IDXGISwapChain::ResizeBuffers. Every reference to the swapchain's back
+    /// buffers has to be released for the resize to succeed.
+    fn before_resize(&mut self, _ctx: &mut ImContext) -> windows_core::Result<()> {
+        Ok(())
+    }
+
+    /// Called after IDXGISwapChain::ResizeBuffers to recreate what before_resize released
+    fn after_resize(&mut self, _ctx: &mut ImContext) -> windows_core::Result<()> {
+        Ok(())
+    }
+
+    /// Upload a newly built font atlas
+    fn rebuild_font_texture(&mut self, ctx: &mut ImContext) -> windows_core::Result<()>;
+
+    /// Upload a decoded image and register it
+    fn create_texture(&mut self, image: &CpuImage) -> windows_core::Result<TextureId>;
+
+    /// Create a texture from tightly packed RGBA8 data and register it
+    fn create_texture_rgba(&mut self, width: u32, height: u32, data: &[u8]) -> windows_core::Result<TextureId> {
+        let image = CpuImage::from_rgba(width, height, data)
+            .map_err(|e| windows_core::Error::new(E_INVALIDARG, e.to_string()))?;
+        self.create_texture(&image)
+    }
+
+    /// Release a texture. Returns false if the ID doesn't belong to one of the renderer's
+    /// textures.
+    fn destroy_texture(&mut self, id: TextureId) -> bool;
+
+    /// ImGui_ImplXXX_Shutdown. Release everything so the renderer can be dropped while the
+    /// game keeps running.
+    fn shutdown(&mut self, ctx: &mut ImContext) -> windows_core::Result<()>;
+
+    /// Get the concrete renderer, for features only one graphics API has (e.g registering an
+    /// existing ID3D11ShaderResourceView)
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
@@ -0,0 +1,182 @@
+// ImGui's clipboard hooks (ImGui_ImplWin32 leaves this to the default Win32 implementation in
+// imgui.cpp, which imgui-rs replaces with ClipboardBackend)
+use imgui::ClipboardBackend;
+#[cfg(windows)]
+use std::ptr::NonNull;
+#[cfg(windows)]
+use windows::Win32::{
+    Foundation::{ GlobalFree, HANDLE, HGLOBAL, HWND },
+    System::{
+        DataExchange::{
+            CloseClipboard,
+            EmptyClipboard,
+            GetClipboardData,
+            IsClipboardFormatAvailable,
+            OpenClipboard,
+            SetClipboardData
+        },
+        Memory::{ GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE },
+        Ole::CF_UNICODETEXT
+    }
+};
+
+/// Storage for UTF-16 clipboard text. Text passed in and out of a store doesn't include a null
+/// terminator.
+pub trait ClipboardStore: 'static {
+    fn get_text(&mut self) -> Option<Vec<u16>>;
+    fn set_text(&mut self, text: &[u16]) -> bool;
+}
+
+/// Convert clipboard text into a string, stopping at the first null. Unpaired surrogates are
+/// replaced with U+FFFD rather than throwing away the whole paste.
+pub fn utf16_to_string(text: &[u16]) -> String {
+    let len = text.iter().position(|c| *c == 0).unwrap_or(text.len());
+    String::from_utf16_lossy(&text[..len])
+}
+
+/// Convert a string into UTF-16 for the clipboard, without a null terminator
+pub fn string_to_utf16(text: &str) -> Vec<u16> {
+    text.encode_utf16().collect()
+}
+
+/// Adapts a ClipboardStore to imgui-rs's ClipboardBackend
+#[derive(Debug)]
+pub struct Clipboard<S: ClipboardStore>(S);
+impl<S: ClipboardStore> Clipboard<S> {
+    pub fn new(store: S) -> Self { Self(store) }
+    pub fn get_store(&self) -> &S { &self.0 }
+    pub fn get_store_mut(&mut self) -> &mut S { &mut self.0 }
+}
+impl<S: ClipboardStore> ClipboardBackend for Clipboard<S> {
+    fn get(&mut self) -> Option<String> {
+        self.0.get_text().map(|t| utf16_to_string(&t))
+    }
+    fn set(&mut self, value: &str) {
+        self.0.set_text(&string_to_utf16(value));
+    }
+}
+
+/// Clipboard that only lives in memory, for when there's no window to own the system clipboard
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    text: Option<Vec<u16>>
+}
+impl MemoryClipboard {
+    pub fn new() -> Self { Self::default() }
+}
+impl ClipboardStore for MemoryClipboard {
+    fn get_text(&mut self) -> Option<Vec<u16>> {
+        self.text.clone()
+    }
+    fn set_text(&mut self, text: &[u16]) -> bool {
+        self.text = Some(text.to_vec());
+        true
+    }
+}
+
+/// Reads and writes CF_UNICODETEXT on the system clipboard
+#[cfg(windows)]
+#[derive(Debug)]
+pub struct Win32Clipboard {
+    hwnd: HWND
+}
+
+// Closes the clipboard when dropped so early returns can't leave it open for other programs
+#[cfg(windows)]
+struct OpenedClipboard;
+#[cfg(windows)]
+impl OpenedClipboard {
+    unsafe fn open(hwnd: HWND) -> Option<Self> {
+        OpenClipboard(Some(hwnd)).ok().map(|_| Self)
+    }
+}
+#[cfg(windows)]
+impl Drop for OpenedClipboard {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseClipboard() };
+    }
+}
+
+#[cfg(windows)]
+impl Win32Clipboard {
+    pub fn new(hwnd: HWND) -> Self { Self { hwnd } }
+}
+#[cfg(windows)]
+impl ClipboardStore for Win32Clipboard {
+    fn get_text(&mut self) -> Option<Vec<u16>> {
+        unsafe {
+            IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32).ok()?;
+            let _clipboard = OpenedClipboard::open(self.hwnd)?;
+            let handle = HGLOBAL(GetClipboardData(CF_UNICODETEXT.0 as u32).ok()?.0);
+            let data = NonNull::new(GlobalLock(handle) as *mut u16)?;
+            // GlobalSize can be larger than the string, utf16_to_string stops at the null
+            let len = GlobalSize(handle) / std::mem::size_of::<u16>();
+            let text = std::slice::from_raw_parts(data.as_ptr(), len).to_vec();
+            let _ = GlobalUnlock(handle);
+            Some(text)
+        }
+    }
+    fn set_text(&mut self, text: &[u16]) -> bool {
+        unsafe {
+            let Some(_clipboard) = OpenedClipboard::open(self.hwnd) else { return false };
+            let Ok(handle) = GlobalAlloc(GMEM_MOVEABLE, (text.len() + 1) * std::mem::size_of::<u16>()) else {
+                return false;
+            };
+            let Some(data) = NonNull::new(GlobalLock(handle) as *mut u16) else {
+                let _ = GlobalFree(Some(handle));
+                return false;
+            };
+            std::ptr::copy_nonoverlapping(text.as_ptr(), data.as_ptr(), text.len());
+            *data.as_ptr().add(text.len()) = 0;
+            let _ = GlobalUnlock(handle);
+            // The clipboard takes ownership of the memory only if SetClipboardData succeeds
+            if EmptyClipboard().is_err() || SetClipboardData(CF_UNICODETEXT.0 as u32, Some(HANDLE(handle.0))).is_err() {
+                let _ = GlobalFree(Some(handle));
+                return false;
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_clipboard_round_trip() {
+        let mut clipboard = Clipboard::new(MemoryClipboard::new());
+        assert_eq!(clipboard.get(), None);
+        clipboard.set("Hello, 世界 🎮");
+        assert_eq!(clipboard.get().as_deref(), Some("Hello, 世界 🎮"));
+        // Stored without a null terminator, with the emoji as a surrogate pair
+        let stored = clipboard.get_store_mut().get_text().unwrap();
+        assert_eq!(stored.len(), 12);
+        assert_ne!(stored.last(), Some(&0));
+        clipboard.set("");
+        assert_eq!(clipboard.get().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn utf16_stops_at_null() {
+        let text: Vec<u16> = "abc\0def".encode_utf16().collect();
+        assert_eq!(utf16_to_string(&text), "abc");
+        assert_eq!(utf16_to_string(&[0, 0x41]), "");
+        assert_eq!(utf16_to_string(&[]), "");
+    }
+
+    #[test]
+    fn utf16_unpaired_surrogates() {
+        // Lone high surrogate, lone low surrogate, and a low surrogate before a high one
+        assert_eq!(utf16_to_string(&[0x41, 0xd83c, 0x42]), "A\u{fffd}B");
+        assert_eq!(utf16_to_string(&[0xdfae, 0x41]), "\u{fffd}A");
+        assert_eq!(utf16_to_string(&[0xdfae, 0xd83c]), "\u{fffd}\u{fffd}");
+        assert_eq!(utf16_to_string(&[0xd83c, 0xdfae]), "🎮");
+    }
+
+    #[test]
+    fn string_to_utf16_has_no_terminator() {
+        assert_eq!(string_to_utf16("A🎮"), vec![0x41, 0xd83c, 0xdfae]);
+        assert!(string_to_utf16("").is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+// ImGui_ImplWin32_UpdateMouseCursor
+use imgui::MouseCursor;
+#[cfg(windows)]
+use windows::{
+    core::PCWSTR,
+    Win32::UI::WindowsAndMessaging::{
+        IDC_ARROW,
+        IDC_HAND,
+        IDC_IBEAM,
+        IDC_NO,
+        IDC_SIZEALL,
+        IDC_SIZENESW,
+        IDC_SIZENS,
+        IDC_SIZENWSE,
+        IDC_SIZEWE,
+        LoadCursorW,
+        SetCursor
+    }
+};
+
+/// Cursors built into Windows that ImGui's cursors map to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemCursor {
+    Arrow,
+    IBeam,
+    SizeAll,
+    SizeNS,
+    SizeWE,
+    SizeNESW,
+    SizeNWSE,
+    Hand,
+    No
+}
+#[cfg(windows)]
+impl SystemCursor {
+    pub fn get_resource(&self) -> PCWSTR {
+        match self {
+            Self::Arrow => IDC_ARROW,
+            Self::IBeam => IDC_IBEAM,
+            Self::SizeAll => IDC_SIZEALL,
+            Self::SizeNS => IDC_SIZENS,
+            Self::SizeWE => IDC_SIZEWE,
+            Self::SizeNESW => IDC_SIZENESW,
+            Self::SizeNWSE => IDC_SIZENWSE,
+            Self::Hand => IDC_HAND,
+            Self::No => IDC_NO
+        }
+    }
+}
+
+/// Pick the system cursor to show for ImGui's requested cursor. Returns None when the cursor
+/// should be hidden, either because ImGui asked for no cursor or because it's drawing the
+/// cursor itself (io.MouseDrawCursor).
+pub fn get_system_cursor(cursor: Option<MouseCursor>, draw_cursor: bool) -> Option<SystemCursor> {
+    if draw_cursor {
+        return None;
+    }
+    cursor.map(|c| match c {
+        MouseCursor::Arrow => SystemCursor::Arrow,
+        MouseCursor::TextInput => SystemCursor::IBeam,
+        MouseCursor::ResizeAll => SystemCursor::SizeAll,
+        MouseCursor::ResizeNS => SystemCursor::SizeNS,
+        MouseCursor::ResizeEW => SystemCursor::SizeWE,
+        MouseCursor::ResizeNESW => SystemCursor::SizeNESW,
+        MouseCursor::ResizeNWSE => SystemCursor::SizeNWSE,
+        MouseCursor::Hand => SystemCursor::Hand,
+        MouseCursor::NotAllowed => SystemCursor::No
+    })
+}
+
+#[cfg(windows)]
+pub unsafe fn set_system_cursor(cursor: Option<SystemCursor>) {
+    let handle = cursor.and_then(|c| LoadCursorW(None, c.get_resource()).ok());
+    SetCursor(handle);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_every_cursor() {
+        for (cursor, expected) in [
+            (MouseCursor::Arrow, SystemCursor::Arrow),
+            (MouseCursor::TextInput, SystemCursor::IBeam),
+            (MouseCursor::ResizeAll, SystemCursor::SizeAll),
+            (MouseCursor::ResizeNS, SystemCursor::SizeNS),
+            (MouseCursor::ResizeEW, SystemCursor::SizeWE),
+            (MouseCursor::ResizeNESW, SystemCursor::SizeNESW),
+            (MouseCursor::ResizeNWSE, SystemCursor::SizeNWSE),
+            (MouseCursor::Hand, SystemCursor::Hand),
+            (MouseCursor::NotAllowed, SystemCursor::No),
+        ] {
+            assert_eq!(get_system_cursor(Some(cursor), false), Some(expected), "{:?}", cursor);
+        }
+    }
+
+    #[test]
+    fn hidden_cursor() {
+        assert_eq!(get_system_cursor(None, false), None);
+    }
+
+    #[test]
+    fn imgui_draws_cursor() {
+        assert_eq!(get_system_cursor(Some(MouseCursor::Arrow), true), None);
+        assert_eq!(get_system_cursor(Some(MouseCursor::TextInput), true), None);
+    }
+}
@@ -0,0 +1,75 @@
+// ImGui_ImplWin32_GetDpiScaleForHwnd. GetDpiForWindow only exists on Windows 10 1607+, so
+// it's looked up at runtime with a fallback to the system DPI.
+#[cfg(windows)]
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::HWND,
+        Graphics::Gdi::{ GetDC, GetDeviceCaps, ReleaseDC, LOGPIXELSX },
+        System::LibraryLoader::{ GetModuleHandleA, GetProcAddress }
+    }
+};
+
+/// DPI that Windows treats as 100% scale
+pub const DEFAULT_DPI: u32 = 96;
+
+/// Get the UI scale for a DPI. An override (from the registry) always wins, and a DPI of zero
+/// (the query failed) is treated as 100%.
+pub fn get_dpi_scale(dpi: u32, override_scale: Option<f32>) -> f32 {
+    match override_scale {
+        Some(v) if v > 0. => v,
+        _ => match dpi {
+            0 => 1.,
+            v => v as f32 / DEFAULT_DPI as f32
+        }
+    }
+}
+
+#[cfg(windows)]
+type GetDpiForWindowFn = unsafe extern "system" fn(HWND) -> u32;
+
+/// Get the DPI of the monitor a window is on. For a process that isn't DPI aware Windows
+/// reports 96 here and scales the window itself.
+#[cfg(windows)]
+pub unsafe fn get_window_dpi(hwnd: HWND) -> u32 {
+    if let Ok(user32) = GetModuleHandleA(PCSTR("user32.dll\0".as_ptr())) {
+        if let Some(f) = GetProcAddress(user32, PCSTR("GetDpiForWindow\0".as_ptr())) {
+            let get_dpi_for_window: GetDpiForWindowFn = std::mem::transmute(f);
+            return get_dpi_for_window(hwnd);
+        }
+    }
+    let hdc = GetDC(Some(hwnd));
+    if hdc.is_invalid() {
+        return DEFAULT_DPI;
+    }
+    let dpi = GetDeviceCaps(Some(hdc), LOGPIXELSX);
+    ReleaseDC(Some(hwnd), hdc);
+    dpi.max(0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_from_dpi() {
+        assert_eq!(get_dpi_scale(DEFAULT_DPI, None), 1.);
+        assert_eq!(get_dpi_scale(144, None), 1.5);
+        assert_eq!(get_dpi_scale(192, None), 2.);
+        assert_eq!(get_dpi_scale(72, None), 0.75);
+    }
+
+    #[test]
+    fn failed_query_is_unscaled() {
+        assert_eq!(get_dpi_scale(0, None), 1.);
+    }
+
+    #[test]
+    fn override_wins() {
+        assert_eq!(get_dpi_scale(192, Some(1.25)), 1.25);
+        assert_eq!(get_dpi_scale(0, Some(3.)), 3.);
+        // Zero or negative overrides are ignored
+        assert_eq!(get_dpi_scale(144, Some(0.)), 1.5);
+        assert_eq!(get_dpi_scale(144, Some(-1.)), 1.5);
+    }
+}
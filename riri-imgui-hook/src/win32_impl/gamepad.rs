@@ -0,0 +1,305 @@
+// ImGui_ImplWin32_UpdateGamepads. XInput is loaded at runtime since which DLL is available
+// depends on the Windows version and whether the DirectX runtime is installed.
+use imgui::Key;
+#[cfg(windows)]
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::{ ERROR_SUCCESS, FreeLibrary, HMODULE },
+        System::LibraryLoader::{ GetProcAddress, LoadLibraryA },
+        UI::Input::XboxController::{
+            XINPUT_CAPABILITIES,
+            XINPUT_FLAG_GAMEPAD,
+            XINPUT_GAMEPAD,
+            XINPUT_STATE
+        }
+    }
+};
+
+// Values from XInput.h
+pub const XINPUT_GAMEPAD_DPAD_UP: u16 = 0x0001;
+pub const XINPUT_GAMEPAD_DPAD_DOWN: u16 = 0x0002;
+pub const XINPUT_GAMEPAD_DPAD_LEFT: u16 = 0x0004;
+pub const XINPUT_GAMEPAD_DPAD_RIGHT: u16 = 0x0008;
+pub const XINPUT_GAMEPAD_START: u16 = 0x0010;
+pub const XINPUT_GAMEPAD_BACK: u16 = 0x0020;
+pub const XINPUT_GAMEPAD_LEFT_THUMB: u16 = 0x0040;
+pub const XINPUT_GAMEPAD_RIGHT_THUMB: u16 = 0x0080;
+pub const XINPUT_GAMEPAD_LEFT_SHOULDER: u16 = 0x0100;
+pub const XINPUT_GAMEPAD_RIGHT_SHOULDER: u16 = 0x0200;
+pub const XINPUT_GAMEPAD_A: u16 = 0x1000;
+pub const XINPUT_GAMEPAD_B: u16 = 0x2000;
+pub const XINPUT_GAMEPAD_X: u16 = 0x4000;
+pub const XINPUT_GAMEPAD_Y: u16 = 0x8000;
+pub const XINPUT_GAMEPAD_TRIGGER_THRESHOLD: u8 = 30;
+pub const XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE: i16 = 7849;
+
+#[cfg(windows)]
+pub(crate) static XINPUT_DLL: [&'static str; 5] = [
+    "xinput1_4.dll\0", // Windows 8+
+    "xinput1_3.dll\0", // DirectX SDK
+    "xinput9_1_0.dll\0", // Windows Vista/Windows 7
+    "xinput1_2.dll\0", // DirectX SDK
+    "xinput1_1.dll\0" // DirectX SDK
+];
+
+/// The buttons, triggers and sticks of a controller, laid out like XINPUT_GAMEPAD
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GamepadState {
+    pub buttons: u16,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    pub thumb_lx: i16,
+    pub thumb_ly: i16,
+    pub thumb_rx: i16,
+    pub thumb_ry: i16
+}
+
+#[cfg(windows)]
+impl From<XINPUT_GAMEPAD> for GamepadState {
+    fn from(value: XINPUT_GAMEPAD) -> Self {
+        Self {
+            buttons: value.wButtons.0,
+            left_trigger: value.bLeftTrigger,
+            right_trigger: value.bRightTrigger,
+            thumb_lx: value.sThumbLX,
+            thumb_ly: value.sThumbLY,
+            thumb_rx: value.sThumbRX,
+            thumb_ry: value.sThumbRY
+        }
+    }
+}
+
+/// Something that can report the state of a gamepad
+pub trait GamepadSource {
+    /// Returns None if no gamepad is connected
+    fn get_state(&mut self) -> Option<GamepadState>;
+    /// Called when devices are added or removed (WM_DEVICECHANGE)
+    fn on_device_change(&mut self) {}
+}
+
+/// A single key or analog update to send to ImGui
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GamepadEvent {
+    pub key: Key,
+    pub down: bool,
+    pub value: f32
+}
+
+static BUTTON_MAP: [(Key, u16); 14] = [
+    (Key::GamepadStart, XINPUT_GAMEPAD_START),
+    (Key::GamepadBack, XINPUT_GAMEPAD_BACK),
+    (Key::GamepadFaceLeft, XINPUT_GAMEPAD_X),
+    (Key::GamepadFaceRight, XINPUT_GAMEPAD_B),
+    (Key::GamepadFaceUp, XINPUT_GAMEPAD_Y),
+    (Key::GamepadFaceDown, XINPUT_GAMEPAD_A),
+    (Key::GamepadDpadLeft, XINPUT_GAMEPAD_DPAD_LEFT),
+    (Key::GamepadDpadRight, XINPUT_GAMEPAD_DPAD_RIGHT),
+    (Key::GamepadDpadUp, XINPUT_GAMEPAD_DPAD_UP),
+    (Key::GamepadDpadDown, XINPUT_GAMEPAD_DPAD_DOWN),
+    (Key::GamepadL1, XINPUT_GAMEPAD_LEFT_SHOULDER),
+    (Key::GamepadR1, XINPUT_GAMEPAD_RIGHT_SHOULDER),
+    (Key::GamepadL3, XINPUT_GAMEPAD_LEFT_THUMB),
+    (Key::GamepadR3, XINPUT_GAMEPAD_RIGHT_THUMB),
+];
+
+// MAP_ANALOG: scale v from the range v0..v1 into 0..1
+fn get_analog(key: Key, v: f32, v0: f32, v1: f32) -> GamepadEvent {
+    let value = ((v - v0) / (v1 - v0)).clamp(0., 1.);
+    GamepadEvent { key, down: value > 0.1, value }
+}
+
+/// Convert a gamepad state into the key events ImGui expects. Every gamepad key is reported
+/// each frame, so released buttons are sent as up.
+pub fn get_gamepad_events(gamepad: &GamepadState) -> Vec<GamepadEvent> {
+    let mut events: Vec<GamepadEvent> = BUTTON_MAP.iter()
+        .map(|(key, flag)| {
+            let down = gamepad.buttons & flag != 0;
+            GamepadEvent { key: *key, down, value: if down { 1. } else { 0. } }
+        })
+        .collect();
+    let trigger_threshold = XINPUT_GAMEPAD_TRIGGER_THRESHOLD as f32;
+    let deadzone = XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE as f32;
+    let (lx, ly) = (gamepad.thumb_lx as f32, gamepad.thumb_ly as f32);
+    let (rx, ry) = (gamepad.thumb_rx as f32, gamepad.thumb_ry as f32);
+    events.extend_from_slice(&[
+        get_analog(Key::GamepadL2, gamepad.left_trigger as f32, trigger_threshold, 255.),
+        get_analog(Key::GamepadR2, gamepad.right_trigger as f32, trigger_threshold, 255.),
+        get_analog(Key::GamepadLStickLeft, lx, -deadzone, -32768.),
+        get_analog(Key::GamepadLStickRight, lx, deadzone, 32767.),
+        get_analog(Key::GamepadLStickUp, ly, deadzone, 32767.),
+        get_analog(Key::GamepadLStickDown, ly, -deadzone, -32768.),
+        get_analog(Key::GamepadRStickLeft, rx, -deadzone, -32768.),
+        get_analog(Key::GamepadRStickRight, rx, deadzone, 32767.),
+        get_analog(Key::GamepadRStickUp, ry, deadzone, 32767.),
+        get_analog(Key::GamepadRStickDown, ry, -deadzone, -32768.),
+    ]);
+    events
+}
+
+#[cfg(windows)]
+type XInputGetCapabilitiesFn = unsafe extern "system" fn(u32, u32, *mut XINPUT_CAPABILITIES) -> u32;
+#[cfg(windows)]
+type XInputGetStateFn = unsafe extern "system" fn(u32, *mut XINPUT_STATE) -> u32;
+
+/// Polls the first XInput controller through whichever XInput DLL could be loaded
+#[cfg(windows)]
+#[derive(Debug)]
+pub struct XInputGamepad {
+    module: HMODULE,
+    get_capabilities: XInputGetCapabilitiesFn,
+    get_state: XInputGetStateFn,
+    // Polling a disconnected controller is slow, so only check for one when devices change
+    want_update_connected: bool,
+    connected: bool
+}
+#[cfg(windows)]
+unsafe impl Send for XInputGamepad {}
+
+#[cfg(windows)]
+impl XInputGamepad {
+    pub unsafe fn new() -> Option<Self> {
+        for name in XINPUT_DLL {
+            let Ok(module) = LoadLibraryA(PCSTR(name.as_ptr())) else { continue };
+            let get_capabilities = GetProcAddress(module, PCSTR("XInputGetCapabilities\0".as_ptr()));
+            let get_state = GetProcAddress(module, PCSTR("XInputGetState\0".as_ptr()));
+            match (get_capabilities, get_state) {
+                (Some(get_capabilities), Some(get_state)) => return Some(Self {
+                    module,
+                    get_capabilities: std::mem::transmute(get_capabilities),
+                    get_state: std::mem::transmute(get_state),
+                    want_update_connected: true,
+                    connected: false
+                }),
+                _ => { let _ = FreeLibrary(module); }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(windows)]
+impl GamepadSource for XInputGamepad {
+    fn get_state(&mut self) -> Option<GamepadState> {
+        if self.want_update_connected {
+            let mut caps = XINPUT_CAPABILITIES::default();
+            self.connected = unsafe { (self.get_capabilities)(0, XINPUT_FLAG_GAMEPAD.0, &raw mut caps) } == ERROR_SUCCESS.0;
+            self.want_update_connected = false;
+        }
+        if !self.connected {
+            return None;
+        }
+        let mut state = XINPUT_STATE::default();
+        match unsafe { (self.get_state)(0, &raw mut state) } == ERROR_SUCCESS.0 {
+            true => Some(state.Gamepad.into()),
+            false => None
+        }
+    }
+    fn on_device_change(&mut self) {
+        self.want_update_connected = true;
+    }
+}
+
+#[cfg(windows)]
+impl Drop for XInputGamepad {
+    fn drop(&mut self) {
+        let _ = unsafe { FreeLibrary(self.module) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Plays back a list of states, one per poll. None means the controller is unplugged.
+    struct ScriptedGamepad {
+        script: VecDeque<Option<GamepadState>>,
+        device_changes: usize
+    }
+
+    impl ScriptedGamepad {
+        fn new(script: impl IntoIterator<Item = Option<GamepadState>>) -> Self {
+            Self { script: script.into_iter().collect(), device_changes: 0 }
+        }
+    }
+
+    impl GamepadSource for ScriptedGamepad {
+        fn get_state(&mut self) -> Option<GamepadState> {
+            self.script.pop_front().flatten()
+        }
+        fn on_device_change(&mut self) {
+            self.device_changes += 1;
+        }
+    }
+
+    fn get_event(events: &[GamepadEvent], key: Key) -> GamepadEvent {
+        *events.iter().find(|e| e.key == key).unwrap()
+    }
+
+    #[test]
+    fn reports_every_key() {
+        let events = get_gamepad_events(&GamepadState::default());
+        assert_eq!(events.len(), BUTTON_MAP.len() + 10);
+        assert!(events.iter().all(|e| !e.down && e.value == 0.));
+    }
+
+    #[test]
+    fn button_down_then_up() {
+        let pressed = GamepadState { buttons: XINPUT_GAMEPAD_A | XINPUT_GAMEPAD_DPAD_LEFT, ..Default::default() };
+        let mut pad = ScriptedGamepad::new([Some(pressed), Some(GamepadState::default())]);
+        let events = get_gamepad_events(&pad.get_state().unwrap());
+        assert_eq!(get_event(&events, Key::GamepadFaceDown), GamepadEvent { key: Key::GamepadFaceDown, down: true, value: 1. });
+        assert!(get_event(&events, Key::GamepadDpadLeft).down);
+        assert!(!get_event(&events, Key::GamepadFaceRight).down);
+        let events = get_gamepad_events(&pad.get_state().unwrap());
+        assert_eq!(get_event(&events, Key::GamepadFaceDown), GamepadEvent { key: Key::GamepadFaceDown, down: false, value: 0. });
+        assert!(!get_event(&events, Key::GamepadDpadLeft).down);
+    }
+
+    #[test]
+    fn triggers_use_threshold() {
+        let under = GamepadState { left_trigger: XINPUT_GAMEPAD_TRIGGER_THRESHOLD, ..Default::default() };
+        let events = get_gamepad_events(&under);
+        assert_eq!(get_event(&events, Key::GamepadL2).value, 0.);
+        let full = GamepadState { left_trigger: 255, right_trigger: 255, ..Default::default() };
+        let events = get_gamepad_events(&full);
+        assert_eq!(get_event(&events, Key::GamepadL2), GamepadEvent { key: Key::GamepadL2, down: true, value: 1. });
+        assert!(get_event(&events, Key::GamepadR2).down);
+    }
+
+    #[test]
+    fn sticks_use_deadzone() {
+        let inside = GamepadState { thumb_lx: XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE - 1, thumb_ry: -100, ..Default::default() };
+        let events = get_gamepad_events(&inside);
+        assert!(events.iter().all(|e| e.value == 0.));
+        let pushed = GamepadState { thumb_lx: i16::MIN, thumb_ly: i16::MAX, ..Default::default() };
+        let events = get_gamepad_events(&pushed);
+        assert_eq!(get_event(&events, Key::GamepadLStickLeft).value, 1.);
+        assert_eq!(get_event(&events, Key::GamepadLStickUp).value, 1.);
+        assert_eq!(get_event(&events, Key::GamepadLStickRight).value, 0.);
+        assert_eq!(get_event(&events, Key::GamepadLStickDown).value, 0.);
+    }
+
+    #[test]
+    fn analog_half_range() {
+        let half = 7849 + (32767 - 7849) / 2;
+        let events = get_gamepad_events(&GamepadState { thumb_rx: half, ..Default::default() });
+        let event = get_event(&events, Key::GamepadRStickRight);
+        assert!(event.down);
+        assert!((event.value - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn disconnect_and_reconnect() {
+        let pressed = GamepadState { buttons: XINPUT_GAMEPAD_START, ..Default::default() };
+        let mut pad = ScriptedGamepad::new([Some(pressed), None, Some(GamepadState::default())]);
+        assert!(pad.get_state().is_some());
+        assert!(pad.get_state().is_none());
+        pad.on_device_change();
+        assert_eq!(pad.device_changes, 1);
+        let events = get_gamepad_events(&pad.get_state().unwrap());
+        assert!(!get_event(&events, Key::GamepadStart).down);
+        assert!(pad.get_state().is_none());
+    }
+}
@@ -0,0 +1,202 @@
+// IME support for Win32Impl. Windows delivers text one UTF-16 unit per message, so characters
+// outside the BMP (emoji, rarer CJK ideographs) arrive as a high/low surrogate pair across two
+// WM_CHAR or WM_IME_CHAR messages.
+#[cfg(windows)]
+use imgui::sys::{ ImGuiPlatformImeData, ImGuiViewport };
+#[cfg(windows)]
+use std::sync::atomic::{ AtomicUsize, Ordering };
+#[cfg(windows)]
+use windows::Win32::{
+    Foundation::{ HWND, POINT, RECT },
+    Globalization::{ CP_ACP, MB_PRECOMPOSED, MultiByteToWideChar },
+    UI::Input::Ime::{
+        CANDIDATEFORM,
+        CFS_EXCLUDE,
+        CFS_FORCE_POSITION,
+        COMPOSITIONFORM,
+        ImmGetContext,
+        ImmReleaseContext,
+        ImmSetCandidateWindow,
+        ImmSetCompositionWindow
+    }
+};
+
+/// Joins UTF-16 units from consecutive character messages into chars
+#[derive(Debug, Default)]
+pub struct SurrogateBuffer {
+    high: Option<u16>
+}
+
+impl SurrogateBuffer {
+    pub fn new() -> Self { Self::default() }
+
+    /// Add the next UTF-16 unit. Returns up to two chars that are now complete: an unpaired
+    /// surrogate becomes U+FFFD, and is followed by the unit that broke the pair.
+    pub fn push(&mut self, unit: u16) -> [Option<char>; 2] {
+        match unit {
+            0xD800..=0xDBFF => [self.high.replace(unit).map(|_| char::REPLACEMENT_CHARACTER), None],
+            0xDC00..=0xDFFF => match self.high.take() {
+                Some(high) => {
+                    let c = 0x10000 + (((high as u32) - 0xD800) << 10) + ((unit as u32) - 0xDC00);
+                    [char::from_u32(c), None]
+                },
+                None => [Some(char::REPLACEMENT_CHARACTER), None]
+            },
+            _ => [
+                self.high.take().map(|_| char::REPLACEMENT_CHARACTER),
+                char::from_u32(unit as u32)
+            ]
+        }
+    }
+
+    /// Drop a half received pair, e.g when focus changes between the two messages
+    pub fn reset(&mut self) {
+        self.high = None;
+    }
+
+    pub fn is_pending(&self) -> bool { self.high.is_some() }
+}
+
+/// Convert a character from a non-Unicode window's WM_IME_CHAR into UTF-16. Double byte
+/// characters carry their lead byte in the high byte of WPARAM.
+pub fn get_ime_char_bytes(wparam: usize) -> Vec<u8> {
+    let lead = ((wparam >> 8) & u8::MAX as usize) as u8;
+    let trail = (wparam & u8::MAX as usize) as u8;
+    match lead {
+        0 => vec![trail],
+        _ => vec![lead, trail]
+    }
+}
+
+#[cfg(windows)]
+pub unsafe fn ansi_to_utf16(bytes: &[u8]) -> Vec<u16> {
+    let len = MultiByteToWideChar(CP_ACP, MB_PRECOMPOSED, bytes, None);
+    if len <= 0 {
+        return vec![];
+    }
+    let mut out = vec![0u16; len as usize];
+    let len = MultiByteToWideChar(CP_ACP, MB_PRECOMPOSED, bytes, Some(&mut out));
+    out.truncate(len.max(0) as usize);
+    out
+}
+
+/// Where to put the IME windows for the text cursor ImGui reports
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImeLayout {
+    /// Top left of the composition string
+    pub composition: POINT,
+    /// Area the candidate list shouldn't cover, which is the line being edited
+    pub exclude: RECT
+}
+
+#[cfg(windows)]
+pub fn get_ime_layout(want_visible: bool, input_pos: [f32; 2], line_height: f32) -> Option<ImeLayout> {
+    if !want_visible {
+        return None;
+    }
+    let x = input_pos[0] as i32;
+    let y = input_pos[1] as i32;
+    Some(ImeLayout {
+        composition: POINT { x, y },
+        exclude: RECT { left: x, top: y, right: x, bottom: y + line_height as i32 }
+    })
+}
+
+// ImGui only tells us about the viewport, which we don't give a platform handle, so keep the
+// window that IME calls should go to here
+#[cfg(windows)]
+static IME_HWND: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(windows)]
+pub fn set_ime_window(hwnd: HWND) {
+    IME_HWND.store(hwnd.0 as usize, Ordering::Relaxed);
+}
+
+// ImGui_ImplWin32_SetImeData
+#[cfg(windows)]
+pub unsafe extern "C" fn set_platform_ime_data(_viewport: *mut ImGuiViewport, data: *mut ImGuiPlatformImeData) {
+    let hwnd = HWND(IME_HWND.load(Ordering::Relaxed) as _);
+    if hwnd.0.is_null() || data.is_null() {
+        return;
+    }
+    let data = &*data;
+    let Some(layout) = get_ime_layout(data.WantVisible, [data.InputPos.x, data.InputPos.y], data.InputLineHeight) else {
+        return;
+    };
+    let himc = ImmGetContext(hwnd);
+    if himc.0.is_null() {
+        return;
+    }
+    let composition = COMPOSITIONFORM {
+        dwStyle: CFS_FORCE_POSITION,
+        ptCurrentPos: layout.composition,
+        rcArea: RECT::default()
+    };
+    let _ = ImmSetCompositionWindow(himc, &raw const composition);
+    let candidate = CANDIDATEFORM {
+        dwIndex: 0,
+        dwStyle: CFS_EXCLUDE,
+        ptCurrentPos: layout.composition,
+        rcArea: layout.exclude
+    };
+    let _ = ImmSetCandidateWindow(himc, &raw const candidate);
+    let _ = ImmReleaseContext(hwnd, himc);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surrogate_pair() {
+        let mut buffer = SurrogateBuffer::new();
+        assert_eq!(buffer.push(0xD83C), [None, None]);
+        assert!(buffer.is_pending());
+        assert_eq!(buffer.push(0xDFAE), [Some('🎮'), None]);
+        assert!(!buffer.is_pending());
+    }
+
+    #[test]
+    fn basic_plane() {
+        let mut buffer = SurrogateBuffer::new();
+        assert_eq!(buffer.push(0x41), [None, Some('A')]);
+        assert_eq!(buffer.push(0x4E16), [None, Some('世')]);
+        assert!(!buffer.is_pending());
+    }
+
+    #[test]
+    fn lone_high_surrogate() {
+        let mut buffer = SurrogateBuffer::new();
+        // Broken by a regular character, which still comes through
+        buffer.push(0xD83C);
+        assert_eq!(buffer.push(0x41), [Some(char::REPLACEMENT_CHARACTER), Some('A')]);
+        // Broken by another high surrogate, which starts a new pair
+        buffer.push(0xD83C);
+        assert_eq!(buffer.push(0xD83D), [Some(char::REPLACEMENT_CHARACTER), None]);
+        assert_eq!(buffer.push(0xDE00), [Some('😀'), None]);
+    }
+
+    #[test]
+    fn lone_low_surrogate() {
+        let mut buffer = SurrogateBuffer::new();
+        assert_eq!(buffer.push(0xDFAE), [Some(char::REPLACEMENT_CHARACTER), None]);
+        assert!(!buffer.is_pending());
+        assert_eq!(buffer.push(0x41), [None, Some('A')]);
+    }
+
+    #[test]
+    fn reset_drops_pending() {
+        let mut buffer = SurrogateBuffer::new();
+        buffer.push(0xD83C);
+        buffer.reset();
+        assert!(!buffer.is_pending());
+        assert_eq!(buffer.push(0xDFAE), [Some(char::REPLACEMENT_CHARACTER), None]);
+    }
+
+    #[test]
+    fn ime_char_bytes() {
+        assert_eq!(get_ime_char_bytes(0x41), vec![0x41]);
+        assert_eq!(get_ime_char_bytes(0x82A0), vec![0x82, 0xA0]);
+    }
+}
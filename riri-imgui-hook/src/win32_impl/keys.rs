@@ -0,0 +1,413 @@
+// Keyboard handling for Win32Impl. Everything that reads the keyboard's state goes through
+// KeyState so the translation can be checked with synthetic messages. Messages are passed in as
+// the raw WPARAM/LPARAM values so this builds without the windows crate.
+use crate::overlay::KeyModifiers;
+use imgui::Key;
+#[cfg(windows)]
+use windows::Win32::UI::Input::KeyboardAndMouse::GetKeyState;
+
+// VK_xxx virtual key codes and KF_xxx flags from WinUser.h
+const VK_BACK: u16 = 0x08;
+const VK_TAB: u16 = 0x09;
+const VK_RETURN: u16 = 0x0d;
+const VK_SHIFT: u16 = 0x10;
+const VK_CONTROL: u16 = 0x11;
+const VK_MENU: u16 = 0x12;
+const VK_PAUSE: u16 = 0x13;
+const VK_CAPITAL: u16 = 0x14;
+const VK_ESCAPE: u16 = 0x1b;
+const VK_SPACE: u16 = 0x20;
+const VK_PRIOR: u16 = 0x21;
+const VK_NEXT: u16 = 0x22;
+const VK_END: u16 = 0x23;
+const VK_HOME: u16 = 0x24;
+const VK_LEFT: u16 = 0x25;
+const VK_UP: u16 = 0x26;
+const VK_RIGHT: u16 = 0x27;
+const VK_DOWN: u16 = 0x28;
+const VK_SNAPSHOT: u16 = 0x2c;
+const VK_INSERT: u16 = 0x2d;
+const VK_DELETE: u16 = 0x2e;
+const VK_0: u16 = 0x30;
+const VK_1: u16 = 0x31;
+const VK_2: u16 = 0x32;
+const VK_3: u16 = 0x33;
+const VK_4: u16 = 0x34;
+const VK_5: u16 = 0x35;
+const VK_6: u16 = 0x36;
+const VK_7: u16 = 0x37;
+const VK_8: u16 = 0x38;
+const VK_9: u16 = 0x39;
+const VK_A: u16 = 0x41;
+const VK_B: u16 = 0x42;
+const VK_C: u16 = 0x43;
+const VK_D: u16 = 0x44;
+const VK_E: u16 = 0x45;
+const VK_F: u16 = 0x46;
+const VK_G: u16 = 0x47;
+const VK_H: u16 = 0x48;
+const VK_I: u16 = 0x49;
+const VK_J: u16 = 0x4a;
+const VK_K: u16 = 0x4b;
+const VK_L: u16 = 0x4c;
+const VK_M: u16 = 0x4d;
+const VK_N: u16 = 0x4e;
+const VK_O: u16 = 0x4f;
+const VK_P: u16 = 0x50;
+const VK_Q: u16 = 0x51;
+const VK_R: u16 = 0x52;
+const VK_S: u16 = 0x53;
+const VK_T: u16 = 0x54;
+const VK_U: u16 = 0x55;
+const VK_V: u16 = 0x56;
+const VK_W: u16 = 0x57;
+const VK_X: u16 = 0x58;
+const VK_Y: u16 = 0x59;
+const VK_Z: u16 = 0x5a;
+const VK_LWIN: u16 = 0x5b;
+const VK_RWIN: u16 = 0x5c;
+const VK_APPS: u16 = 0x5d;
+const VK_NUMPAD0: u16 = 0x60;
+const VK_NUMPAD1: u16 = 0x61;
+const VK_NUMPAD2: u16 = 0x62;
+const VK_NUMPAD3: u16 = 0x63;
+const VK_NUMPAD4: u16 = 0x64;
+const VK_NUMPAD5: u16 = 0x65;
+const VK_NUMPAD6: u16 = 0x66;
+const VK_NUMPAD7: u16 = 0x67;
+const VK_NUMPAD8: u16 = 0x68;
+const VK_NUMPAD9: u16 = 0x69;
+const VK_MULTIPLY: u16 = 0x6a;
+const VK_ADD: u16 = 0x6b;
+const VK_SUBTRACT: u16 = 0x6d;
+const VK_DECIMAL: u16 = 0x6e;
+const VK_DIVIDE: u16 = 0x6f;
+const VK_F1: u16 = 0x70;
+const VK_F2: u16 = 0x71;
+const VK_F3: u16 = 0x72;
+const VK_F4: u16 = 0x73;
+const VK_F5: u16 = 0x74;
+const VK_F6: u16 = 0x75;
+const VK_F7: u16 = 0x76;
+const VK_F8: u16 = 0x77;
+const VK_F9: u16 = 0x78;
+const VK_F10: u16 = 0x79;
+const VK_F11: u16 = 0x7a;
+const VK_F12: u16 = 0x7b;
+const VK_NUMLOCK: u16 = 0x90;
+const VK_SCROLL: u16 = 0x91;
+const VK_LSHIFT: u16 = 0xa0;
+const VK_RSHIFT: u16 = 0xa1;
+const VK_LCONTROL: u16 = 0xa2;
+const VK_RCONTROL: u16 = 0xa3;
+const VK_LMENU: u16 = 0xa4;
+const VK_RMENU: u16 = 0xa5;
+const VK_OEM_COMMA: u16 = 0xbc;
+const VK_OEM_PERIOD: u16 = 0xbe;
+const KF_EXTENDED: u16 = 0x0100;
+const KF_REPEAT: u16 = 0x4000;
+
+// HIWORD(lParam) of WM_KEYDOWN/WM_KEYUP: the scancode in the low byte and KF_xxx flags in the
+// high byte
+fn get_key_flags(lparam: isize) -> u16 {
+    ((lparam >> u16::BITS as isize) & u16::MAX as isize) as u16
+}
+
+/// Queries whether a virtual key is currently held
+pub trait KeyState {
+    fn is_vk_down(&self, vk: u16) -> bool;
+}
+
+/// Reads key state from the calling thread's message queue through GetKeyState
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemKeyState;
+#[cfg(windows)]
+impl KeyState for SystemKeyState {
+    fn is_vk_down(&self, vk: u16) -> bool {
+        (unsafe { GetKeyState(vk as i32) } as u16 & 0x8000) != 0
+    }
+}
+
+// ImGui_ImplWin32_UpdateKeyModifiers
+pub fn get_modifier_events<S: KeyState>(state: &S) -> [(Key, bool); 4] {
+    [
+        (Key::ModCtrl, state.is_vk_down(VK_CONTROL)),
+        (Key::ModShift, state.is_vk_down(VK_SHIFT)),
+        (Key::ModAlt, state.is_vk_down(VK_MENU)),
+        (Key::ModSuper, state.is_vk_down(VK_LWIN) || state.is_vk_down(VK_RWIN))
+    ]
+}
+
+/// Modifiers held right now, for matching hotkeys
+pub fn get_key_modifiers<S: KeyState>(state: &S) -> KeyModifiers {
+    KeyModifiers {
+        ctrl: state.is_vk_down(VK_CONTROL),
+        shift: state.is_vk_down(VK_SHIFT),
+        alt: state.is_vk_down(VK_MENU)
+    }
+}
+
+/// True if a WM_KEYDOWN is an auto-repeat of a key that was already down
+pub fn is_key_repeat(lparam: isize) -> bool {
+    get_key_flags(lparam) & KF_REPEAT != 0
+}
+
+// Messages for Shift, Ctrl and Alt only carry the generic VK_SHIFT/VK_CONTROL/VK_MENU, so check
+// which side changed and submit that key
+pub fn get_side_key_events<S: KeyState>(wparam: usize, is_key_down: bool, state: &S) -> Vec<(Key, bool)> {
+    let sides = match wparam as u16 {
+        VK_SHIFT => [(Key::LeftShift, VK_LSHIFT), (Key::RightShift, VK_RSHIFT)],
+        VK_CONTROL => [(Key::LeftCtrl, VK_LCONTROL), (Key::RightCtrl, VK_RCONTROL)],
+        VK_MENU => [(Key::LeftAlt, VK_LMENU), (Key::RightAlt, VK_RMENU)],
+        _ => return vec![]
+    };
+    sides.into_iter()
+        .filter(|(_, vk)| state.is_vk_down(*vk) == is_key_down)
+        .map(|(key, _)| (key, is_key_down))
+        .collect()
+}
+
+// Keys that Windows sometimes doesn't send WM_KEYUP for:
+// - When both shift keys are held together, releasing the first one doesn't send a key up
+// - Key up for the Win keys isn't always passed down to the app (e.g Win+V on some setups)
+static STUCK_KEY_CHECKS: [(Key, u16); 4] = [
+    (Key::LeftShift, VK_LSHIFT),
+    (Key::RightShift, VK_RSHIFT),
+    (Key::LeftSuper, VK_LWIN),
+    (Key::RightSuper, VK_RWIN)
+];
+
+// ImGui_ImplWin32_ProcessKeyEventsWorkarounds. Returns keys that ImGui thinks are held but have
+// since been released.
+pub fn get_stuck_keys<S: KeyState, F: Fn(Key) -> bool>(state: &S, is_imgui_key_down: F) -> Vec<Key> {
+    STUCK_KEY_CHECKS.iter()
+        .filter(|(key, vk)| is_imgui_key_down(*key) && !state.is_vk_down(*vk))
+        .map(|(key, _)| *key)
+        .collect()
+}
+
+// Map VK_xxx to ImGuiKey_xxx.
+pub fn key_event_to_imgui_key(wparam: usize, lparam: isize) -> Option<Key> {
+    // There is no distinct VK_xxx for keypad enter, instead it is VK_RETURN + KF_EXTENDED.
+    if wparam == VK_RETURN as usize && get_key_flags(lparam) & KF_EXTENDED != 0 {
+        return Some(Key::KeypadEnter);
+    }
+    match wparam as u16 {
+        VK_TAB => Some(Key::Tab),
+        VK_LEFT => Some(Key::LeftArrow),
+        VK_RIGHT => Some(Key::RightArrow),
+        VK_UP => Some(Key::UpArrow),
+        VK_DOWN => Some(Key::DownArrow),
+        VK_PRIOR => Some(Key::PageUp),
+        VK_NEXT => Some(Key::PageDown),
+        VK_HOME => Some(Key::Home),
+        VK_END => Some(Key::End),
+        VK_INSERT => Some(Key::Insert),
+        VK_DELETE => Some(Key::Delete),
+        VK_BACK => Some(Key::Backspace),
+        VK_SPACE => Some(Key::Space),
+        VK_RETURN => Some(Key::Enter),
+        VK_ESCAPE => Some(Key::Escape),
+        VK_OEM_COMMA => Some(Key::Comma),
+        VK_OEM_PERIOD => Some(Key::Period),
+        VK_CAPITAL => Some(Key::CapsLock),
+        VK_SCROLL => Some(Key::ScrollLock),
+        VK_NUMLOCK => Some(Key::NumLock),
+        VK_SNAPSHOT => Some(Key::PrintScreen),
+        VK_PAUSE => Some(Key::Pause),
+        VK_NUMPAD0 => Some(Key::Keypad0),
+        VK_NUMPAD1 => Some(Key::Keypad1),
+        VK_NUMPAD2 => Some(Key::Keypad2),
+        VK_NUMPAD3 => Some(Key::Keypad3),
+        VK_NUMPAD4 => Some(Key::Keypad4),
+        VK_NUMPAD5 => Some(Key::Keypad5),
+        VK_NUMPAD6 => Some(Key::Keypad6),
+        VK_NUMPAD7 => Some(Key::Keypad7),
+        VK_NUMPAD8 => Some(Key::Keypad8),
+        VK_NUMPAD9 => Some(Key::Keypad9),
+        VK_DECIMAL => Some(Key::KeypadDecimal),
+        VK_DIVIDE => Some(Key::KeypadDivide),
+        VK_MULTIPLY => Some(Key::KeypadMultiply),
+        VK_SUBTRACT => Some(Key::KeypadSubtract),
+        VK_ADD => Some(Key::KeypadAdd),
+        VK_LSHIFT => Some(Key::LeftShift),
+        VK_LCONTROL => Some(Key::LeftCtrl),
+        VK_LMENU => Some(Key::LeftAlt),
+        VK_LWIN => Some(Key::LeftSuper),
+        VK_RSHIFT => Some(Key::RightShift),
+        VK_RCONTROL => Some(Key::RightCtrl),
+        VK_RMENU => Some(Key::RightAlt),
+        VK_RWIN => Some(Key::RightSuper),
+        VK_APPS => Some(Key::Menu),
+        VK_0 => Some(Key::Alpha0),
+        VK_1 => Some(Key::Alpha1),
+        VK_2 => Some(Key::Alpha2),
+        VK_3 => Some(Key::Alpha3),
+        VK_4 => Some(Key::Alpha4),
+        VK_5 => Some(Key::Alpha5),
+        VK_6 => Some(Key::Alpha6),
+        VK_7 => Some(Key::Alpha7),
+        VK_8 => Some(Key::Alpha8),
+        VK_9 => Some(Key::Alpha9),
+        VK_A => Some(Key::A),
+        VK_B => Some(Key::B),
+        VK_C => Some(Key::C),
+        VK_D => Some(Key::D),
+        VK_E => Some(Key::E),
+        VK_F => Some(Key::F),
+        VK_G => Some(Key::G),
+        VK_H => Some(Key::H),
+        VK_I => Some(Key::I),
+        VK_J => Some(Key::J),
+        VK_K => Some(Key::K),
+        VK_L => Some(Key::L),
+        VK_M => Some(Key::M),
+        VK_N => Some(Key::N),
+        VK_O => Some(Key::O),
+        VK_P => Some(Key::P),
+        VK_Q => Some(Key::Q),
+        VK_R => Some(Key::R),
+        VK_S => Some(Key::S),
+        VK_T => Some(Key::T),
+        VK_U => Some(Key::U),
+        VK_V => Some(Key::V),
+        VK_W => Some(Key::W),
+        VK_X => Some(Key::X),
+        VK_Y => Some(Key::Y),
+        VK_Z => Some(Key::Z),
+        VK_F1 => Some(Key::F1),
+        VK_F2 => Some(Key::F2),
+        VK_F3 => Some(Key::F3),
+        VK_F4 => Some(Key::F4),
+        VK_F5 => Some(Key::F5),
+        VK_F6 => Some(Key::F6),
+        VK_F7 => Some(Key::F7),
+        VK_F8 => Some(Key::F8),
+        VK_F9 => Some(Key::F9),
+        VK_F10 => Some(Key::F10),
+        VK_F11 => Some(Key::F11),
+        VK_F12 => Some(Key::F12),
+        _ => {
+            // Fallback to scancode
+            // https://handmade.network/forums/t/2011-keyboard_inputs_-_scancodes,_raw_input,_text_input,_key_names
+            let scancode = get_key_flags(lparam) & u8::MAX as u16;
+            match scancode {
+                41 => Some(Key::GraveAccent), // VK_OEM_8 in EN-UK, VK_OEM_3 in EN-US, VK_OEM_7 in FR, VK_OEM_5 in DE, etc.
+                12 => Some(Key::Minus),
+                13 => Some(Key::Equal),
+                26 => Some(Key::LeftBracket),
+                27 => Some(Key::RightBracket),
+                43 => Some(Key::Backslash),
+                39 => Some(Key::Semicolon),
+                40 => Some(Key::Apostrophe),
+                51 => Some(Key::Comma),
+                52 => Some(Key::Period),
+                53 => Some(Key::Slash),
+                _ => None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KF_UP: u16 = 0x8000;
+
+    struct FakeKeyState(&'static [u16]);
+    impl KeyState for FakeKeyState {
+        fn is_vk_down(&self, vk: u16) -> bool {
+            self.0.contains(&vk)
+        }
+    }
+
+    // LPARAM for a single key message: repeat count of 1, then the scancode and flags in the
+    // high word
+    fn make_lparam(scancode: u8, flags: u16) -> isize {
+        1 | (((scancode as u16 | flags) as u32 as isize) << 16)
+    }
+
+    #[test]
+    fn translate_virtual_keys() {
+        for (vk, key) in [
+            (VK_A, Key::A),
+            (VK_Z, Key::Z),
+            (VK_0, Key::Alpha0),
+            (VK_F1, Key::F1),
+            (VK_F12, Key::F12),
+            (VK_NUMPAD5, Key::Keypad5),
+            (VK_PRIOR, Key::PageUp),
+            (VK_INSERT, Key::Insert),
+            (VK_ESCAPE, Key::Escape),
+            (VK_LSHIFT, Key::LeftShift),
+            (VK_RMENU, Key::RightAlt),
+        ] {
+            assert_eq!(key_event_to_imgui_key(vk as usize, make_lparam(0, 0)), Some(key), "VK 0x{:x}", vk);
+        }
+    }
+
+    #[test]
+    fn translate_keypad_enter() {
+        assert_eq!(key_event_to_imgui_key(VK_RETURN as usize, make_lparam(28, 0)), Some(Key::Enter));
+        assert_eq!(key_event_to_imgui_key(VK_RETURN as usize, make_lparam(28, KF_EXTENDED)), Some(Key::KeypadEnter));
+        assert_eq!(key_event_to_imgui_key(VK_RETURN as usize, make_lparam(28, KF_EXTENDED | KF_UP)), Some(Key::KeypadEnter));
+        // Other extended keys aren't affected
+        assert_eq!(key_event_to_imgui_key(VK_DELETE as usize, make_lparam(83, KF_EXTENDED)), Some(Key::Delete));
+    }
+
+    #[test]
+    fn translate_scancode_fallback() {
+        // VK_OEM_3 is ` on US layouts, but the scancode is what decides the key
+        assert_eq!(key_event_to_imgui_key(0xc0, make_lparam(41, 0)), Some(Key::GraveAccent));
+        assert_eq!(key_event_to_imgui_key(0xbd, make_lparam(12, KF_UP)), Some(Key::Minus));
+        assert_eq!(key_event_to_imgui_key(0xbf, make_lparam(53, 0)), Some(Key::Slash));
+        assert_eq!(key_event_to_imgui_key(0xff, make_lparam(0x70, 0)), None);
+    }
+
+    #[test]
+    fn key_repeat() {
+        assert!(!is_key_repeat(make_lparam(30, 0)));
+        assert!(is_key_repeat(make_lparam(30, KF_REPEAT)));
+        assert!(!is_key_repeat(make_lparam(30, KF_EXTENDED)));
+    }
+
+    #[test]
+    fn side_key_down() {
+        let state = FakeKeyState(&[ VK_SHIFT, VK_LSHIFT ]);
+        assert_eq!(get_side_key_events(VK_SHIFT as usize, true, &state), vec![(Key::LeftShift, true)]);
+        let state = FakeKeyState(&[ VK_MENU, VK_RMENU ]);
+        assert_eq!(get_side_key_events(VK_MENU as usize, true, &state), vec![(Key::RightAlt, true)]);
+        let state = FakeKeyState(&[ VK_CONTROL, VK_LCONTROL, VK_RCONTROL ]);
+        assert_eq!(get_side_key_events(VK_CONTROL as usize, true, &state),
+            vec![(Key::LeftCtrl, true), (Key::RightCtrl, true)]);
+    }
+
+    #[test]
+    fn side_key_up() {
+        // Left shift was released while right shift is still held
+        let state = FakeKeyState(&[ VK_SHIFT, VK_RSHIFT ]);
+        assert_eq!(get_side_key_events(VK_SHIFT as usize, false, &state), vec![(Key::LeftShift, false)]);
+        assert!(get_side_key_events(VK_A as usize, false, &state).is_empty());
+    }
+
+    #[test]
+    fn modifiers() {
+        let state = FakeKeyState(&[ VK_CONTROL, VK_LCONTROL, VK_RWIN ]);
+        assert_eq!(get_modifier_events(&state), [
+            (Key::ModCtrl, true), (Key::ModShift, false), (Key::ModAlt, false), (Key::ModSuper, true)
+        ]);
+        assert_eq!(get_key_modifiers(&state), KeyModifiers { ctrl: true, shift: false, alt: false });
+        let state = FakeKeyState(&[ VK_SHIFT, VK_MENU ]);
+        assert_eq!(get_key_modifiers(&state), KeyModifiers { ctrl: false, shift: true, alt: true });
+    }
+
+    #[test]
+    fn stuck_keys() {
+        let state = FakeKeyState(&[ VK_LSHIFT ]);
+        let imgui_down = [ Key::LeftShift, Key::RightShift, Key::LeftSuper, Key::A ];
+        assert_eq!(get_stuck_keys(&state, |k| imgui_down.contains(&k)), vec![Key::RightShift, Key::LeftSuper]);
+        assert!(get_stuck_keys(&state, |_| false).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+// Mouse tracking for Win32Impl, following ImGui_ImplWin32_WndProcHandler
+#[cfg(windows)]
+use windows::Win32::{
+    Foundation::HWND,
+    UI::Input::KeyboardAndMouse::{
+        TrackMouseEvent,
+        TME_CANCEL,
+        TME_LEAVE,
+        TME_NONCLIENT,
+        TRACKMOUSEEVENT
+    }
+};
+
+// Defined in Win32_UI_Controls, which we don't otherwise need
+pub const WM_MOUSELEAVE: u32 = 0x02A3;
+
+/// What produced a mouse message. Windows sends touch and pen input as mouse messages too, and
+/// only marks them in the message's extra info.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseSource {
+    Mouse = 0,
+    TouchScreen = 1,
+    Pen = 2
+}
+
+// See https://learn.microsoft.com/en-us/windows/win32/tablet/system-events-and-mouse-messages
+// Pen and touch both have the 0xFF515700 signature, touch also sets bit 7
+pub fn get_mouse_source(extra_info: isize) -> MouseSource {
+    match (extra_info as u32) & 0xFFFFFF80 {
+        0xFF515700 => MouseSource::Pen,
+        0xFF515780 => MouseSource::TouchScreen,
+        _ => MouseSource::Mouse
+    }
+}
+
+/// Which part of the window WM_MOUSELEAVE is being requested for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseArea {
+    Client,
+    NonClient
+}
+
+/// Change to make to the TrackMouseEvent request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackRequest {
+    /// The previous request has to be cancelled first
+    pub cancel: bool,
+    pub area: MouseArea
+}
+
+/// Remembers which area we've asked Windows to send a leave message for
+#[derive(Debug, Default)]
+pub struct MouseTracker {
+    area: Option<MouseArea>
+}
+impl MouseTracker {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn get_area(&self) -> Option<MouseArea> { self.area }
+
+    /// Called on WM_MOUSEMOVE/WM_NCMOUSEMOVE. Returns the tracking request to make if the
+    /// mouse moved into a different area.
+    pub fn on_move(&mut self, area: MouseArea) -> Option<TrackRequest> {
+        if self.area == Some(area) {
+            return None;
+        }
+        let cancel = self.area.is_some();
+        self.area = Some(area);
+        Some(TrackRequest { cancel, area })
+    }
+
+    /// Called on WM_MOUSELEAVE/WM_NCMOUSELEAVE. Returns true if the mouse has left the window,
+    /// a leave message for an area we've since moved out of is ignored.
+    pub fn on_leave(&mut self, area: MouseArea) -> bool {
+        if self.area != Some(area) {
+            return false;
+        }
+        self.area = None;
+        true
+    }
+
+    /// Stop tracking without waiting for a leave message, e.g when the window loses focus
+    pub fn reset(&mut self) {
+        self.area = None;
+    }
+}
+
+#[cfg(windows)]
+pub unsafe fn track_mouse_event(hwnd: HWND, request: TrackRequest) {
+    if request.cancel {
+        let mut cancel = TRACKMOUSEEVENT {
+            cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+            dwFlags: TME_CANCEL,
+            hwndTrack: hwnd,
+            dwHoverTime: 0
+        };
+        let _ = TrackMouseEvent(&raw mut cancel);
+    }
+    let mut track = TRACKMOUSEEVENT {
+        cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+        dwFlags: match request.area {
+            MouseArea::Client => TME_LEAVE,
+            MouseArea::NonClient => TME_LEAVE | TME_NONCLIENT
+        },
+        hwndTrack: hwnd,
+        dwHoverTime: 0
+    };
+    let _ = TrackMouseEvent(&raw mut track);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_source_from_extra_info() {
+        assert_eq!(get_mouse_source(0), MouseSource::Mouse);
+        assert_eq!(get_mouse_source(0xFF515700_u32 as i32 as isize), MouseSource::Pen);
+        assert_eq!(get_mouse_source(0xFF515780_u32 as i32 as isize), MouseSource::TouchScreen);
+        // The low bits carry the pen or touch id
+        assert_eq!(get_mouse_source(0xFF51577F_u32 as i32 as isize), MouseSource::Pen);
+        assert_eq!(get_mouse_source(0xFF5157FF_u32 as i32 as isize), MouseSource::TouchScreen);
+        assert_eq!(get_mouse_source(0x12345678), MouseSource::Mouse);
+    }
+
+    #[test]
+    fn track_first_move() {
+        let mut tracker = MouseTracker::new();
+        assert_eq!(tracker.on_move(MouseArea::Client), Some(TrackRequest { cancel: false, area: MouseArea::Client }));
+        assert_eq!(tracker.on_move(MouseArea::Client), None);
+        assert_eq!(tracker.get_area(), Some(MouseArea::Client));
+    }
+
+    #[test]
+    fn track_area_change() {
+        let mut tracker = MouseTracker::new();
+        tracker.on_move(MouseArea::Client);
+        assert_eq!(tracker.on_move(MouseArea::NonClient), Some(TrackRequest { cancel: true, area: MouseArea::NonClient }));
+        // The cancelled client request can still deliver a leave message
+        assert!(!tracker.on_leave(MouseArea::Client));
+        assert!(tracker.on_leave(MouseArea::NonClient));
+        assert_eq!(tracker.get_area(), None);
+    }
+
+    #[test]
+    fn track_reset() {
+        let mut tracker = MouseTracker::new();
+        tracker.on_move(MouseArea::Client);
+        tracker.reset();
+        assert!(!tracker.on_leave(MouseArea::Client));
+        assert_eq!(tracker.on_move(MouseArea::Client), Some(TrackRequest { cancel: false, area: MouseArea::Client }));
+    }
+}
@@ -1,8 +1,19 @@
 // Adapted from imgui_impl_win32.cpp
 // https://github.com/ocornut/imgui/blob/master/backends/imgui_impl_win32.cpp
 #![allow(unused_imports)]
+use crate::win32_impl::{
+    clipboard::{ Clipboard, Win32Clipboard },
+    cursor::{ self, SystemCursor },
+    dpi,
+    gamepad::{ self, GamepadSource, XInputGamepad },
+    ime::{ self, SurrogateBuffer },
+    keys::{ self, SystemKeyState },
+    mouse::{ self, MouseArea, MouseSource, MouseTracker, WM_MOUSELEAVE }
+};
 use imgui::{
+    internal::RawCast,
     BackendFlags,
+    ConfigFlags,
     Context as ImContext,
     FontConfig,
     FontSource,
@@ -28,14 +39,18 @@ use windows::{
                 GetClientRect,
                 GetCursorPos,
                 GetForegroundWindow,
+                GetMessageExtraInfo,
                 GetWindowLongPtrA,
                 GetWindowLongPtrW,
                 GWL_WNDPROC,
+                HTCLIENT,
                 IsWindowUnicode,
                 WHEEL_DELTA,
                 WM_CHAR,
+                WM_IME_CHAR,
                 WM_MOUSEMOVE,
                 WM_NCMOUSEMOVE,
+                WM_NCMOUSELEAVE,
                 WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
                 WM_LBUTTONDOWN, WM_LBUTTONDBLCLK,
                 WM_RBUTTONDOWN, WM_RBUTTONDBLCLK,
@@ -51,6 +66,8 @@ use windows::{
                 WM_ACTIVATE, WM_NCACTIVATE,
                 WM_GETICON,
                 WM_DEVICECHANGE,
+                WM_DPICHANGED,
+                DBT_DEVNODES_CHANGED,
                 WM_PAINT,
                 XBUTTON1
             }
@@ -62,19 +79,26 @@ use windows::{
 pub struct Win32Impl {
     last_frame: Instant,
     hwnd: HWND,
-    wnd_proc: usize
+    wnd_proc: usize,
+    // Cursor that ImGui asked for on the last frame, None if it should be hidden
+    last_cursor: Option<SystemCursor>,
+    // Keys we've told ImGui are held down
+    keys_down: Vec<Key>,
+    // High surrogate waiting for the next WM_CHAR/WM_IME_CHAR
+    surrogates: SurrogateBuffer,
+    // None if no XInput DLL could be loaded
+    gamepad: Option<Box<dyn GamepadSource + Send>>,
+    // False while the overlay is passive, input is left for the game
+    input_enabled: bool,
+    // Area of the window we've asked for WM_MOUSELEAVE/WM_NCMOUSELEAVE on
+    mouse_tracker: MouseTracker,
+    // DPI of the monitor the window is on
+    dpi: u32,
+    dpi_scale_override: Option<f32>,
+    // Set when the scale changes, until the backend picks it up
+    dpi_scale_changed: bool
 }
 
-#[allow(dead_code)]
-pub(crate) static XINPUT_DLL: [&'static str; 5] = [
-    "xinput1_4.dll\0", // Windows 8+
-    "xinput1_3.dll\0", // DirectX SDK
-    "xinput9_1_0.dll\0", // Windows Vista/Windows 7
-    "xinput1_2.dll\0", // DirectX SDK
-    "xinput1_1.dll\0" // DirectX SDK
-];
-
-#[allow(unused)]
 fn wparam_get_low_word(wparam: WPARAM) -> u16 {
     (wparam.0 & u16::MAX as usize) as u16
 }
@@ -82,7 +106,6 @@ fn wparam_get_low_word(wparam: WPARAM) -> u16 {
 fn wparam_get_high_word(wparam: WPARAM) -> u16 {
     ((wparam.0 >> u16::BITS as usize) & u16::MAX as usize) as u16
 }
-#[allow(unused)]
 fn lparam_get_low_word(lparam: LPARAM) -> i16 {
     (lparam.0 & u16::MAX as isize) as i16
 }
@@ -97,8 +120,11 @@ impl Win32Impl {
         ctx.fonts().add_font(&[FontSource::DefaultFontData { 
             config: Some(FontConfig { size_pixels: 13., ..FontConfig::default() })
         }]);
+        ctx.set_clipboard_backend(Clipboard::new(Win32Clipboard::new(hwnd)));
         let io = ctx.io_mut();
         io.backend_flags.insert(BackendFlags::HAS_MOUSE_CURSORS);
+        ime::set_ime_window(hwnd);
+        io.set_platform_ime_data_fn = Some(ime::set_platform_ime_data);
         let wnd_proc = unsafe { match IsWindowUnicode(hwnd).into() {
             true => GetWindowLongPtrW(hwnd, GWL_WNDPROC),
             false => GetWindowLongPtrA(hwnd, GWL_WNDPROC),
@@ -106,11 +132,23 @@ impl Win32Impl {
         
         // create_hook!(wnd_proc, hook_window_proc);
         // io.backend_flags.insert(BackendFlags::HAS_SET_MOUSE_POS);
-        Self { last_frame: Instant::now(), hwnd, wnd_proc }
+        Self { last_frame: Instant::now(), hwnd, wnd_proc, last_cursor: None, keys_down: vec![], surrogates: SurrogateBuffer::new(),
+            gamepad: unsafe { XInputGamepad::new() }.map(|g| Box::new(g) as Box<dyn GamepadSource + Send>),
+            input_enabled: true,
+            mouse_tracker: MouseTracker::new(),
+            dpi: unsafe { dpi::get_window_dpi(hwnd) },
+            dpi_scale_override: None,
+            dpi_scale_changed: true
+        }
     }
 
     pub fn get_wnd_proc(&self) -> usize { self.wnd_proc }
 
+    /// Replace where gamepad input is read from, or pass None to turn gamepad input off
+    pub fn set_gamepad_source(&mut self, source: Option<Box<dyn GamepadSource + Send>>) {
+        self.gamepad = source;
+    }
+
     pub fn new_frame(&mut self, ctx: &mut ImContext) {
         let io = ctx.io_mut();
         // Set display size
@@ -124,13 +162,95 @@ impl Win32Impl {
         io.delta_time = new_time.duration_since(self.last_frame).as_secs_f32();
         self.last_frame = new_time;
 
+        if !self.input_enabled {
+            return;
+        }
+
         self.update_mouse_pos(ctx);
-        // TODO: Workarounds for known Windows key handling issues
+
+        // Update OS mouse cursor with the cursor requested by imgui
+        let mouse_cursor = cursor::get_system_cursor(ctx.mouse_cursor(), ctx.io().mouse_draw_cursor);
+        if self.last_cursor != mouse_cursor {
+            self.last_cursor = mouse_cursor;
+            self.update_mouse_cursor(ctx);
+        }
+        // Process workarounds for known Windows key handling issues
+        self.process_key_events_workarounds(ctx);
+
+        // Update game controllers (if enabled and available)
+        self.update_gamepads(ctx);
+    }
+
+    pub fn is_input_enabled(&self) -> bool { self.input_enabled }
+
+    /// UI scale for the window's current DPI. Display size is already in physical pixels, so
+    /// this is applied to sizes and fonts rather than the framebuffer scale.
+    pub fn get_dpi_scale(&self) -> f32 {
+        dpi::get_dpi_scale(self.dpi, self.dpi_scale_override)
+    }
+
+    /// Use a fixed scale instead of the one from the window's DPI
+    pub fn set_dpi_scale_override(&mut self, scale: Option<f32>) {
+        self.dpi_scale_override = scale;
+        self.dpi_scale_changed = true;
+    }
+
+    /// Returns the new scale if it changed since this was last called
+    pub fn take_dpi_scale_change(&mut self) -> Option<f32> {
+        match std::mem::replace(&mut self.dpi_scale_changed, false) {
+            true => Some(self.get_dpi_scale()),
+            false => None
+        }
     }
 
+    /// Stop or resume sending mouse, keyboard and gamepad state to ImGui. When input is turned
+    /// off, anything ImGui thinks is held is released and the mouse is moved away so nothing
+    /// stays hovered.
+    pub fn set_input_enabled(&mut self, ctx: &mut ImContext, enabled: bool) {
+        if self.input_enabled == enabled {
+            return;
+        }
+        self.input_enabled = enabled;
+        if !enabled {
+            self.release_all_keys(ctx);
+            self.surrogates.reset();
+            // Leave messages won't reach us while input is off
+            self.mouse_tracker.reset();
+            let io = ctx.io_mut();
+            for button in MouseButton::VARIANTS {
+                io.add_mouse_button_event(button, false);
+            }
+            io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+        }
+    }
+
+    // ImGui_ImplWin32_UpdateGamepads
+    fn update_gamepads(&mut self, ctx: &mut ImContext) {
+        let io = ctx.io_mut();
+        let state = self.gamepad.as_mut().and_then(|g| g.get_state());
+        io.backend_flags.set(BackendFlags::HAS_GAMEPAD, state.is_some());
+        let Some(state) = state else { return };
+        for event in gamepad::get_gamepad_events(&state) {
+            unsafe { imgui::sys::ImGuiIO_AddKeyAnalogEvent(io.raw_mut(), event.key as imgui::sys::ImGuiKey, event.down, event.value) };
+        }
+    }
+
+    // Only take over the cursor while ImGui wants the mouse, otherwise the game keeps control
+    // of it. Returns true if the cursor was set.
+    fn update_mouse_cursor(&self, ctx: &ImContext) -> bool {
+        let io = ctx.io();
+        if io.config_flags.contains(ConfigFlags::NO_MOUSE_CURSOR_CHANGE) || !io.want_capture_mouse {
+            return false;
+        }
+        unsafe { cursor::set_system_cursor(self.last_cursor) };
+        true
+    }
+
+    // ImGui_ImplWin32_UpdateMouseData. While the mouse is over the window WM_MOUSEMOVE keeps
+    // the position updated, otherwise poll it so dragging outside the window still works.
     fn update_mouse_pos(&mut self, ctx: &mut ImContext) {
         let focus_hwnd = unsafe { GetForegroundWindow() };
-        if focus_hwnd == self.hwnd {
+        if focus_hwnd == self.hwnd && self.mouse_tracker.get_area().is_none() {
             let io = ctx.io_mut();
             let mut point = MaybeUninit::uninit();
             unsafe {
@@ -144,159 +264,116 @@ impl Win32Impl {
         }
     }
 
-    /* 
+    fn add_key_event(&mut self, ctx: &mut ImContext, key: Key, down: bool) {
+        ctx.io_mut().add_key_event(key, down);
+        match (down, self.keys_down.iter().position(|k| *k == key)) {
+            (true, None) => self.keys_down.push(key),
+            (false, Some(i)) => { self.keys_down.swap_remove(i); },
+            _ => ()
+        }
+    }
+
     fn update_key_modifiers(&mut self, ctx: &mut ImContext) {
-        let io = ctx.io_mut();
-        // io.add_key_event(Key::LeftCtrl)
+        for (key, down) in keys::get_modifier_events(&SystemKeyState) {
+            ctx.io_mut().add_key_event(key, down);
+        }
     }
-    */
 
-    // Map VK_xxx to ImGuiKey_xxx.
-    fn from_key_event(wparam: WPARAM, lparam: LPARAM) -> Option<Key> {
-        // There is no distinct VK_xxx for keypad enter, instead it is VK_RETURN + KF_EXTENDED.
-        /* 
-        if wparam.0 == windows::Win32::UI::Input::KeyboardAndMouse::VK_RETURN
-        && lparam_get_high_word(lparam) as u32 & windows::Win32::UI::WindowsAndMessaging::KF_EXTENDED != 0 {
-            return Key::KeypadEnter;
+    // ImGui_ImplWin32_ProcessKeyEventsWorkarounds
+    fn process_key_events_workarounds(&mut self, ctx: &mut ImContext) {
+        let stuck = keys::get_stuck_keys(&SystemKeyState, |k| self.keys_down.contains(&k));
+        for key in stuck {
+            self.add_key_event(ctx, key, false);
         }
-        */
-        match VIRTUAL_KEY(wparam.0 as u16) {
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_TAB => Some(Key::Tab),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_LEFT => Some(Key::LeftArrow),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_RIGHT => Some(Key::RightArrow),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_UP => Some(Key::UpArrow),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_DOWN => Some(Key::DownArrow),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_PRIOR => Some(Key::PageUp),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NEXT => Some(Key::PageDown),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_HOME => Some(Key::Home),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_END => Some(Key::End),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_INSERT => Some(Key::Insert),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_DELETE => Some(Key::Delete),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_BACK => Some(Key::Backspace),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_SPACE => Some(Key::Space),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_RETURN => Some(Key::Enter),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_ESCAPE => Some(Key::Escape),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_OEM_COMMA => Some(Key::Comma),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_OEM_PERIOD => Some(Key::Period),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_CAPITAL => Some(Key::CapsLock),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_SCROLL => Some(Key::ScrollLock),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMLOCK => Some(Key::NumLock),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_SNAPSHOT => Some(Key::PrintScreen),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_PAUSE => Some(Key::Pause),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD0 => Some(Key::Keypad0),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD1 => Some(Key::Keypad1),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD2 => Some(Key::Keypad2),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD3 => Some(Key::Keypad3),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD4 => Some(Key::Keypad4),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD5 => Some(Key::Keypad5),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD6 => Some(Key::Keypad6),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD7 => Some(Key::Keypad7),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD8 => Some(Key::Keypad8),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_NUMPAD9 => Some(Key::Keypad9),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_DECIMAL => Some(Key::KeypadDecimal),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_DIVIDE => Some(Key::KeypadDivide),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_MULTIPLY => Some(Key::KeypadMultiply),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_SUBTRACT => Some(Key::KeypadSubtract),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_ADD => Some(Key::KeypadAdd),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_LSHIFT => Some(Key::LeftShift),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_LCONTROL => Some(Key::LeftCtrl),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_LMENU => Some(Key::LeftAlt),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_LWIN => Some(Key::LeftSuper),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_RSHIFT => Some(Key::RightShift),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_RCONTROL => Some(Key::RightCtrl),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_RMENU => Some(Key::RightAlt),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_RWIN => Some(Key::RightSuper),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_APPS => Some(Key::Menu),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_0 => Some(Key::Alpha0),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_1 => Some(Key::Alpha1),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_2 => Some(Key::Alpha2),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_3 => Some(Key::Alpha3),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_4 => Some(Key::Alpha4),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_5 => Some(Key::Alpha5),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_6 => Some(Key::Alpha6),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_7 => Some(Key::Alpha7),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_8 => Some(Key::Alpha8),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_9 => Some(Key::Alpha9),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_A => Some(Key::A),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_B => Some(Key::B),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_C => Some(Key::C),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_D => Some(Key::D),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_E => Some(Key::E),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F => Some(Key::F),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_G => Some(Key::G),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_H => Some(Key::H),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_I => Some(Key::I),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_J => Some(Key::J),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_K => Some(Key::K),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_L => Some(Key::L),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_M => Some(Key::M),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_N => Some(Key::N),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_O => Some(Key::O),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_P => Some(Key::P),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_Q => Some(Key::Q),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_R => Some(Key::R),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_S => Some(Key::S),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_T => Some(Key::T),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_U => Some(Key::U),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_V => Some(Key::V),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_W => Some(Key::W),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_X => Some(Key::X),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_Y => Some(Key::Y),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_Z => Some(Key::Z),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F1 => Some(Key::F1),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F2 => Some(Key::F2),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F3 => Some(Key::F3),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F4 => Some(Key::F4),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F5 => Some(Key::F5),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F6 => Some(Key::F6),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F7 => Some(Key::F7),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F8 => Some(Key::F8),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F9 => Some(Key::F9),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F10 => Some(Key::F10),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F11 => Some(Key::F11),
-            windows::Win32::UI::Input::KeyboardAndMouse::VK_F12 => Some(Key::F12),
-            _ => {
-                // Fallback to scancode
-                // https://handmade.network/forums/t/2011-keyboard_inputs_-_scancodes,_raw_input,_text_input,_key_names
-                let scancode = lparam_get_high_word(lparam) as u16 & u8::MAX as u16;
-                match scancode {
-                    41 => Some(Key::GraveAccent), // VK_OEM_8 in EN-UK, VK_OEM_3 in EN-US, VK_OEM_7 in FR, VK_OEM_5 in DE, etc.
-                    12 => Some(Key::Minus),
-                    13 => Some(Key::Equal),
-                    26 => Some(Key::LeftBracket),
-                    27 => Some(Key::RightBracket),
-                    43 => Some(Key::Backslash),
-                    39 => Some(Key::Semicolon),
-                    40 => Some(Key::Apostrophe),
-                    51 => Some(Key::Comma),
-                    52 => Some(Key::Period),
-                    53 => Some(Key::Slash),
-                    _ => None
+    }
+
+    // Release everything we think is held when the window loses focus, since the key up
+    // messages will go to another window (e.g after alt-tab)
+    fn release_all_keys(&mut self, ctx: &mut ImContext) {
+        while let Some(key) = self.keys_down.pop() {
+            ctx.io_mut().add_key_event(key, false);
+        }
+        self.update_key_modifiers(ctx);
+    }
+
+    fn add_utf16_input(&mut self, ctx: &mut ImContext, unit: u16) {
+        for c in self.surrogates.push(unit).into_iter().flatten() {
+            ctx.io_mut().add_input_character(c);
+        }
+    }
+
+    // ImGui_ImplWin32_Shutdown
+    pub fn shutdown(&mut self, ctx: &mut ImContext) {
+        self.set_input_enabled(ctx, false);
+        self.gamepad = None;
+        ime::set_ime_window(HWND::default());
+        let io = ctx.io_mut();
+        io.set_platform_ime_data_fn = None;
+        io.backend_flags.remove(BackendFlags::HAS_MOUSE_CURSORS | BackendFlags::HAS_GAMEPAD);
+        ctx.set_platform_name(None);
+    }
+
+    /// Handle messages that are about the window rather than input. These are needed even while
+    /// input is going to the game.
+    pub fn handle_window_message(&mut self, umsg: u32, wparam: WPARAM) {
+        match umsg {
+            WM_DPICHANGED => {
+                // The X and Y DPI are always the same. The game is left to resize the window.
+                let new_dpi = wparam_get_low_word(wparam) as u32;
+                if new_dpi != self.dpi {
+                    self.dpi = new_dpi;
+                    self.dpi_scale_changed = true;
                 }
-            }
+            },
+            WM_DEVICECHANGE => {
+                if wparam.0 as u32 == DBT_DEVNODES_CHANGED {
+                    if let Some(gamepad) = self.gamepad.as_mut() {
+                        gamepad.on_device_change();
+                    }
+                }
+            },
+            _ => ()
         }
     }
 
+    fn add_mouse_source_event(ctx: &mut ImContext, source: MouseSource) {
+        unsafe { imgui::sys::ImGuiIO_AddMouseSourceEvent(ctx.io_mut().raw_mut(), source as imgui::sys::ImGuiMouseSource) };
+    }
+
     pub unsafe fn wnd_proc(&mut self, ctx: &mut ImContext, umsg: u32, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
         match umsg {
             WM_MOUSEMOVE | WM_NCMOUSEMOVE => {
                 // We need to call TrackMouseEvent in order to receive WM_MOUSELEAVE events
-                // let area = if umsg == WM_MOUSEMOVE { 1 } else { 2 };
-                // ...
+                let area = if umsg == WM_MOUSEMOVE { MouseArea::Client } else { MouseArea::NonClient };
+                if let Some(request) = self.mouse_tracker.on_move(area) {
+                    mouse::track_mouse_event(self.hwnd, request);
+                }
+                // Coordinates are signed, they can be negative on multi-monitor setups
                 let mut mouse_pos = POINT { 
-                    x: (lparam.0 & u16::MAX as isize) as i32,
-                    y: ((lparam.0 >> u16::BITS as isize) & u16::MAX as isize) as i32,
+                    x: lparam_get_low_word(lparam) as i32,
+                    y: lparam_get_high_word(lparam) as i32,
                 };
+                // WM_NCMOUSEMOVE is in screen coordinates
                 if umsg != WM_NCMOUSEMOVE || ScreenToClient(self.hwnd, &raw mut mouse_pos) == BOOL(1) {
+                    Self::add_mouse_source_event(ctx, mouse::get_mouse_source(GetMessageExtraInfo().0));
                     let io = ctx.io_mut();
                     io.add_mouse_pos_event([mouse_pos.x as f32, mouse_pos.y as f32]);
                 }
                 None
             },
+            WM_MOUSELEAVE | WM_NCMOUSELEAVE => {
+                let area = if umsg == WM_MOUSELEAVE { MouseArea::Client } else { MouseArea::NonClient };
+                if self.mouse_tracker.on_leave(area) {
+                    let io = ctx.io_mut();
+                    io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+                }
+                None
+            },
             WM_LBUTTONDOWN | WM_LBUTTONDBLCLK |
             WM_RBUTTONDOWN | WM_RBUTTONDBLCLK |
             WM_MBUTTONDOWN | WM_MBUTTONDBLCLK |
             WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => {
+                Self::add_mouse_source_event(ctx, mouse::get_mouse_source(GetMessageExtraInfo().0));
                 let io = ctx.io_mut();
                 let mouse_button = match umsg {
                     WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => MouseButton::Left,
@@ -314,6 +391,7 @@ impl Win32Impl {
                 } else { None }
             },
             WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP | WM_XBUTTONUP => {
+                Self::add_mouse_source_event(ctx, mouse::get_mouse_source(GetMessageExtraInfo().0));
                 let io = ctx.io_mut();
                 let mouse_button = match umsg {
                     WM_LBUTTONUP => MouseButton::Left,
@@ -349,15 +427,24 @@ impl Win32Impl {
             WM_SETFOCUS | WM_KILLFOCUS => {
                 let io = ctx.io_mut();
                 io.app_focus_lost = umsg == WM_KILLFOCUS;
+                if umsg == WM_KILLFOCUS {
+                    self.release_all_keys(ctx);
+                    self.surrogates.reset();
+                }
                 None
             },
             WM_KEYDOWN | WM_KEYUP | WM_SYSKEYDOWN | WM_SYSKEYUP => {
                 let is_key_down = umsg == WM_KEYDOWN || umsg == WM_SYSKEYDOWN;
                 if wparam.0 < 256 {
-                    // self.update_key_modifiers(ctx);
-                    if let Some(key) = Self::from_key_event(wparam, lparam) {
-                        let io = ctx.io_mut();
-                        io.add_key_event(key, is_key_down);
+                    // Submit modifiers
+                    self.update_key_modifiers(ctx);
+                    // Submit key event
+                    if let Some(key) = keys::key_event_to_imgui_key(wparam.0, lparam.0) {
+                        self.add_key_event(ctx, key, is_key_down);
+                    }
+                    // Submit individual left/right modifier events
+                    for (key, down) in keys::get_side_key_events(wparam.0, is_key_down, &SystemKeyState) {
+                        self.add_key_event(ctx, key, down);
                     }
                 }
                 let io = ctx.io_mut();
@@ -366,26 +453,48 @@ impl Win32Impl {
                 } else { None }
             },
             WM_CHAR => {
-                let io = ctx.io_mut();
                 if IsWindowUnicode(self.hwnd).into() {
                     if wparam.0 > 0 && wparam.0 <= u16::MAX as usize {
-                        let as_utf8: Vec<char> = std::char::decode_utf16([wparam.0 as u16])
-                            .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
-                            .collect();
-                        io.add_input_character(as_utf8[0]);
+                        self.add_utf16_input(ctx, wparam.0 as u16);
                     }
                 } else { 
                     if let Some(c) = std::char::from_u32(wparam.0 as u32) {
-                        io.add_input_character(c);
+                        ctx.io_mut().add_input_character(c);
                     }
                 }
-                if io.want_text_input {
+                if ctx.io().want_text_input {
                     Some(LRESULT(0))
                 } else { None }
             },
-            WM_GETICON | WM_DEVICECHANGE
+            WM_IME_CHAR => {
+                if IsWindowUnicode(self.hwnd).into() {
+                    if wparam.0 > 0 && wparam.0 <= u16::MAX as usize {
+                        self.add_utf16_input(ctx, wparam.0 as u16);
+                    }
+                } else {
+                    for unit in ime::ansi_to_utf16(&ime::get_ime_char_bytes(wparam.0)) {
+                        self.add_utf16_input(ctx, unit);
+                    }
+                }
+                // Returning here stops DefWindowProc from sending the same character again
+                // as WM_CHAR
+                if ctx.io().want_text_input {
+                    Some(LRESULT(0))
+                } else { None }
+            },
+            WM_SETCURSOR => {
+                // This is required to restore cursor when transitioning from e.g resize borders to client area.
+                if lparam_get_low_word(lparam) as u16 as u32 == HTCLIENT && self.update_mouse_cursor(ctx) {
+                    Some(LRESULT(1))
+                } else { None }
+            },
+            WM_DPICHANGED | WM_DEVICECHANGE => {
+                self.handle_window_message(umsg, wparam);
+                None
+            },
+            WM_GETICON
             | WM_ACTIVATE | WM_PAINT 
-            | WM_SETCURSOR | WM_NCHITTEST => None,
+            | WM_NCHITTEST => None,
             _u => {
                 // logln!(Verbose, "Unhandled window event 0x{:x}", u);
                 None